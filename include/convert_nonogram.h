@@ -0,0 +1,40 @@
+/* C API for convert-nonogram's line solver. Build with `cargo build --release --features ffi`
+ * and link against the resulting cdylib. See src/ffi.rs for details. */
+
+#ifndef CONVERT_NONOGRAM_H
+#define CONVERT_NONOGRAM_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct CnPuzzle CnPuzzle;
+typedef struct CnReport CnReport;
+
+/* The most recent error on this thread, or NULL. Valid until the next call on this thread. */
+const char *cn_last_error(void);
+
+/* `format` is "image", "svg", "webpbn", "olsak", "char-grid", or "json". Returns NULL on failure. */
+CnPuzzle *cn_puzzle_load(const uint8_t *data, size_t len, const char *format);
+void cn_puzzle_free(CnPuzzle *puzzle);
+int cn_puzzle_width(const CnPuzzle *puzzle);
+int cn_puzzle_height(const CnPuzzle *puzzle);
+
+/* Returns NULL if the clues are contradictory. */
+CnReport *cn_solve(const CnPuzzle *puzzle);
+void cn_report_free(CnReport *report);
+int cn_report_skims(const CnReport *report);
+int cn_report_scrubs(const CnReport *report);
+/* 0 means the puzzle was solved (and is therefore unique). */
+int cn_report_cells_left(const CnReport *report);
+/* Color index (0 is the background), or -1 if undetermined or out of bounds. */
+int cn_report_cell(const CnReport *report, size_t x, size_t y);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
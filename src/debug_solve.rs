@@ -0,0 +1,181 @@
+//! `debug-solve`: stepping through the solver by hand, to see why it does what it does.
+
+use std::io::{BufRead, Write};
+
+use convert_nonogram::{
+    error::Lane,
+    grid_solve::{self, Solver, Technique},
+    puzzle::Puzzle,
+};
+
+const HELP: &str = "\
+Commands:
+  s, step [N]     take one step (or N)
+  n, next-scrub   take steps until one is a scrub
+  r, run          take steps until the solver is done
+  l, lane R3      show what each cell of a lane could still be (named as in the steps, so R1
+                  is the first row)
+  u, undo         go back one step
+  d, dump         show the grid and the work done so far
+  h, help         show this
+  q, quit";
+
+fn parse_lane(text: &str, puzzle: &Puzzle) -> Result<Lane, String> {
+    let (row, number) = match text.split_at_checked(1) {
+        Some((r, number)) if r.eq_ignore_ascii_case("r") => (true, number),
+        Some((c, number)) if c.eq_ignore_ascii_case("c") => (false, number),
+        _ => return Err(format!("expected a lane like R3 or C12, not {:?}", text)),
+    };
+    let count = if row {
+        puzzle.rows.len()
+    } else {
+        puzzle.cols.len()
+    };
+    match number.parse::<usize>() {
+        Ok(number) if (1..=count).contains(&number) => Ok(Lane {
+            row,
+            index: number - 1,
+        }),
+        _ => Err(format!("{:?} isn't a number from 1 to {}", number, count)),
+    }
+}
+
+fn show_lane(solver: &Solver, puzzle: &Puzzle, lane: Lane) {
+    let clues = if lane.row {
+        &puzzle.rows[lane.index]
+    } else {
+        &puzzle.cols[lane.index]
+    };
+    let clue_text: Vec<String> = clues
+        .iter()
+        .map(|clue| format!("{}{}", clue.count_text(), puzzle.palette[&clue.color].ch))
+        .collect();
+    println!("{}: {}", lane.short_name(), clue_text.join(" "));
+
+    let mut colors: Vec<_> = puzzle.palette.values().collect();
+    colors.sort_by_key(|color_info| color_info.color.0);
+    let grid = solver.grid();
+    let cells = if lane.row {
+        grid.row(lane.index)
+    } else {
+        grid.column(lane.index)
+    };
+    for (i, cell) in cells.iter().enumerate() {
+        let possible: String = colors
+            .iter()
+            .filter(|color_info| cell.can_be(color_info.color))
+            .map(|color_info| color_info.ch)
+            .collect();
+        println!("  {: >3}: {}", i + 1, possible);
+    }
+}
+
+/// Takes a step, remembering how to undo it. Returns whether there was one to take.
+fn step<'a>(
+    solver: &mut Solver<'a>,
+    history: &mut Vec<Solver<'a>>,
+) -> anyhow::Result<Option<Technique>> {
+    let before = solver.clone();
+    match solver.next() {
+        Some(step) => {
+            history.push(before);
+            Ok(Some(step?.technique))
+        }
+        None => {
+            println!("The solver is done.");
+            Ok(None)
+        }
+    }
+}
+
+/// Takes up to `limit` steps, stopping early after one that `stop` picks out, or when the solver
+/// is done.
+fn step_until<'a>(
+    solver: &mut Solver<'a>,
+    history: &mut Vec<Solver<'a>>,
+    limit: usize,
+    stop: impl Fn(Technique) -> bool,
+) -> anyhow::Result<()> {
+    for _ in 0..limit {
+        match step(solver, history)? {
+            Some(technique) if !stop(technique) => {}
+            _ => break,
+        }
+    }
+    Ok(())
+}
+
+/// Prompts for commands on stdin until it runs out or the user quits.
+pub fn debug_solve(puzzle: &Puzzle) -> anyhow::Result<()> {
+    let mut solver = Solver::new(puzzle);
+    // Each step describes itself as it's taken.
+    solver.set_trace(true);
+    let mut history: Vec<Solver> = vec![];
+    println!("{}", HELP);
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            return Ok(());
+        };
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(()),
+            ["s" | "step"] => step(&mut solver, &mut history).map(|_| ()),
+            ["s" | "step", n] => match n.parse::<usize>() {
+                Ok(n) => step_until(&mut solver, &mut history, n, |_| false),
+                Err(_) => {
+                    println!("{:?} isn't a number of steps", n);
+                    Ok(())
+                }
+            },
+            ["n" | "next-scrub"] => {
+                step_until(&mut solver, &mut history, usize::MAX, |technique| {
+                    technique == Technique::Scrub
+                })
+            }
+            ["r" | "run"] => step_until(&mut solver, &mut history, usize::MAX, |_| false),
+            ["l" | "lane", lane] => {
+                match parse_lane(lane, puzzle) {
+                    Ok(lane) => show_lane(&solver, puzzle, lane),
+                    Err(message) => println!("{}", message),
+                }
+                Ok(())
+            }
+            ["u" | "undo"] => {
+                match history.pop() {
+                    Some(earlier) => solver = earlier,
+                    None => println!("Nothing to undo."),
+                }
+                Ok(())
+            }
+            ["d" | "dump"] => {
+                let report = solver.clone().into_report();
+                print!("{}", grid_solve::grid_text(solver.grid(), puzzle));
+                println!(
+                    "{} skims, {} scrubs, {} cells left",
+                    report.skims, report.scrubs, report.cells_left
+                );
+                Ok(())
+            }
+            ["h" | "help"] => {
+                println!("{}", HELP);
+                Ok(())
+            }
+            ["q" | "quit"] => return Ok(()),
+            _ => {
+                println!("Unknown command; try \"help\".");
+                Ok(())
+            }
+        };
+        // A contradiction ends the solve, but it's still worth poking around (or undoing).
+        if let Err(e) = result {
+            println!("{:#}", e);
+        }
+    }
+}
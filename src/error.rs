@@ -0,0 +1,74 @@
+//! Errors from reading, writing, and solving puzzles.
+
+use std::fmt;
+
+/// A row or column of the puzzle. Displayed counting from 1, as in most puzzle editors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct Lane {
+    pub row: bool,
+    pub index: usize,
+}
+
+impl fmt::Display for Lane {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.row { "row" } else { "column" };
+        write!(f, "{} {}", kind, self.index + 1)
+    }
+}
+
+impl Lane {
+    /// The same name, abbreviated for output that lines lanes up in a column: "R1" is row 1.
+    pub fn short_name(&self) -> String {
+        format!("{}{}", if self.row { "R" } else { "C" }, self.index + 1)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The input is malformed. `line` counts from 1, and is present when it's known.
+    #[error("{}{message}", line_prefix(.line))]
+    Parse {
+        line: Option<usize>,
+        message: String,
+    },
+    /// The input makes sense, but describes something that isn't supported.
+    #[error("{0}")]
+    Unsupported(String),
+    /// The clues in a lane can't be satisfied.
+    #[error("{lane}: {message}")]
+    Contradiction { lane: Lane, message: String },
+    /// A clue in a cell (as in a Mosaic puzzle) can't be satisfied. Counts from 0.
+    #[error("row {}, column {}: {message}", .y + 1, .x + 1)]
+    CellContradiction { x: usize, y: usize, message: String },
+    #[error(transparent)]
+    Xml(#[from] roxmltree::Error),
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[error(transparent)]
+    Png(#[from] png::EncodingError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+fn line_prefix(line: &Option<usize>) -> String {
+    line.map(|line| format!("line {}: ", line))
+        .unwrap_or_default()
+}
+
+impl Error {
+    pub fn parse(message: impl Into<String>) -> Error {
+        Error::Parse {
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn parse_at(line: Option<usize>, message: impl Into<String>) -> Error {
+        Error::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
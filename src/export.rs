@@ -1,23 +1,83 @@
-use std::path::Path;
+//! Writing puzzles and solutions out in various formats.
+
+use std::{collections::HashMap, path::Path};
 
 use axohtml::{elements::html, html, text};
-use image::{Rgb, RgbImage};
+use image::{imageops, imageops::FilterType, Rgb, RgbImage};
 
-use crate::puzzle::{Clue, Puzzle, Solution};
+use crate::{
+    error::{Error, Result},
+    puzzle::{
+        Clue, Color, ColorInfo, Document, Metadata, NoteTarget, Pack, Puzzle, Rule, Solution,
+        BACKGROUND,
+    },
+};
 
-fn style_color(c: &Clue, puzzle: &Puzzle) -> String {
-    let (r, g, b) = puzzle.palette[&c.color].rgb;
-    format!("color:rgb({},{},{})", r, g, b)
+/// What to do about clue colors that are too light to read on a white page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LowContrast {
+    /// Draw the number in a darker shade of the color.
+    #[default]
+    Darken,
+    /// Draw the number on a dark background.
+    Chip,
+    /// Use the color as-is.
+    Keep,
 }
 
-pub fn as_html(puzzle: &Puzzle) -> String {
-    let html: axohtml::dom::DOMTree<String> = html!(
-        <html>
-            <head>
-            <title></title>
-            <style>
-            {text!(
-"
+/// WCAG contrast ratio between `rgb` and white, from 1 (white) to 21 (black).
+fn contrast_with_white((r, g, b): (u8, u8, u8)) -> f64 {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let luminance = 0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b);
+    1.05 / (luminance + 0.05)
+}
+
+fn fill_style(solution: &Solution, x: usize, y: usize) -> String {
+    let (r, g, b) = solution.palette[&solution.grid[x][y]].rgb;
+    format!("background-color:rgb({},{},{})", r, g, b)
+}
+
+fn style_color(c: &Clue, puzzle: &Puzzle, low_contrast: LowContrast) -> String {
+    // The WCAG minimum for large text; clue numbers are small, but bold colors are easy to see.
+    const MIN_CONTRAST: f64 = 3.0;
+
+    let (mut r, mut g, mut b) = puzzle.palette[&c.color].rgb;
+    if contrast_with_white((r, g, b)) >= MIN_CONTRAST {
+        return format!("color:rgb({},{},{})", r, g, b);
+    }
+    match low_contrast {
+        LowContrast::Darken => {
+            while contrast_with_white((r, g, b)) < MIN_CONTRAST {
+                let darken = |c: u8| (c as f64 * 0.9) as u8;
+                (r, g, b) = (darken(r), darken(g), darken(b));
+            }
+            format!("color:rgb({},{},{})", r, g, b)
+        }
+        LowContrast::Chip => format!(
+            "color:rgb({},{},{});background-color:#333;border-radius:3px;padding:0 2px",
+            r, g, b
+        ),
+        LowContrast::Keep => format!("color:rgb({},{},{})", r, g, b),
+    }
+}
+
+/// `major_lines` is the interval between bold grid lines; 0 turns them off.
+pub fn as_html(
+    puzzle: &Puzzle,
+    major_lines: usize,
+    low_contrast: LowContrast,
+    metadata: &Metadata,
+    answer_key: Option<&Solution>,
+) -> String {
+    let mut style = String::from(
+        "
 table, td, th {
     border-collapse: collapse;
 }
@@ -26,25 +86,52 @@ td {
     width: 40px;
     height: 40px;
 }
+",
+    );
 
-table tr:nth-of-type(5n) td {
+    if major_lines > 0 {
+        style.push_str(&format!(
+            "
+table tr:nth-of-type({n}n) td {{
     border-bottom: 3px solid;
-}
-table td:nth-of-type(5n) {
+}}
+table td:nth-of-type({n}n) {{
     border-right: 3px solid;
-}
+}}
+",
+            n = major_lines
+        ));
+    }
 
+    style.push_str(
+        "
 table tr:last-child td {
     border-bottom: 1px solid;
 }
 table td:last-child {
     border-right: 1px solid;
 }
+table tr td.void {
+    border: none;
+}
 .col {
   vertical-align: bottom;
   border-top: none;
   font-family: courier;
 }
+.clue-area {
+  font-size: small;
+}
+td.noted, .note {
+  cursor: help;
+}
+.note {
+  color: gray;
+}
+.answer-key {
+  break-before: page;
+  margin-top: 40px;
+}
 .row {
   text-align: right;
   border-left: none;
@@ -53,31 +140,93 @@ table td:last-child {
 }
 
 
-    ")}
+    ",
+    );
+
+    let area = puzzle.clue_area();
+    let clue_area = format!(
+        "{}x{}; up to {} clues per row and {} per column",
+        puzzle.cols.len(),
+        puzzle.rows.len(),
+        area.max_row_clues,
+        area.max_col_clues
+    );
+
+    let html: axohtml::dom::DOMTree<String> = html!(
+        <html>
+            <head>
+            <title>{text!(metadata.title.clone().unwrap_or_default())}</title>
+            <style>
+            {text!(style)}
             </style>
             </head>
             <body>
+                { metadata.id.iter().map(|id| html!(
+                    <div class="identity" data-id=(id.clone()) data-created=(metadata.created.clone().unwrap_or_default()) data-modified=(metadata.modified.clone().unwrap_or_default())></div>
+                )) }
+                { metadata.title.iter().map(|title| html!(<h1>{text!(title.clone())}</h1>)) }
+                { metadata.description.iter().map(|description| html!(<p class="description">{text!(description.clone())}</p>)) }
+                <p class="clue-area">{text!(clue_area)}</p>
+                { metadata.difficulty.iter().map(|difficulty| html!(<p class="difficulty">{text!("Difficulty: {}", difficulty)}</p>)) }
+                { puzzle.separate_blocks.then_some("Blocks never touch, even blocks of different colors.").into_iter().map(|rule| html!(<p>{text!(rule)}</p>)) }
                 <table>
                     <thead>
                         <tr>
                         <th></th>
-                        { puzzle.cols.iter().map(|col| html!(<th class="col">{
-                            col.iter().map(|clue| html!(<div style=(style_color(clue, puzzle))>{text!("{} ", clue.count)} </div>))
+                        { puzzle.cols.iter().enumerate().map(|(x, col)| html!(<th class="col">{
+                            col.iter().map(|clue| html!(<div style=(style_color(clue, puzzle, low_contrast))>{text!("{} ", clue.count_text())} </div>))
+                        }{
+                            metadata.note_on(NoteTarget::Column(x)).into_iter().map(|note| html!(<div class="note" title=(note.to_string())>{text!("*")}</div>))
                         }</th>))}
                         </tr>
                     </thead>
                     <tbody>
                     {
-                        puzzle.rows.iter().map(|row| html!(<tr><th class="row">{
-                            row.iter().map(|clue| html!(<span style=(style_color(clue, puzzle))>{text!("{} ", clue.count)} </span>))
+                        puzzle.rows.iter().enumerate().map(|(y, row)| html!(<tr><th class="row">{
+                            metadata.note_on(NoteTarget::Row(y)).into_iter().map(|note| html!(<span class="note" title=(note.to_string())>{text!("* ")}</span>))
+                        }{
+                            row.iter().map(|clue| html!(<span style=(style_color(clue, puzzle, low_contrast))>{text!("{} ", clue.count_text())} </span>))
                         }</th>
                         {
-                            puzzle.cols.iter().map(|_| html!(<td></td>))
+                            (0..puzzle.cols.len()).map(move |x| if puzzle.void.contains(&(x, y)) {
+                                html!(<td class="void"></td>)
+                            } else if let Some(note) = metadata.note_on(NoteTarget::Cell(x, y)) {
+                                html!(<td class="noted" title=(note.to_string())></td>)
+                            } else {
+                                html!(<td></td>)
+                            })
                         }
                         </tr>))
                     }
                     </tbody>
                 </table>
+                <footer>
+                    { metadata.byline().into_iter().map(|byline| html!(<p>{text!(byline)}</p>)) }
+                    { metadata.license.iter().map(|license| html!(<p class="license">{text!("License: {}", license)}</p>)) }
+                </footer>
+                { metadata.solver_stats.iter().map(|stats| html!(
+                    <p class="solver-stats" data-skims=(stats.skims.to_string()) data-scrubs=(stats.scrubs.to_string()) data-cells-left=(stats.cells_left.to_string())>
+                        {text!("Line solver: {}", stats)}
+                    </p>
+                )) }
+                { answer_key.into_iter().map(|solution| html!(
+                    <div class="answer-key">
+                        <h2>{text!("Solution")}</h2>
+                        <table>
+                            <tbody>
+                            {
+                                (0..puzzle.rows.len()).map(move |y| html!(<tr>{
+                                    (0..puzzle.cols.len()).map(move |x| if solution.void.contains(&(x, y)) {
+                                        html!(<td class="void"></td>)
+                                    } else {
+                                        html!(<td style=(fill_style(solution, x, y))></td>)
+                                    })
+                                }</tr>))
+                            }
+                            </tbody>
+                        </table>
+                    </div>
+                )) }
             </body>
         </html>
     );
@@ -85,7 +234,14 @@ table td:last-child {
     html.to_string()
 }
 
-pub fn as_webpbn(puzzle: &Puzzle) -> String {
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn as_webpbn(puzzle: &Puzzle, metadata: &Metadata) -> String {
     use indoc::indoc;
 
     let mut res = String::new();
@@ -96,6 +252,18 @@ pub fn as_webpbn(puzzle: &Puzzle) -> String {
         <puzzle type="grid" defaultcolor="white">
         <source>convert-nonogram</source>
         "#});
+    // The DTD requires this order.
+    for (tag, value) in [
+        ("id", &metadata.id),
+        ("title", &metadata.title),
+        ("author", &metadata.author),
+        ("copyright", &metadata.copyright_notice()),
+        ("description", &metadata.description),
+    ] {
+        if let Some(value) = value {
+            res.push_str(&format!("<{tag}>{}</{tag}>\n", xml_escape(value)));
+        }
+    }
     for color in puzzle.palette.values() {
         let (r, g, b) = color.rgb;
         res.push_str(&format!(
@@ -133,6 +301,22 @@ pub fn as_webpbn(puzzle: &Puzzle) -> String {
     res.push_str(r#"</clues>"#);
     res.push('\n');
 
+    if let Some(stats) = metadata.solver_stats {
+        res.push_str(&format!("<note>Line solver: {}</note>\n", stats));
+    }
+    for note in &metadata.notes {
+        res.push_str(&format!("<note>{}</note>\n", xml_escape(&note.to_string())));
+    }
+    // `import::webpbn_metadata` looks for these prefixes.
+    for (prefix, value) in [
+        ("Created", &metadata.created),
+        ("Modified", &metadata.modified),
+    ] {
+        if let Some(value) = value {
+            res.push_str(&format!("<note>{}: {}</note>\n", prefix, xml_escape(value)));
+        }
+    }
+
     res.push_str(r#"</puzzle></puzzleset>"#);
     res.push('\n');
 
@@ -159,7 +343,8 @@ pub fn as_olsak(puzzle: &Puzzle) -> String {
         for clue in row {
             res.push_str(&format!(
                 "{}{} ",
-                clue.count, puzzle.palette[&clue.color].ch
+                clue.count_text(),
+                puzzle.palette[&clue.color].ch
             ));
         }
         res.push('\n');
@@ -169,7 +354,8 @@ pub fn as_olsak(puzzle: &Puzzle) -> String {
         for clue in column {
             res.push_str(&format!(
                 "{}{} ",
-                clue.count, puzzle.palette[&clue.color].ch
+                clue.count_text(),
+                puzzle.palette[&clue.color].ch
             ));
         }
         res.push('\n');
@@ -178,13 +364,93 @@ pub fn as_olsak(puzzle: &Puzzle) -> String {
     res
 }
 
-pub fn emit_image<P>(solution: &Solution, path: P) -> anyhow::Result<()>
-where
-    P: AsRef<Path>,
-{
+/// One character per cell, as `import::char_grid_to_solution` reads them.
+pub fn as_char_grid(solution: &Solution) -> String {
+    let height = solution.grid.first().map_or(0, Vec::len);
+    let mut res = String::new();
+    for y in 0..height {
+        for col in &solution.grid {
+            res.push(solution.palette[&col[y]].ch);
+        }
+        res.push('\n');
+    }
+    res
+}
+
+/// A preview for the terminal: the picture (if there is one) in its own colors, two characters per
+/// cell, then the clues, each in its color. When `colored` has been told not to color anything,
+/// it's plain ASCII instead: cells are their colors' characters, and so are clues' suffixes.
+pub fn as_terminal(puzzle: &Puzzle, solution: Option<&Solution>) -> String {
+    use colored::Colorize;
+    let plain = !colored::control::SHOULD_COLORIZE.should_colorize();
+    let colorize = |text: &str, color: Color| {
+        let info = &puzzle.palette[&color];
+        if plain {
+            return format!("{}{}", text, info.ch);
+        }
+        let (r, g, b) = info.rgb;
+        text.truecolor(r, g, b).to_string()
+    };
+
+    let mut res = String::new();
+    if let Some(solution) = solution {
+        let height = solution.grid.first().map_or(0, Vec::len);
+        for y in 0..height {
+            for (x, col) in solution.grid.iter().enumerate() {
+                match col[y] {
+                    _ if solution.void.contains(&(x, y)) => res.push_str("  "),
+                    color if plain => {
+                        res.push(' ');
+                        res.push(solution.palette[&color].ch);
+                    }
+                    color if color == BACKGROUND => res.push_str(&" ·".dimmed().to_string()),
+                    color => res.push_str(&colorize("██", color)),
+                }
+            }
+            res.push('\n');
+        }
+        res.push('\n');
+    }
+
+    for (heading, lanes) in [("Rows", &puzzle.rows), ("Columns", &puzzle.cols)] {
+        res.push_str(&format!("{}:\n", heading));
+        let number_width = lanes.len().to_string().len();
+        for (i, clues) in lanes.iter().enumerate() {
+            res.push_str(&format!("{:>width$}:", i + 1, width = number_width));
+            for clue in clues {
+                res.push(' ');
+                res.push_str(&colorize(&clue.count_text(), clue.color));
+            }
+            res.push('\n');
+        }
+    }
+    res
+}
+
+/// Emits the palette as a GIMP palette (`.gpl`), readable by `import::gpl_to_palette`.
+pub fn as_gpl(palette: &HashMap<Color, ColorInfo>, name: &str) -> String {
+    let mut res = String::new();
+    res.push_str("GIMP Palette\n");
+    res.push_str(&format!("Name: {}\n", name));
+    res.push_str("#\n");
+
+    let mut colors: Vec<&ColorInfo> = palette.values().collect();
+    colors.sort_by_key(|ci| ci.color.0);
+    for color in colors {
+        let (r, g, b) = color.rgb;
+        res.push_str(&format!(
+            "{:3} {:3} {:3}\t{} {}\n",
+            r, g, b, color.ch, color.name
+        ));
+    }
+
+    res
+}
+
+fn solution_to_image(solution: &Solution) -> RgbImage {
     let mut image = RgbImage::new(
         solution.grid.len() as u32,
-        solution.grid.first().unwrap().len() as u32,
+        solution.grid.first().map_or(0, Vec::len) as u32,
     );
 
     for (x, col) in solution.grid.iter().enumerate() {
@@ -195,5 +461,390 @@ where
         }
     }
 
-    Ok(image.save(path)?)
+    image
+}
+
+/// How to draw a solution as an image.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageOptions {
+    /// Pixels per cell, in each direction.
+    pub scale: u32,
+    /// Draw a 1-pixel line between cells (and around the edge).
+    pub grid_lines: bool,
+    /// Interval between dark grid lines; 0 turns them off.
+    pub major_lines: usize,
+    /// For HTML, what to do about clue colors that don't show up on white.
+    pub low_contrast: LowContrast,
+    /// For HTML, add a page with the solution after the puzzle, if there is one.
+    pub answer_key: bool,
+    /// Write the puzzle even if the format can't express all of its rules (see `check_rules`),
+    /// leaving out the ones it can't.
+    pub drop_rules: bool,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        ImageOptions {
+            scale: 1,
+            grid_lines: false,
+            major_lines: 0,
+            low_contrast: LowContrast::default(),
+            answer_key: false,
+            drop_rules: false,
+        }
+    }
+}
+
+/// The picture, before it's encoded as a PNG.
+pub fn solution_to_scaled_image(solution: &Solution, options: ImageOptions) -> RgbImage {
+    let cells = solution_to_image(solution);
+    if options.scale <= 1 && !options.grid_lines {
+        return cells;
+    }
+
+    let scale = std::cmp::max(options.scale, 1);
+    let line = options.grid_lines as u32;
+    let (width, height) = cells.dimensions();
+    let image_width = width * (scale + line) + line;
+    let image_height = height * (scale + line) + line;
+    let mut image = RgbImage::new(image_width, image_height);
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let (cell_x, offset_x) = (x / (scale + line), x % (scale + line));
+        let (cell_y, offset_y) = (y / (scale + line), y % (scale + line));
+        let on_line_x = line == 1 && offset_x == 0;
+        let on_line_y = line == 1 && offset_y == 0;
+        *pixel = if on_line_x || on_line_y {
+            let is_major = |cell: u32, on_line: bool| {
+                on_line
+                    && options.major_lines > 0
+                    && (cell as usize).is_multiple_of(options.major_lines)
+            };
+            let edge = x == 0 || y == 0 || x == image_width - 1 || y == image_height - 1;
+            if edge || is_major(cell_x, on_line_x) || is_major(cell_y, on_line_y) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([160, 160, 160])
+            }
+        } else {
+            *cells.get_pixel(cell_x, cell_y)
+        };
+    }
+    image
+}
+
+/// Writes PNGs directly (rather than through `image`), so that the metadata can go in text chunks.
+fn write_png<W: std::io::Write>(image: &RgbImage, metadata: &Metadata, w: W) -> Result<()> {
+    // One per line, which `import::png_metadata` splits up again.
+    let notes: Vec<String> = metadata.notes.iter().map(|note| note.to_string()).collect();
+    let notes_text = (!notes.is_empty()).then(|| notes.join("\n"));
+    let mut encoder = png::Encoder::new(w, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, value) in [
+        ("Title", &metadata.title),
+        ("Author", &metadata.author),
+        ("Copyright", &metadata.copyright),
+        ("License", &metadata.license),
+        ("Description", &metadata.description),
+        ("Difficulty", &metadata.difficulty),
+        (
+            "Solver",
+            &metadata.solver_stats.map(|stats| stats.to_string()),
+        ),
+        ("Identifier", &metadata.id),
+        ("Creation Time", &metadata.created),
+        ("Modification Time", &metadata.modified),
+        ("Notes", &notes_text),
+    ] {
+        if let Some(value) = value {
+            encoder.add_itxt_chunk(keyword.to_string(), value.clone())?;
+        }
+    }
+    encoder.write_header()?.write_image_data(image.as_raw())?;
+    Ok(())
+}
+
+/// Infers the image format from the extension of `path`. Only PNGs get the metadata.
+pub fn emit_image<P>(
+    solution: &Solution,
+    path: P,
+    options: ImageOptions,
+    metadata: &Metadata,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+{
+    let image = solution_to_scaled_image(solution, options);
+    let is_png = path
+        .as_ref()
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        write_png(&image, metadata, std::fs::File::create(path)?)
+    } else {
+        Ok(image.save(path)?)
+    }
+}
+
+/// Encodes the solution as a PNG, for when there's no filename to infer a format from.
+pub fn as_image_bytes(
+    solution: &Solution,
+    options: ImageOptions,
+    metadata: &Metadata,
+) -> Result<Vec<u8>> {
+    let mut res = vec![];
+    write_png(
+        &solution_to_scaled_image(solution, options),
+        metadata,
+        &mut res,
+    )?;
+    Ok(res)
+}
+
+/// The rule variants that `format` (as in `to_bytes`) can write.
+pub fn rules_supported(format: &str) -> &'static [Rule] {
+    match format {
+        "html" | "json" => &Rule::ALL,
+        "olsak" => &[Rule::InexactClues],
+        _ => &[],
+    }
+}
+
+/// An error if `puzzle` uses rules that `format` can't write, since a solver reading the result
+/// would be solving a different puzzle.
+pub fn check_rules(puzzle: &Puzzle, format: &str) -> Result<()> {
+    let missing: Vec<String> = puzzle
+        .rules()
+        .into_iter()
+        .filter(|rule| !rules_supported(format).contains(rule))
+        .map(|rule| rule.to_string())
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Unsupported(format!(
+        "{} output can't express {}",
+        format,
+        missing.join(" or ")
+    )))
+}
+
+/// Writes the document in `format` ("webpbn", "olsak", "html", "json", "char-grid", or "image",
+/// which is a PNG). `options.major_lines`, `options.low_contrast`, and `options.answer_key` also
+/// apply to HTML. Fails if the format can't express the puzzle's rules, unless
+/// `options.drop_rules`.
+pub fn to_bytes(document: &Document, format: &str, options: ImageOptions) -> Result<Vec<u8>> {
+    let (puzzle, metadata) = (&document.puzzle, &document.metadata);
+    if !options.drop_rules {
+        check_rules(puzzle, format)?;
+    }
+    Ok(match format {
+        "webpbn" => as_webpbn(puzzle, metadata).into_bytes(),
+        "olsak" => as_olsak(puzzle).into_bytes(),
+        "html" => {
+            let answer_key = document.solution.as_ref().filter(|_| options.answer_key);
+            as_html(
+                puzzle,
+                options.major_lines,
+                options.low_contrast,
+                metadata,
+                answer_key,
+            )
+            .into_bytes()
+        }
+        "json" => {
+            let unsupported = |e: serde_json::Error| Error::Unsupported(e.to_string());
+            let mut value = serde_json::to_value(puzzle).map_err(unsupported)?;
+            // Alongside the puzzle's fields, so that anything expecting a bare `Puzzle` can still
+            // read it.
+            if let (false, Some(object)) = (metadata.is_empty(), value.as_object_mut()) {
+                let stored = serde_json::to_value(metadata).map_err(unsupported)?;
+                object.insert("metadata".to_string(), stored);
+            }
+            serde_json::to_vec(&value).map_err(unsupported)?
+        }
+        "image" | "char-grid" => match &document.solution {
+            Some(solution) if format == "image" => as_image_bytes(solution, options, metadata)?,
+            Some(solution) => as_char_grid(solution).into_bytes(),
+            None => {
+                return Err(Error::Unsupported(format!(
+                    "{} output needs a solution, but {} input only has clues",
+                    format, document.source_format
+                )))
+            }
+        },
+        _ => {
+            return Err(Error::Unsupported(format!(
+                "can't write {:?} puzzles",
+                format
+            )))
+        }
+    })
+}
+
+/// A `.loompack`: JSON with the pack's metadata, each distinct palette once, and the puzzles. Each
+/// puzzle is in the same form as "json" output, except that its `palette` is an index into the
+/// shared ones, and it has a `solution` (columns of color numbers, as in `Solution::grid`) if
+/// the document has one.
+pub fn as_loompack(pack: &Pack) -> Result<Vec<u8>> {
+    let unsupported = |e: serde_json::Error| Error::Unsupported(e.to_string());
+    let mut palettes: Vec<Vec<&ColorInfo>> = vec![];
+    let mut puzzles = vec![];
+    for document in &pack.documents {
+        let mut palette: Vec<&ColorInfo> = document.puzzle.palette.values().collect();
+        palette.sort_by_key(|info| info.color.0);
+        let index = match palettes.iter().position(|seen| *seen == palette) {
+            Some(index) => index,
+            None => {
+                palettes.push(palette);
+                palettes.len() - 1
+            }
+        };
+
+        let mut value = serde_json::to_value(&document.puzzle).map_err(unsupported)?;
+        let object = value
+            .as_object_mut()
+            .expect("a `Puzzle` should serialize as an object");
+        object.insert("palette".to_string(), index.into());
+        if let Some(solution) = &document.solution {
+            let grid = serde_json::to_value(&solution.grid).map_err(unsupported)?;
+            object.insert("solution".to_string(), grid);
+        }
+        if !document.metadata.is_empty() {
+            let stored = serde_json::to_value(&document.metadata).map_err(unsupported)?;
+            object.insert("metadata".to_string(), stored);
+        }
+        puzzles.push(value);
+    }
+
+    let mut res = serde_json::json!({
+        "loompack": 1,
+        "palettes": palettes,
+        "puzzles": puzzles,
+    });
+    if !pack.metadata.is_empty() {
+        res["metadata"] = serde_json::to_value(&pack.metadata).map_err(unsupported)?;
+    }
+    serde_json::to_vec(&res).map_err(unsupported)
+}
+
+/// One puzzle in `as_gallery_index`.
+#[derive(Clone, Debug)]
+pub struct GalleryEntry {
+    pub title: String,
+    /// Where the puzzle's own page is, relative to the index.
+    pub page: String,
+    /// Where its preview is, relative to the index, if there's a picture to preview.
+    pub thumbnail: Option<String>,
+    pub width: usize,
+    pub height: usize,
+    pub colors: usize,
+    /// E.g. "40 skims, 2 scrubs".
+    pub difficulty: String,
+}
+
+/// A web page listing a collection of puzzles, with a preview of each one that links to its page.
+pub fn as_gallery_index(title: &str, entries: &[GalleryEntry]) -> String {
+    let style = "
+table {
+  border-collapse: collapse;
+}
+td, th {
+  padding: 4px 12px;
+  text-align: left;
+}
+tr:nth-child(even) {
+  background: #f4f4f4;
+}
+img {
+  image-rendering: pixelated;
+}
+";
+
+    let html: axohtml::dom::DOMTree<String> = html!(
+        <html>
+            <head>
+            <title>{text!(title)}</title>
+            <style>
+            {text!(style)}
+            </style>
+            </head>
+            <body>
+                <h1>{text!(title)}</h1>
+                <table>
+                    <thead>
+                        <tr>
+                            <th></th>
+                            <th>{text!("Puzzle")}</th>
+                            <th>{text!("Size")}</th>
+                            <th>{text!("Colors")}</th>
+                            <th>{text!("Difficulty")}</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                    { entries.iter().map(|entry| html!(
+                        <tr>
+                            <td><a href=(entry.page.clone())>{
+                                entry.thumbnail.iter().map(|src| html!(<img src=(src.clone()) alt=(entry.title.clone())/>))
+                            }</a></td>
+                            <td><a href=(entry.page.clone())>{text!(entry.title.clone())}</a></td>
+                            <td>{text!("{}x{}", entry.width, entry.height)}</td>
+                            <td>{text!("{}", entry.colors)}</td>
+                            <td>{text!(entry.difficulty.clone())}</td>
+                        </tr>
+                    )) }
+                    </tbody>
+                </table>
+            </body>
+        </html>
+    );
+
+    html.to_string()
+}
+
+/// A small preview of the solution, at most `size` pixels on a side. `pixelate` averages it into
+/// blocks that many cells wide, and `blur` applies a Gaussian blur with that sigma, so that
+/// previews in a gallery don't give the puzzle away.
+pub fn thumbnail(
+    solution: &Solution,
+    size: u32,
+    pixelate: Option<u32>,
+    blur: Option<f32>,
+) -> RgbImage {
+    let mut image = solution_to_image(solution);
+    let (width, height) = image.dimensions();
+
+    if let Some(block) = pixelate.filter(|&block| block > 1) {
+        let small = imageops::resize(
+            &image,
+            width.div_ceil(block),
+            height.div_ceil(block),
+            FilterType::Triangle,
+        );
+        image = imageops::resize(&small, width, height, FilterType::Nearest);
+    }
+
+    // Scale by a whole number when enlarging, so the cells stay square.
+    let longest = std::cmp::max(width, height);
+    let (thumb_width, thumb_height) = if longest <= size {
+        let scale = size / longest;
+        (width * scale, height * scale)
+    } else {
+        (
+            std::cmp::max(1, width * size / longest),
+            std::cmp::max(1, height * size / longest),
+        )
+    };
+    let filter = if longest <= size {
+        FilterType::Nearest
+    } else {
+        FilterType::Triangle
+    };
+    image = imageops::resize(&image, thumb_width, thumb_height, filter);
+
+    if let Some(sigma) = blur {
+        image = imageops::blur(&image, sigma);
+    }
+    image
 }
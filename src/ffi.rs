@@ -0,0 +1,195 @@
+//! A C API for the solver, enabled by the `ffi` feature. `include/convert_nonogram.h` declares
+//! it. Every function is safe to call with null pointers (they're treated as errors), and errors
+//! are reported by returning null or -1, with details from `cn_last_error`.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::{
+    error::{Error, Result},
+    grid_solve::{self, Report},
+    import,
+    puzzle::Puzzle,
+};
+
+pub struct CnPuzzle(Puzzle);
+
+pub struct CnReport(Report);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, turning errors and panics into `on_error` (and a message for `cn_last_error`), so
+/// that neither crosses the FFI boundary.
+fn guard<T>(on_error: T, f: impl FnOnce() -> Result<T>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(res)) => res,
+        Ok(Err(e)) => {
+            set_last_error(e.to_string());
+            on_error
+        }
+        Err(_) => {
+            set_last_error("internal error (panic)".to_string());
+            on_error
+        }
+    }
+}
+
+fn null_error() -> Error {
+    Error::Unsupported("unexpected null pointer".to_string())
+}
+
+/// The message for the most recent error on this thread, or null if there hasn't been one. Valid
+/// until the next call into this library on the same thread.
+#[no_mangle]
+pub extern "C" fn cn_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |msg| msg.as_ptr())
+    })
+}
+
+/// Reads a puzzle from `len` bytes at `data`. `format` is one of "image", "svg", "webpbn",
+/// "olsak", "char-grid", or "json". Returns null on failure.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, and `format` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cn_puzzle_load(
+    data: *const u8,
+    len: usize,
+    format: *const c_char,
+) -> *mut CnPuzzle {
+    guard(ptr::null_mut(), || {
+        if data.is_null() || format.is_null() {
+            return Err(null_error());
+        }
+        let bytes = std::slice::from_raw_parts(data, len);
+        let format = CStr::from_ptr(format).to_string_lossy();
+        let document = import::from_bytes(bytes, &format)?;
+        Ok(Box::into_raw(Box::new(CnPuzzle(document.puzzle))))
+    })
+}
+
+/// # Safety
+///
+/// `puzzle` must be null or have come from `cn_puzzle_load`, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn cn_puzzle_free(puzzle: *mut CnPuzzle) {
+    if !puzzle.is_null() {
+        drop(Box::from_raw(puzzle));
+    }
+}
+
+/// # Safety
+///
+/// `puzzle` must be null or a live puzzle from `cn_puzzle_load`.
+#[no_mangle]
+pub unsafe extern "C" fn cn_puzzle_width(puzzle: *const CnPuzzle) -> c_int {
+    guard(-1, || {
+        let puzzle = puzzle.as_ref().ok_or_else(null_error)?;
+        Ok(puzzle.0.cols.len() as c_int)
+    })
+}
+
+/// # Safety
+///
+/// `puzzle` must be null or a live puzzle from `cn_puzzle_load`.
+#[no_mangle]
+pub unsafe extern "C" fn cn_puzzle_height(puzzle: *const CnPuzzle) -> c_int {
+    guard(-1, || {
+        let puzzle = puzzle.as_ref().ok_or_else(null_error)?;
+        Ok(puzzle.0.rows.len() as c_int)
+    })
+}
+
+/// Solves the puzzle with line logic. Returns null if the clues are contradictory. Getting stuck
+/// isn't an error; check `cn_report_cells_left`.
+///
+/// # Safety
+///
+/// `puzzle` must be null or a live puzzle from `cn_puzzle_load`.
+#[no_mangle]
+pub unsafe extern "C" fn cn_solve(puzzle: *const CnPuzzle) -> *mut CnReport {
+    guard(ptr::null_mut(), || {
+        let puzzle = puzzle.as_ref().ok_or_else(null_error)?;
+        let report = grid_solve::solve(&puzzle.0, false, None)?;
+        Ok(Box::into_raw(Box::new(CnReport(report))))
+    })
+}
+
+/// # Safety
+///
+/// `report` must be null or have come from `cn_solve`, and not have been freed already.
+#[no_mangle]
+pub unsafe extern "C" fn cn_report_free(report: *mut CnReport) {
+    if !report.is_null() {
+        drop(Box::from_raw(report));
+    }
+}
+
+/// # Safety
+///
+/// `report` must be null or a live report from `cn_solve`.
+#[no_mangle]
+pub unsafe extern "C" fn cn_report_skims(report: *const CnReport) -> c_int {
+    guard(-1, || {
+        let report = report.as_ref().ok_or_else(null_error)?;
+        Ok(report.0.skims as c_int)
+    })
+}
+
+/// # Safety
+///
+/// `report` must be null or a live report from `cn_solve`.
+#[no_mangle]
+pub unsafe extern "C" fn cn_report_scrubs(report: *const CnReport) -> c_int {
+    guard(-1, || {
+        let report = report.as_ref().ok_or_else(null_error)?;
+        Ok(report.0.scrubs as c_int)
+    })
+}
+
+/// How many cells line logic couldn't determine; 0 means the puzzle was solved (and is unique).
+///
+/// # Safety
+///
+/// `report` must be null or a live report from `cn_solve`.
+#[no_mangle]
+pub unsafe extern "C" fn cn_report_cells_left(report: *const CnReport) -> c_int {
+    guard(-1, || {
+        let report = report.as_ref().ok_or_else(null_error)?;
+        Ok(report.0.cells_left as c_int)
+    })
+}
+
+/// The color index (0 for the background) of the cell at column `x`, row `y`, or -1 if it's
+/// undetermined or out of bounds.
+///
+/// # Safety
+///
+/// `report` must be null or a live report from `cn_solve`.
+#[no_mangle]
+pub unsafe extern "C" fn cn_report_cell(report: *const CnReport, x: usize, y: usize) -> c_int {
+    guard(-1, || {
+        let report = report.as_ref().ok_or_else(null_error)?;
+        Ok(report
+            .0
+            .grid
+            .get([y, x])
+            .and_then(|cell| cell.known_or())
+            .map_or(-1, |color| color.0 as c_int))
+    })
+}
@@ -0,0 +1,910 @@
+//! Making puzzles, rather than converting them.
+//!
+//! Random puzzles, for filler and for benchmarking the solver, are made by generate-and-test:
+//! scatter random cells, then keep the first picture that line logic solves (so it's unique) with
+//! a difficulty in the requested range. Puzzles from photos are downsampled and reduced to a few
+//! colors, and can be repaired if they turn out to be ambiguous.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use rand::Rng;
+
+use crate::{
+    error::{Error, Result},
+    grid_solve::{self, Report},
+    import::{self, Rgb},
+    puzzle::{Color, ColorInfo, Puzzle, Solution, BACKGROUND},
+};
+
+/// Foreground colors, in the order they're used.
+static COLORS: [(char, &str, (u8, u8, u8)); 8] = [
+    ('#', "black", (0, 0, 0)),
+    ('r', "red", (220, 40, 40)),
+    ('b', "blue", (40, 80, 220)),
+    ('g', "green", (40, 160, 60)),
+    ('y', "yellow", (240, 200, 40)),
+    ('m', "magenta", (200, 40, 200)),
+    ('c', "cyan", (40, 200, 220)),
+    ('o', "orange", (240, 130, 30)),
+];
+
+pub const MAX_COLORS: usize = COLORS.len();
+
+pub struct GenOptions {
+    pub width: usize,
+    pub height: usize,
+    /// Foreground colors, from 1 to `MAX_COLORS`.
+    pub colors: usize,
+    /// The chance that a cell is filled in.
+    pub density: f64,
+    /// Average size of a patch of one color; 1 scatters cells independently.
+    pub blob_size: usize,
+    /// Relative share of the filled cells for each color; empty means equal shares.
+    pub color_weights: Vec<f64>,
+    pub skims: RangeInclusive<usize>,
+    pub scrubs: RangeInclusive<usize>,
+    /// How many pictures to try before giving up.
+    pub attempts: usize,
+    /// Solver time limit for each attempt.
+    pub timeout: Option<Duration>,
+}
+
+/// A successful attempt.
+pub struct Generated {
+    pub puzzle: Puzzle,
+    pub solution: Solution,
+    pub report: Report,
+    /// 1-based.
+    pub attempt: usize,
+}
+
+/// A picture with each cell filled in with probability `density`, in one of `colors` colors.
+pub fn random_solution(
+    rng: &mut impl Rng,
+    width: usize,
+    height: usize,
+    colors: usize,
+    density: f64,
+) -> Solution {
+    let colors = colors.clamp(1, MAX_COLORS);
+
+    let grid = (0..width)
+        .map(|_| {
+            (0..height)
+                .map(|_| {
+                    if rng.gen_bool(density.clamp(0.0, 1.0)) {
+                        Color(rng.gen_range(1..=colors as u8))
+                    } else {
+                        BACKGROUND
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    Solution {
+        palette: palette(colors),
+        grid,
+        void: BTreeSet::new(),
+    }
+}
+
+/// Grows random patches of color, each about `options.blob_size` cells, until about
+/// `options.density` of the grid is filled in. Each patch's color is picked according to
+/// `options.color_weights`.
+pub fn random_blobs(rng: &mut impl Rng, options: &GenOptions) -> Solution {
+    let (width, height) = (options.width, options.height);
+    let colors = options.colors.clamp(1, MAX_COLORS);
+    let weights: Vec<f64> = (0..colors)
+        .map(|i| {
+            options
+                .color_weights
+                .get(i)
+                .copied()
+                .unwrap_or(1.0)
+                .max(0.0)
+        })
+        .collect();
+    let total_weight: f64 = weights.iter().sum();
+
+    let mut grid = vec![vec![BACKGROUND; height]; width];
+    let target = (options.density.clamp(0.0, 1.0) * (width * height) as f64).round() as usize;
+    let mut filled = 0;
+    // Seeds can land on filled cells, so give up eventually rather than looping forever.
+    let mut tries = 0;
+    while filled < target && tries < 100 * width * height {
+        tries += 1;
+        let (x, y) = (rng.gen_range(0..width), rng.gen_range(0..height));
+        if grid[x][y] != BACKGROUND {
+            continue;
+        }
+
+        let mut pick = rng.gen_range(0.0..total_weight.max(f64::MIN_POSITIVE));
+        let mut color = Color(colors as u8);
+        for (i, weight) in weights.iter().enumerate() {
+            if pick < *weight {
+                color = Color(i as u8 + 1);
+                break;
+            }
+            pick -= weight;
+        }
+
+        // Sizes vary from 1 to about twice the average.
+        let size = rng.gen_range(1..=2 * std::cmp::max(options.blob_size, 1) - 1);
+        let mut frontier = vec![(x, y)];
+        let mut grown = 0;
+        while grown < size && filled < target && !frontier.is_empty() {
+            let (x, y) = frontier.swap_remove(rng.gen_range(0..frontier.len()));
+            if grid[x][y] != BACKGROUND {
+                continue;
+            }
+            grid[x][y] = color;
+            grown += 1;
+            filled += 1;
+            if x > 0 {
+                frontier.push((x - 1, y));
+            }
+            if x + 1 < width {
+                frontier.push((x + 1, y));
+            }
+            if y > 0 {
+                frontier.push((x, y - 1));
+            }
+            if y + 1 < height {
+                frontier.push((x, y + 1));
+            }
+        }
+    }
+
+    Solution {
+        palette: palette(colors),
+        grid,
+        void: BTreeSet::new(),
+    }
+}
+
+/// The background and the first `colors` of `COLORS`.
+fn palette(colors: usize) -> HashMap<Color, ColorInfo> {
+    let mut palette = HashMap::new();
+    palette.insert(
+        BACKGROUND,
+        ColorInfo {
+            ch: '.',
+            name: "white".to_string(),
+            rgb: (255, 255, 255),
+            color: BACKGROUND,
+        },
+    );
+    for (i, (ch, name, rgb)) in COLORS.iter().take(colors).enumerate() {
+        let color = Color(i as u8 + 1);
+        palette.insert(
+            color,
+            ColorInfo {
+                ch: *ch,
+                name: name.to_string(),
+                rgb: *rgb,
+                color,
+            },
+        );
+    }
+    palette
+}
+
+/// Regular pictures, for feeding the solver inputs with a known shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// Horizontal bands `period` cells thick, cycling through the background and the colors.
+    Stripes,
+    /// Squares `period` cells on a side, cycling like `Stripes`.
+    Checkers,
+    /// Diagonal lines, `period` cells apart. On a square grid with a period at least as large as
+    /// the grid, every clue is a single 1, so line logic can't determine a thing, but it has to do
+    /// as much work as possible to find that out.
+    WorstCase,
+}
+
+pub fn pattern_solution(
+    pattern: Pattern,
+    width: usize,
+    height: usize,
+    colors: usize,
+    period: usize,
+) -> Solution {
+    let colors = colors.clamp(1, MAX_COLORS);
+    let period = std::cmp::max(period, 1);
+    let cycle = |i: usize| Color((i % (colors + 1)) as u8);
+
+    let grid = (0..width)
+        .map(|x| {
+            (0..height)
+                .map(|y| match pattern {
+                    Pattern::Stripes => cycle(y / period),
+                    Pattern::Checkers => cycle(x / period + y / period),
+                    Pattern::WorstCase if x % period == y % period => cycle(1 + x / period),
+                    Pattern::WorstCase => BACKGROUND,
+                })
+                .collect()
+        })
+        .collect();
+
+    Solution {
+        palette: palette(colors),
+        grid,
+        void: BTreeSet::new(),
+    }
+}
+
+/// Tries up to `options.attempts` random pictures; `None` if none of them fit.
+pub fn generate(rng: &mut impl Rng, options: &GenOptions) -> Result<Option<Generated>> {
+    for attempt in 1..=options.attempts {
+        let solution = if options.blob_size <= 1 && options.color_weights.is_empty() {
+            random_solution(
+                rng,
+                options.width,
+                options.height,
+                options.colors,
+                options.density,
+            )
+        } else {
+            random_blobs(rng, options)
+        };
+        let puzzle = import::solution_to_puzzle(&solution)?;
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let report = grid_solve::solve(&puzzle, false, deadline)?;
+
+        if report.cells_left == 0
+            && options.skims.contains(&report.skims)
+            && options.scrubs.contains(&report.scrubs)
+        {
+            return Ok(Some(Generated {
+                puzzle,
+                solution,
+                report,
+                attempt,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// How to turn a photo into a puzzle; see `photo_to_solution`.
+pub struct PhotoOptions {
+    /// Columns in the puzzle.
+    pub width: u32,
+    /// Rows in the puzzle; `None` keeps the photo's aspect ratio.
+    pub height: Option<u32>,
+    /// Colors in the puzzle, counting the background.
+    pub colors: usize,
+    pub dither: bool,
+}
+
+/// Downsamples the photo to the puzzle's size, then reduces it to `options.colors` colors
+/// (dithering if asked to). Transparent areas become background.
+pub fn photo_to_solution(photo: &DynamicImage, options: &PhotoOptions) -> Result<Solution> {
+    let photo = &flatten(photo);
+    let height = options.height.unwrap_or_else(|| {
+        let aspect = photo.height() as f64 / std::cmp::max(photo.width(), 1) as f64;
+        std::cmp::max((options.width as f64 * aspect).round() as u32, 1)
+    });
+    let small = photo.resize_exact(options.width, height, FilterType::Triangle);
+
+    let (reduced, map) = import::reduce_image_colors(&small, options.colors);
+    let image = if options.dither {
+        let palette: BTreeSet<Rgb> = map.values().copied().collect();
+        import::dither_image(&small, &palette.into_iter().collect::<Vec<_>>())
+    } else {
+        reduced
+    };
+    import::image_to_solution(&image)
+}
+
+/// Edits cells that line logic can't determine, one at a time, keeping only edits that leave
+/// fewer cells undetermined, until the puzzle solves or `max_edits` edits have been made.
+/// Returns the number of edits and the final report.
+pub fn repair(
+    solution: &mut Solution,
+    max_edits: usize,
+    timeout: Option<Duration>,
+) -> Result<(usize, Report)> {
+    // Trying every undetermined cell each round would take too long on big, ambiguous puzzles.
+    const CANDIDATES_PER_EDIT: usize = 30;
+
+    let solve = |solution: &Solution| {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        grid_solve::solve(&import::solution_to_puzzle(solution)?, false, deadline)
+    };
+
+    let mut report = solve(solution)?;
+    let mut edits = 0;
+    while report.cells_left > 0 && edits < max_edits {
+        let undetermined: Vec<(usize, usize)> = report
+            .grid
+            .indexed_iter()
+            .filter(|(_, cell)| cell.known_or().is_none())
+            .map(|((y, x), _)| (x, y))
+            .take(CANDIDATES_PER_EDIT)
+            .collect();
+
+        let mut improved = false;
+        for (x, y) in undetermined {
+            let mut candidate = solution.clone();
+            candidate.grid[x][y] = if candidate.grid[x][y] == BACKGROUND {
+                neighboring_color(&candidate, x, y)
+            } else {
+                BACKGROUND
+            };
+            let candidate_report = solve(&candidate)?;
+            if candidate_report.cells_left < report.cells_left {
+                *solution = candidate;
+                report = candidate_report;
+                improved = true;
+                break;
+            }
+        }
+        if !improved {
+            break;
+        }
+        edits += 1;
+    }
+    Ok((edits, report))
+}
+
+/// The most common foreground color next to the cell (or any foreground color, if there are none
+/// nearby).
+fn neighboring_color(solution: &Solution, x: usize, y: usize) -> Color {
+    let mut counts = HashMap::<Color, usize>::new();
+    for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+        if nx < 0 || ny < 0 {
+            continue;
+        }
+        if let Some(&color) = solution
+            .grid
+            .get(nx as usize)
+            .and_then(|col| col.get(ny as usize))
+        {
+            if color != BACKGROUND {
+                *counts.entry(color).or_default() += 1;
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(color, count)| (*count, std::cmp::Reverse(color.0)))
+        .map(|(color, _)| color)
+        .or_else(|| {
+            solution
+                .palette
+                .keys()
+                .filter(|color| **color != BACKGROUND)
+                .min_by_key(|color| color.0)
+                .copied()
+        })
+        .unwrap_or(BACKGROUND)
+}
+
+/// A change to one cell of a solution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub x: usize,
+    pub y: usize,
+    pub from: Color,
+    pub to: Color,
+}
+
+pub fn apply_edits(solution: &mut Solution, edits: &[Edit]) {
+    for edit in edits {
+        solution.grid[edit.x][edit.y] = edit.to;
+    }
+}
+
+/// Searches for the smallest set of edits (of at most `max_edits` cells) that lets line logic
+/// solve the puzzle. Every recoloring of each cell the solver couldn't determine is tried on its
+/// own first; then every combination of the `max_candidates` cells whose recolorings helped most
+/// is tried. Unlike `repair`, this finds fixes where no single edit is enough. Returns
+/// `Some(vec![])` if the puzzle already solves, and `None` if nothing within the bounds works.
+pub fn minimal_repair(
+    solution: &Solution,
+    max_edits: usize,
+    max_candidates: usize,
+    timeout: Option<Duration>,
+) -> Result<Option<Vec<Edit>>> {
+    let cells_left = |solution: &Solution| -> Result<usize> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let puzzle = import::solution_to_puzzle(solution)?;
+        Ok(grid_solve::solve(&puzzle, false, deadline)?.cells_left)
+    };
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let report = grid_solve::solve(&import::solution_to_puzzle(solution)?, false, deadline)?;
+    if report.cells_left == 0 {
+        return Ok(Some(vec![]));
+    }
+    if max_edits == 0 {
+        return Ok(None);
+    }
+
+    let mut colors: Vec<Color> = solution.palette.keys().copied().collect();
+    colors.sort_by_key(|color| color.0);
+
+    // Each undetermined cell, with every way of recoloring it, and how well the best one does.
+    let mut candidates: Vec<(usize, Vec<Edit>)> = vec![];
+    for ((y, x), cell) in report.grid.indexed_iter() {
+        if cell.known_or().is_some() {
+            continue;
+        }
+        let from = solution.grid[x][y];
+        let edits: Vec<Edit> = colors
+            .iter()
+            .filter(|to| **to != from)
+            .map(|&to| Edit { x, y, from, to })
+            .collect();
+        let mut best = report.cells_left;
+        for edit in &edits {
+            let mut candidate = solution.clone();
+            apply_edits(&mut candidate, &[*edit]);
+            let left = cells_left(&candidate)?;
+            if left == 0 {
+                return Ok(Some(vec![*edit]));
+            }
+            best = std::cmp::min(best, left);
+        }
+        candidates.push((best, edits));
+    }
+    candidates.sort_by_key(|(best, _)| *best);
+    let candidates: Vec<Vec<Edit>> = candidates
+        .into_iter()
+        .take(max_candidates)
+        .map(|(_, edits)| edits)
+        .collect();
+
+    // Iterative deepening, so the first fix found is a smallest one.
+    let solves = |solution: &Solution| Ok(cells_left(solution)? == 0);
+    for size in 2..=max_edits {
+        let mut chosen = vec![];
+        if let Some(edits) = search(solution, &candidates, 0, size, &mut chosen, &solves)? {
+            return Ok(Some(edits));
+        }
+    }
+    Ok(None)
+}
+
+/// Tries every way of adding `remaining` more edits (from `candidates[start..]`) to `chosen`.
+fn search(
+    solution: &Solution,
+    candidates: &[Vec<Edit>],
+    start: usize,
+    remaining: usize,
+    chosen: &mut Vec<Edit>,
+    solves: &impl Fn(&Solution) -> Result<bool>,
+) -> Result<Option<Vec<Edit>>> {
+    if remaining == 0 {
+        let mut candidate = solution.clone();
+        apply_edits(&mut candidate, chosen);
+        return Ok(if solves(&candidate)? {
+            Some(chosen.clone())
+        } else {
+            None
+        });
+    }
+    for i in start..candidates.len() {
+        for edit in &candidates[i] {
+            chosen.push(*edit);
+            let found = search(solution, candidates, i + 1, remaining - 1, chosen, solves)?;
+            chosen.pop();
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// What `tune` aims for. Difficulty is measured by the solver's skims and scrubs; at least one
+/// target should be given.
+pub struct TuneOptions {
+    pub target_skims: Option<usize>,
+    pub target_scrubs: Option<usize>,
+    /// Only cells within this distance of the edge of the picture (where the background meets
+    /// anything else) may change, so that the silhouette stays recognizable. With 0, only the
+    /// cells on either side of the edge may.
+    pub tolerance: usize,
+    pub iterations: usize,
+    /// Solver time limit for each iteration.
+    pub timeout: Option<Duration>,
+}
+
+/// The best solution `tune` found.
+pub struct Tuned {
+    pub solution: Solution,
+    /// Relative to the original solution.
+    pub edits: Vec<Edit>,
+    pub report: Report,
+}
+
+/// Nudges the solution's difficulty toward the targets by simulated annealing over single-cell
+/// edits, keeping the puzzle line-solvable. Returns `None` if the original isn't line-solvable, and
+/// fails if no cell is close enough to an edge to change.
+pub fn tune(
+    rng: &mut impl Rng,
+    original: &Solution,
+    options: &TuneOptions,
+) -> Result<Option<Tuned>> {
+    let solve = |solution: &Solution| {
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        grid_solve::solve(&import::solution_to_puzzle(solution)?, false, deadline)
+    };
+    // Unsolvable puzzles are far worse than any amount of missing the target.
+    let energy = |report: &Report| {
+        let miss = |target: Option<usize>, actual: usize| target.map_or(0, |t| t.abs_diff(actual));
+        if report.cells_left > 0 {
+            return f64::INFINITY;
+        }
+        (miss(options.target_skims, report.skims) + miss(options.target_scrubs, report.scrubs))
+            as f64
+    };
+
+    let report = solve(original)?;
+    if report.cells_left > 0 {
+        return Ok(None);
+    }
+
+    let mutable = edge_cells(original, options.tolerance);
+    if mutable.is_empty() {
+        return Err(Error::Unsupported(
+            "no cells are near an edge of the picture, so there's nothing to change".to_string(),
+        ));
+    }
+    let mut current = (original.clone(), energy(&report));
+    let mut best = (original.clone(), report, current.1);
+
+    for iteration in 0..options.iterations {
+        if best.2 == 0.0 {
+            break;
+        }
+        // Cools linearly from accepting a step 2 worse about a third of the time, to greedy.
+        let temperature = 2.0 * (1.0 - iteration as f64 / options.iterations as f64);
+
+        let (x, y) = mutable[rng.gen_range(0..mutable.len())];
+        let mut candidate = current.0.clone();
+        candidate.grid[x][y] = if candidate.grid[x][y] == BACKGROUND {
+            neighboring_color(&candidate, x, y)
+        } else {
+            BACKGROUND
+        };
+        let candidate_report = solve(&candidate)?;
+        let candidate_energy = energy(&candidate_report);
+        if !candidate_energy.is_finite() {
+            continue;
+        }
+
+        let delta = candidate_energy - current.1;
+        if delta <= 0.0 || (temperature > 0.0 && rng.gen_bool((-delta / temperature).exp())) {
+            if candidate_energy < best.2 {
+                best = (candidate.clone(), candidate_report, candidate_energy);
+            }
+            current = (candidate, candidate_energy);
+        }
+    }
+
+    // The random walk picks up edits that don't matter; undo any that can be undone without
+    // getting further from the target.
+    let (mut solution, mut report, best_energy) = best;
+    for edit in diff(original, &solution) {
+        let mut candidate = solution.clone();
+        candidate.grid[edit.x][edit.y] = edit.from;
+        let candidate_report = solve(&candidate)?;
+        if energy(&candidate_report) <= best_energy {
+            solution = candidate;
+            report = candidate_report;
+        }
+    }
+
+    Ok(Some(Tuned {
+        edits: diff(original, &solution),
+        solution,
+        report,
+    }))
+}
+
+/// Cells within `tolerance` (in both directions) of a cell whose neighbors differ in whether
+/// they're background. With a `tolerance` of 0, cells with such a neighbor (above, below, or to
+/// either side).
+fn edge_cells(solution: &Solution, tolerance: usize) -> Vec<(usize, usize)> {
+    let width = solution.grid.len();
+    let height = solution.grid.first().map_or(0, Vec::len);
+    let is_bg = |x: usize, y: usize| solution.grid[x][y] == BACKGROUND;
+
+    if tolerance == 0 {
+        let on_edge = |x: usize, y: usize| {
+            let neighbors = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+            neighbors
+                .iter()
+                .any(|&(nx, ny)| nx < width && ny < height && is_bg(nx, ny) != is_bg(x, y))
+        };
+        return (0..width)
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .filter(|&(x, y)| !solution.void.contains(&(x, y)) && on_edge(x, y))
+            .collect();
+    }
+
+    let mut res = vec![];
+    for x in 0..width {
+        for y in 0..height {
+            if solution.void.contains(&(x, y)) {
+                continue; // Not part of the picture, so not ours to change.
+            }
+            let (x_lo, x_hi) = (
+                x.saturating_sub(tolerance),
+                std::cmp::min(x + tolerance, width - 1),
+            );
+            let (y_lo, y_hi) = (
+                y.saturating_sub(tolerance),
+                std::cmp::min(y + tolerance, height - 1),
+            );
+            let mut seen_bg = false;
+            let mut seen_fg = false;
+            for nx in x_lo..=x_hi {
+                for ny in y_lo..=y_hi {
+                    if is_bg(nx, ny) {
+                        seen_bg = true;
+                    } else {
+                        seen_fg = true;
+                    }
+                }
+            }
+            if seen_bg && seen_fg {
+                res.push((x, y));
+            }
+        }
+    }
+    res
+}
+
+/// The edits that turn `from` into `to` (which must be the same size).
+pub fn diff(from: &Solution, to: &Solution) -> Vec<Edit> {
+    let mut res = vec![];
+    for (x, (from_col, to_col)) in from.grid.iter().zip(&to.grid).enumerate() {
+        for (y, (from, to)) in from_col.iter().zip(to_col).enumerate() {
+            if from != to {
+                res.push(Edit {
+                    x,
+                    y,
+                    from: *from,
+                    to: *to,
+                });
+            }
+        }
+    }
+    res.sort_by_key(|edit| (edit.y, edit.x));
+    res
+}
+
+/// Composites the image onto white, so that transparency doesn't turn into extra colors.
+fn flatten(image: &DynamicImage) -> DynamicImage {
+    let mut res = image.to_rgba8();
+    for pixel in res.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for i in 0..3 {
+            pixel[i] = ((pixel[i] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+    DynamicImage::ImageRgba8(res)
+}
+
+/// Scales `width` by `height` to fit in a `size`-by-`size` square, keeping the aspect ratio.
+pub fn fit(width: u32, height: u32, size: u32) -> (u32, u32) {
+    let longest = std::cmp::max(std::cmp::max(width, height), 1) as f64;
+    let scale = |side: u32| std::cmp::max((side as f64 * size as f64 / longest).round() as u32, 1);
+    (scale(width), scale(height))
+}
+
+/// Draws `ch` from the font (a TrueType or OpenType file) in black on white, cropped to the glyph
+/// and scaled so that its longer side is `size` pixels. `None` if the font has no glyph for `ch`,
+/// or the glyph is blank (like a space's).
+pub fn glyph_image(font: &[u8], ch: char, size: u32) -> Result<Option<DynamicImage>> {
+    use resvg::tiny_skia;
+
+    struct PathBuilder(tiny_skia::PathBuilder);
+    impl ttf_parser::OutlineBuilder for PathBuilder {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.0.move_to(x, y);
+        }
+        fn line_to(&mut self, x: f32, y: f32) {
+            self.0.line_to(x, y);
+        }
+        fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+            self.0.quad_to(x1, y1, x, y);
+        }
+        fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+            self.0.cubic_to(x1, y1, x2, y2, x, y);
+        }
+        fn close(&mut self) {
+            self.0.close();
+        }
+    }
+
+    let face = ttf_parser::Face::parse(font, 0)
+        .map_err(|e| Error::parse(format!("not a usable font: {}", e)))?;
+    let glyph = match face.glyph_index(ch) {
+        Some(glyph) => glyph,
+        None => return Ok(None),
+    };
+    let mut builder = PathBuilder(tiny_skia::PathBuilder::new());
+    let bbox = match face.outline_glyph(glyph, &mut builder) {
+        Some(bbox) => bbox,
+        None => return Ok(None),
+    };
+    let path = match builder.0.finish() {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let (glyph_width, glyph_height) = (bbox.width() as u32, bbox.height() as u32);
+    let (width, height) = fit(glyph_width, glyph_height, size);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| Error::Unsupported(format!("can't draw at {}x{}", width, height)))?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+
+    // Font units have y going up.
+    let scale_x = width as f32 / std::cmp::max(glyph_width, 1) as f32;
+    let scale_y = height as f32 / std::cmp::max(glyph_height, 1) as f32;
+    let transform = tiny_skia::Transform::from_row(
+        scale_x,
+        0.0,
+        0.0,
+        -scale_y,
+        -(bbox.x_min as f32) * scale_x,
+        bbox.y_max as f32 * scale_y,
+    );
+    let mut paint = tiny_skia::Paint::default();
+    paint.set_color(tiny_skia::Color::BLACK);
+    paint.anti_alias = false;
+    pixmap.fill_path(&path, &paint, tiny_skia::FillRule::Winding, transform, None);
+
+    let mut image = image::RgbaImage::new(width, height);
+    for (pixel, drawn) in image.pixels_mut().zip(pixmap.pixels()) {
+        *pixel = image::Rgba([drawn.red(), drawn.green(), drawn.blue(), 255]);
+    }
+    Ok(Some(DynamicImage::ImageRgba8(image)))
+}
+
+#[cfg(test)]
+fn test_solution(grid: &str) -> Solution {
+    import::char_grid_to_solution(grid, true, &std::collections::HashSet::new()).unwrap()
+}
+
+#[cfg(test)]
+fn cells_left(solution: &Solution) -> usize {
+    let puzzle = import::solution_to_puzzle(solution).unwrap();
+    grid_solve::solve(&puzzle, false, None).unwrap().cells_left
+}
+
+#[test]
+fn minimal_repair_test() {
+    let solvable = test_solution("##..\n.#..\n....\n....\n");
+    assert_eq!(
+        minimal_repair(&solvable, 2, 10, None).unwrap(),
+        Some(vec![])
+    );
+
+    // The two diagonals of the top-left corner have the same clues.
+    let ambiguous = test_solution("#...\n.#..\n....\n....\n");
+    assert!(cells_left(&ambiguous) > 0);
+    let edits = minimal_repair(&ambiguous, 2, 10, None).unwrap().unwrap();
+    assert_eq!(edits.len(), 1);
+    let mut repaired = ambiguous.clone();
+    apply_edits(&mut repaired, &edits);
+    assert_eq!(cells_left(&repaired), 0);
+    assert_eq!(diff(&ambiguous, &repaired), edits);
+
+    assert_eq!(minimal_repair(&ambiguous, 0, 10, None).unwrap(), None);
+}
+
+#[test]
+fn tune_test() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let original = test_solution("......\n.####.\n.####.\n.####.\n..##..\n......\n");
+    assert_eq!(cells_left(&original), 0);
+    let options = TuneOptions {
+        target_skims: Some(30),
+        target_scrubs: None,
+        tolerance: 1,
+        iterations: 40,
+        timeout: None,
+    };
+    let run = |seed: u64| tune(&mut StdRng::seed_from_u64(seed), &original, &options).unwrap();
+
+    let first = run(7).unwrap();
+    let again = run(7).unwrap();
+    assert_eq!(first.edits, again.edits);
+    assert_eq!(first.report.skims, again.report.skims);
+    assert_eq!(first.report.cells_left, 0);
+    assert_eq!(diff(&original, &first.solution), first.edits);
+
+    // A tolerance of 0 still leaves the cells right at the edge.
+    let edge = edge_cells(&original, 0);
+    assert!(edge.contains(&(1, 1)) && edge.contains(&(0, 1)));
+    assert!(!edge.contains(&(2, 2)) && !edge.contains(&(5, 0)));
+    // But a blank picture has no edges at all.
+    let mut blank = original.clone();
+    for col in &mut blank.grid {
+        col.fill(BACKGROUND);
+    }
+    assert!(edge_cells(&blank, 3).is_empty());
+    assert!(tune(&mut StdRng::seed_from_u64(7), &blank, &options).is_err());
+
+    // Only ambiguous pictures are left alone.
+    let ambiguous = test_solution("#...\n.#..\n....\n....\n");
+    assert!(tune(&mut StdRng::seed_from_u64(7), &ambiguous, &options)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn generate_test() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let options = GenOptions {
+        width: 8,
+        height: 8,
+        colors: 1,
+        density: 0.6,
+        blob_size: 1,
+        color_weights: vec![],
+        skims: 10..=40,
+        scrubs: 0..=0,
+        attempts: 200,
+        timeout: None,
+    };
+    let generated = generate(&mut StdRng::seed_from_u64(3), &options)
+        .unwrap()
+        .unwrap();
+    assert_eq!(generated.report.cells_left, 0);
+    assert!(options.skims.contains(&generated.report.skims));
+    assert_eq!(generated.report.scrubs, 0);
+    assert_eq!(generated.solution.grid.len(), 8);
+
+    // Impossible to satisfy, so it gives up.
+    let impossible = GenOptions {
+        skims: 0..=0,
+        attempts: 5,
+        ..options
+    };
+    assert!(generate(&mut StdRng::seed_from_u64(3), &impossible)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn photo_to_solution_test() {
+    assert_eq!(fit(200, 100, 20), (20, 10));
+    assert_eq!(fit(1, 1000, 10), (1, 10));
+
+    let photo = image::RgbaImage::from_fn(40, 20, |x, _| {
+        let v = (x * 6) as u8;
+        image::Rgba([v, v, 255 - v, 255])
+    });
+    let options = PhotoOptions {
+        width: 10,
+        height: None,
+        colors: 3,
+        dither: false,
+    };
+    let solution = photo_to_solution(&DynamicImage::ImageRgba8(photo), &options).unwrap();
+    assert_eq!(solution.grid.len(), 10);
+    assert_eq!(solution.grid[0].len(), 5);
+    assert!(solution.palette.len() <= 3);
+}
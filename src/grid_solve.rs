@@ -1,18 +1,66 @@
-use anyhow::bail;
+//! Solving a whole puzzle by repeatedly applying line logic to whichever lane looks most
+//! promising, keeping track of how much work it took.
+
+use std::time::Instant;
+
 use colored::Colorize;
 use ndarray::{ArrayView1, ArrayViewMut1};
 
 use crate::{
+    error::{Error, Lane, Result},
     line_solve::{scrub_heuristic, scrub_line, skim_heuristic, skim_line, Cell},
-    puzzle::{Clue, Puzzle},
+    puzzle::{Clue, Puzzle, Solution, BACKGROUND},
 };
 
-type Grid = ndarray::Array2<Cell>;
+pub type Grid = ndarray::Array2<Cell>;
+
+pub struct Report {
+    pub skims: usize,
+    pub scrubs: usize,
+    /// Cells that line logic couldn't determine; 0 if the puzzle was solved.
+    pub cells_left: usize,
+    /// The solver gave up at the deadline, so `grid` is only partially solved.
+    pub timed_out: bool,
+    pub grid: Grid,
+    /// Rows first, then columns.
+    pub lanes: Vec<LaneStats>,
+}
 
-pub struct Report {}
+impl Report {
+    /// The picture the solver found, if it solved the whole puzzle.
+    pub fn solution(&self, puzzle: &Puzzle) -> Option<Solution> {
+        if self.cells_left > 0 {
+            return None;
+        }
+        let (height, width) = self.grid.dim();
+        Some(Solution {
+            palette: puzzle.palette.clone(),
+            grid: (0..width)
+                .map(|x| {
+                    (0..height)
+                        .map(|y| self.grid[[y, x]].unwrap_color())
+                        .collect()
+                })
+                .collect(),
+            void: puzzle.void.clone(),
+        })
+    }
+}
 
+/// How much work went into one lane.
+#[derive(Clone, Debug)]
+pub struct LaneStats {
+    pub row: bool,
+    pub index: usize,
+    pub skims: usize,
+    pub scrubs: usize,
+    pub cells_determined: usize,
+}
+
+#[derive(Clone)]
 pub struct LaneState<'a> {
     clues: &'a [Clue], // just convenience, since `row` and `index` suffice to find it again
+    separate_blocks: bool,
     row: bool,
     index: ndarray::Ix,
     scrubbed: bool,
@@ -21,12 +69,19 @@ pub struct LaneState<'a> {
     skimmed: bool,
     skim_score: i32,
     processed_skim_score: i32,
+    stats: LaneStats,
 }
 
 impl<'a> LaneState<'a> {
-    fn new(clues: &'a [Clue], row: bool, idx: usize, grid: &Grid) -> LaneState<'a> {
+    fn new(puzzle: &'a Puzzle, row: bool, idx: usize, grid: &Grid) -> LaneState<'a> {
+        let clues = if row {
+            &puzzle.rows[idx]
+        } else {
+            &puzzle.cols[idx]
+        };
         let mut res = LaneState {
             clues,
+            separate_blocks: puzzle.separate_blocks,
             row,
             index: idx,
             scrubbed: false,
@@ -35,6 +90,13 @@ impl<'a> LaneState<'a> {
             skimmed: false,
             skim_score: 0,
             processed_skim_score: 0,
+            stats: LaneStats {
+                row,
+                index: idx,
+                skims: 0,
+                scrubs: 0,
+                cells_determined: 0,
+            },
         };
         res.rescore(grid, false);
         res
@@ -50,7 +112,7 @@ impl<'a> LaneState<'a> {
             self.processed_scrub_score = self.scrub_score;
             self.processed_skim_score = self.skim_score;
         }
-        self.scrub_score = scrub_heuristic(self.clues, lane);
+        self.scrub_score = scrub_heuristic(self.clues, self.separate_blocks, lane);
         self.skim_score = skim_heuristic(self.clues, lane);
     }
 
@@ -107,20 +169,44 @@ fn find_best_lane<'a, 'b>(
     res
 }
 
-fn print_grid(grid: &Grid, puzzle: &Puzzle) {
-    for row in grid.rows() {
-        for cell in row {
-            match cell.known_or() {
-                None => {
-                    print!("?");
-                }
-                Some(c) => {
-                    print!("{}", puzzle.palette[&c].ch);
-                }
-            }
-        }
-        println!();
+/// The cells of one lane, as characters, with `?` for the ones that aren't known yet.
+pub fn lane_text(grid: &Grid, puzzle: &Puzzle, lane: Lane) -> String {
+    let cells = if lane.row {
+        grid.row(lane.index)
+    } else {
+        grid.column(lane.index)
+    };
+    cells
+        .iter()
+        .map(|cell| match cell.known_or() {
+            None => '?',
+            Some(c) => puzzle.palette[&c].ch,
+        })
+        .collect()
+}
+
+/// The grid as `print_grid` shows it.
+pub fn grid_text(grid: &Grid, puzzle: &Puzzle) -> String {
+    let mut res = String::new();
+    for index in 0..grid.nrows() {
+        res.push_str(&lane_text(grid, puzzle, Lane { row: true, index }));
+        res.push('\n');
     }
+    res
+}
+
+pub fn print_grid(grid: &Grid, puzzle: &Puzzle) {
+    print!("{}", grid_text(grid, puzzle));
+}
+
+/// How `set_trace` lays out each step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TraceLayout {
+    /// Wrap lanes that don't fit in this many columns onto continuation lines, lined up with the
+    /// start of the lane.
+    pub width: Option<usize>,
+    /// Print only the part of the lane from the first change to the last, with its position.
+    pub changed_only: bool,
 }
 
 fn display_step<'a>(
@@ -129,152 +215,361 @@ fn display_step<'a>(
     scrub: bool,
     grid: &'a Grid,
     puzzle: &'a Puzzle,
+    layout: TraceLayout,
 ) {
     use std::fmt::Write;
     let mut clues = String::new();
 
     for clue in clue_lane.clues {
-        write!(clues, "{}{} ", clue.count, puzzle.palette[&clue.color].ch).unwrap();
+        write!(
+            clues,
+            "{}{} ",
+            clue.count_text(),
+            puzzle.palette[&clue.color].ch
+        )
+        .unwrap();
     }
 
-    let r_or_c = if clue_lane.row { "R" } else { "C" };
-
-    print!("{}{: <3} {: >16}", r_or_c, clue_lane.index, clues);
+    let lane = Lane {
+        row: clue_lane.row,
+        index: clue_lane.index,
+    };
+    let mut prefix = format!("{: <4} {: >16}", lane.short_name(), clues);
 
     if scrub {
-        print!(" ! ");
+        prefix.push_str(" ! ");
     } else {
-        print!(" | ");
+        prefix.push_str(" | ");
     }
 
-    for (orig, now) in orig_lane.iter().zip(get_grid_lane(clue_lane, grid)) {
-        let new_ch = match now.known_or() {
-            None => "?".to_string(),
-            Some(known_color) => puzzle.palette[&known_color].ch.to_string(),
-        };
-
-        if *orig != *now {
-            print!("{}", new_ch.underline());
-        } else {
-            print!("{}", new_ch);
+    let cells: Vec<(String, bool)> = orig_lane
+        .iter()
+        .zip(get_grid_lane(clue_lane, grid))
+        .map(|(orig, now)| {
+            let new_ch = match now.known_or() {
+                None => "?".to_string(),
+                Some(known_color) => puzzle.palette[&known_color].ch.to_string(),
+            };
+            (new_ch, *orig != *now)
+        })
+        .collect();
+
+    let mut shown = 0..cells.len();
+    if layout.changed_only {
+        let first = cells.iter().position(|(_, changed)| *changed);
+        let last = cells.iter().rposition(|(_, changed)| *changed);
+        if let (Some(first), Some(last)) = (first, last) {
+            shown = first..last + 1;
+            // 1-based, like the rest of the user-facing output.
+            write!(prefix, "@{} ", first + 1).unwrap();
         }
     }
 
+    // Keep at least a little of the lane on each line, however narrow the terminal is.
+    let indent = prefix.chars().count();
+    let per_line = layout.width.map_or(usize::MAX, |width| {
+        std::cmp::max(width.saturating_sub(indent), 10)
+    });
     // Hackish way of getting the original score...
-    if scrub {
-        let lane_arr: ndarray::Array1<Cell> = orig_lane.into();
-        let orig_score =
-            scrub_heuristic(clue_lane.clues, lane_arr.rows().into_iter().next().unwrap());
-        println!("   {}->{}", orig_score, clue_lane.scrub_score);
+    let lane_arr: ndarray::Array1<Cell> = orig_lane.into();
+    let orig_lane = lane_arr.rows().into_iter().next().unwrap();
+    let score = if scrub {
+        let orig_score = scrub_heuristic(clue_lane.clues, clue_lane.separate_blocks, orig_lane);
+        format!("   {}->{}", orig_score, clue_lane.scrub_score)
     } else {
-        let lane_arr: ndarray::Array1<Cell> = orig_lane.into();
-        let orig_score =
-            skim_heuristic(clue_lane.clues, lane_arr.rows().into_iter().next().unwrap());
-        println!("   {}->{}", orig_score, clue_lane.skim_score);
+        let orig_score = skim_heuristic(clue_lane.clues, orig_lane);
+        format!("   {}->{}", orig_score, clue_lane.skim_score)
+    };
+
+    // Without color, there's no underlining, so changes get marked on a line of their own.
+    let plain = !colored::control::SHOULD_COLORIZE.should_colorize();
+    print!("{}", prefix);
+    let chunks: Vec<_> = cells[shown].chunks(per_line).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        if i > 0 {
+            print!("\n{}", " ".repeat(indent));
+        }
+        for (ch, changed) in chunk.iter() {
+            if *changed && !plain {
+                print!("{}", ch.underline());
+            } else {
+                print!("{}", ch);
+            }
+        }
+        if i + 1 == chunks.len() {
+            print!("{}", score);
+        }
+        if plain && chunk.iter().any(|(_, changed)| *changed) {
+            let marks: String = chunk
+                .iter()
+                .map(|(_, changed)| if *changed { '^' } else { ' ' })
+                .collect();
+            print!("\n{}{}", " ".repeat(indent), marks.trim_end());
+        }
     }
+    println!();
+}
+
+/// The two ways of working on a lane: skimming is quick and shallow; scrubbing is slow and
+/// thorough.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Technique {
+    Skim,
+    Scrub,
+}
+
+/// One unit of work done by a `Solver`.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct SolveStep {
+    pub lane: Lane,
+    pub technique: Technique,
+    pub cells_determined: usize,
+}
+
+/// Solves a puzzle one lane at a time. Iterating yields each step as it's taken, so callers can
+/// pause, render `grid()`, or stop between steps; it ends when the puzzle is solved or line logic
+/// runs out of ideas. `solve` is the all-at-once version. Cloning it is a cheap way to be able to
+/// go back to an earlier point.
+#[derive(Clone)]
+pub struct Solver<'a> {
+    puzzle: &'a Puzzle,
+    grid: Grid,
+    lanes: Vec<LaneState<'a>>,
+    cells_left: usize,
+    skims: usize,
+    scrubs: usize,
+    allowed_skims: i32,
+    done: bool,
+    trace_solve: bool,
+    trace_layout: TraceLayout,
 }
 
-pub fn solve(puzzle: &Puzzle, trace_solve: bool) -> anyhow::Result<Report> {
-    let mut grid = Grid::from_elem((puzzle.rows.len(), puzzle.cols.len()), Cell::new(puzzle));
+impl<'a> Solver<'a> {
+    pub fn new(puzzle: &'a Puzzle) -> Solver<'a> {
+        let mut grid = Grid::from_elem((puzzle.rows.len(), puzzle.cols.len()), Cell::new(puzzle));
+        let mut void_cells = 0;
+        for &(x, y) in &puzzle.void {
+            if let Some(cell) = grid.get_mut([y, x]) {
+                *cell = Cell::from_color(BACKGROUND);
+                void_cells += 1;
+            }
+        }
 
-    let mut solve_lanes = vec![];
+        let mut lanes = vec![];
+        for idx in 0..puzzle.rows.len() {
+            lanes.push(LaneState::new(puzzle, true, idx, &grid));
+        }
+        for idx in 0..puzzle.cols.len() {
+            lanes.push(LaneState::new(puzzle, false, idx, &grid));
+        }
+
+        Solver {
+            puzzle,
+            grid,
+            lanes,
+            cells_left: puzzle.rows.len() * puzzle.cols.len() - void_cells,
+            skims: 0,
+            scrubs: 0,
+            allowed_skims: 10,
+            done: false,
+            trace_solve: false,
+            trace_layout: TraceLayout::default(),
+        }
+    }
+
+    /// Print each step, line-by-line, as it's taken.
+    pub fn set_trace(&mut self, trace_solve: bool) {
+        self.trace_solve = trace_solve;
+    }
 
-    for (idx, clue_row) in puzzle.rows.iter().enumerate() {
-        solve_lanes.push(LaneState::new(clue_row, true, idx, &grid));
+    pub fn set_trace_layout(&mut self, trace_layout: TraceLayout) {
+        self.trace_layout = trace_layout;
     }
 
-    for (idx, clue_col) in puzzle.cols.iter().enumerate() {
-        solve_lanes.push(LaneState::new(clue_col, false, idx, &grid));
+    /// The cells as known so far.
+    pub fn grid(&self) -> &Grid {
+        &self.grid
     }
 
-    let mut cells_left = puzzle.rows.len() * puzzle.cols.len();
-    let mut skims = 0;
-    let mut scrubs = 0;
+    pub fn cells_left(&self) -> usize {
+        self.cells_left
+    }
+
+    /// Summarizes the work done so far.
+    pub fn into_report(self) -> Report {
+        Report {
+            skims: self.skims,
+            scrubs: self.scrubs,
+            cells_left: self.cells_left,
+            timed_out: false,
+            grid: self.grid,
+            lanes: self.lanes.into_iter().map(|ls| ls.stats).collect(),
+        }
+    }
+
+    /// Takes steps until there's nothing left to learn, or `deadline` passes.
+    pub fn run(self, deadline: Option<Instant>) -> Result<Report> {
+        self.run_with(deadline, |_, _| {})
+    }
+
+    /// Like `run`, but calls `on_step` after each step, with the solver as it is after the step.
+    pub fn run_with(
+        mut self,
+        deadline: Option<Instant>,
+        mut on_step: impl FnMut(&SolveStep, &Solver),
+    ) -> Result<Report> {
+        let mut timed_out = false;
+        loop {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                timed_out = true;
+                break;
+            }
+            match self.next() {
+                Some(step) => on_step(&step?, &self),
+                None => break,
+            }
+        }
+
+        Ok(Report {
+            timed_out,
+            ..self.into_report()
+        })
+    }
 
-    let mut allowed_skims = 10;
-    loop {
-        let will_scrub = allowed_skims == 0;
+    fn step(&mut self) -> Result<Option<SolveStep>> {
+        loop {
+            let will_scrub = self.allowed_skims == 0;
 
-        let (report, was_row) = {
-            let best_clue_lane = match find_best_lane(&mut solve_lanes, will_scrub) {
+            let best_clue_lane = match find_best_lane(&mut self.lanes, will_scrub) {
                 Some(lane) => lane,
                 None => {
                     if will_scrub {
-                        print_grid(&grid, puzzle);
-                        bail!("Cannot solve: {} cells left", cells_left);
+                        return Ok(None); // Out of ideas
                     } else {
-                        print!("=>!! ");
-                        allowed_skims = 0; // Try again, but scrub.
+                        if self.trace_solve {
+                            print!("=>!! ");
+                        }
+                        self.allowed_skims = 0; // Try again, but scrub.
                         continue;
                     }
                 }
             };
 
-            let best_grid_lane = get_mut_grid_lane(best_clue_lane, &mut grid);
+            let best_grid_lane = get_mut_grid_lane(best_clue_lane, &mut self.grid);
             let orig_version_of_line: Vec<Cell> = best_grid_lane.iter().cloned().collect();
 
+            let lane = Lane {
+                row: best_clue_lane.row,
+                index: best_clue_lane.index,
+            };
+            let contradiction = move |e: anyhow::Error| Error::Contradiction {
+                lane,
+                message: format!("{:#}", e),
+            };
             let report = if will_scrub {
                 best_clue_lane.scrubbed = true;
-                scrubs += 1;
-                scrub_line(best_clue_lane.clues, best_grid_lane)?
+                best_clue_lane.stats.scrubs += 1;
+                self.scrubs += 1;
+                scrub_line(
+                    best_clue_lane.clues,
+                    best_clue_lane.separate_blocks,
+                    best_grid_lane,
+                )
+                .map_err(contradiction)?
             } else {
                 best_clue_lane.skimmed = true;
-                skims += 1;
-                skim_line(best_clue_lane.clues, best_grid_lane)?
+                best_clue_lane.stats.skims += 1;
+                self.skims += 1;
+                skim_line(
+                    best_clue_lane.clues,
+                    best_clue_lane.separate_blocks,
+                    best_grid_lane,
+                )
+                .map_err(contradiction)?
             };
 
-            best_clue_lane.rescore(&grid, /*was_processed=*/ true);
+            best_clue_lane.rescore(&self.grid, /*was_processed=*/ true);
 
             // TODO: there's got to be a simpler way than calling `get_mut_grid_lane` again.
             // Maybe just have `skim`/`scrub` report the difference directly
             let known_before = orig_version_of_line.iter().filter(|c| c.is_known()).count();
-            let known_after = get_mut_grid_lane(best_clue_lane, &mut grid)
+            let known_after = get_mut_grid_lane(best_clue_lane, &mut self.grid)
                 .iter()
                 .filter(|c| c.is_known())
                 .count();
 
-            cells_left -= known_after - known_before;
+            self.cells_left -= known_after - known_before;
+            best_clue_lane.stats.cells_determined += known_after - known_before;
 
-            if trace_solve {
+            if self.trace_solve {
                 display_step(
                     best_clue_lane,
                     orig_version_of_line,
                     will_scrub,
-                    &grid,
-                    puzzle,
+                    &self.grid,
+                    self.puzzle,
+                    self.trace_layout,
                 );
             }
 
-            (report, best_clue_lane.row)
-        };
+            let step = SolveStep {
+                lane,
+                technique: if will_scrub {
+                    Technique::Scrub
+                } else {
+                    Technique::Skim
+                },
+                cells_determined: known_after - known_before,
+            };
 
-        if cells_left == 0 {
-            println!();
-            println!("Solved in {skims} skims, {scrubs} scrubs.");
-            break;
-        }
+            if self.cells_left == 0 {
+                self.done = true;
+                return Ok(Some(step));
+            }
 
-        if will_scrub {
-            if !report.affected_cells.is_empty() {
-                allowed_skims = 10;
+            if will_scrub {
+                if !report.affected_cells.is_empty() {
+                    self.allowed_skims = 10;
+                }
+            } else if report.affected_cells.is_empty() {
+                self.allowed_skims -= 1;
+            } else {
+                self.allowed_skims = std::cmp::max(10, self.allowed_skims + 1);
             }
-        } else if report.affected_cells.is_empty() {
-            allowed_skims -= 1;
-        } else {
-            allowed_skims = std::cmp::max(10, allowed_skims + 1);
-        }
 
-        // Affected intersecting lanes now may need to be re-examined:
-        for other_lane in solve_lanes.iter_mut() {
-            if other_lane.row != was_row && report.affected_cells.contains(&other_lane.index) {
-                other_lane.rescore(&grid, /*was_processed=*/ false);
-                other_lane.skimmed = false;
-                other_lane.scrubbed = false;
+            // Affected intersecting lanes now may need to be re-examined:
+            for other_lane in self.lanes.iter_mut() {
+                if other_lane.row != lane.row && report.affected_cells.contains(&other_lane.index) {
+                    other_lane.rescore(&self.grid, /*was_processed=*/ false);
+                    other_lane.skimmed = false;
+                    other_lane.scrubbed = false;
+                }
             }
+
+            return Ok(Some(step));
         }
     }
+}
+
+impl Iterator for Solver<'_> {
+    type Item = Result<SolveStep>;
 
-    print_grid(&grid, puzzle);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = self.step().transpose();
+        if !matches!(res, Some(Ok(_))) {
+            self.done = true;
+        }
+        res
+    }
+}
 
-    Ok(Report {})
+/// If `deadline` passes, gives up and returns what it has so far.
+pub fn solve(puzzle: &Puzzle, trace_solve: bool, deadline: Option<Instant>) -> Result<Report> {
+    let mut solver = Solver::new(puzzle);
+    solver.set_trace(trace_solve);
+    solver.run(deadline)
 }
@@ -1,16 +1,36 @@
-use anyhow::bail;
+//! Reading puzzles and solutions in from various formats, and cleaning them up.
+
 use image::{DynamicImage, GenericImageView, Pixel, Rgba};
 use std::{
     char::from_digit,
-    collections::{BTreeMap, BTreeSet, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 };
 
-use puzzle::Clue;
+use puzzle::{Clue, Length};
 
-use crate::puzzle::{self, Color, ColorInfo, Puzzle, Solution, BACKGROUND};
+use crate::puzzle::{
+    self, Color, ColorInfo, Document, PalettePreset, PresetColor, Puzzle, Solution, BACKGROUND,
+};
+use crate::{
+    error::{Error, Lane, Result},
+    verbosity,
+};
 
-pub fn image_to_solution(image: &DynamicImage) -> Solution {
+/// Fully transparent pixels are outside the puzzle's outline (see `Solution::void`).
+pub fn image_to_solution(image: &DynamicImage) -> Result<Solution> {
     let (width, height) = image.dimensions();
+    let is_void = |pixel: &Rgba<u8>| pixel.0[3] == 0;
+
+    // Check up front, since characters and color indices run out not long after this.
+    let mut distinct: HashSet<Rgba<u8>> = image
+        .pixels()
+        .map(|(_, _, pixel)| pixel)
+        .filter(|pixel| !is_void(pixel))
+        .collect();
+    distinct.insert(image::Rgba::<u8>([255, 255, 255, 255]));
+    if distinct.len() > 30 {
+        return Err(too_many_colors(distinct.len()));
+    }
 
     let mut palette = HashMap::<image::Rgba<u8>, ColorInfo>::new();
     let mut grid: Vec<Vec<Color>> = vec![vec![BACKGROUND; height as usize]; width as usize];
@@ -28,11 +48,16 @@ pub fn image_to_solution(image: &DynamicImage) -> Solution {
 
     let mut next_char = 'a';
     let mut next_color_idx: u8 = 1; // BACKGROUND is 0
+    let mut void = BTreeSet::new();
 
     // Gather the palette+
     for y in 0..height {
         for x in 0..width {
             let pixel: Rgba<u8> = image.get_pixel(x, y);
+            if is_void(&pixel) {
+                void.insert((x as usize, y as usize)); // `grid` is already `BACKGROUND` here
+                continue;
+            }
             let color = palette.entry(pixel).or_insert_with(|| {
                 let this_char = next_char;
                 let (r, g, b, _) = pixel.channels4();
@@ -53,16 +78,66 @@ pub fn image_to_solution(image: &DynamicImage) -> Solution {
         }
     }
 
-    Solution {
+    Ok(Solution {
         palette: palette
             .into_values()
             .map(|color_info| (color_info.color, color_info))
             .collect(),
         grid,
+        void,
+    })
+}
+
+/// Rasterizes an SVG at `width` by `height` pixels (one per cell), on a white background.
+/// Either dimension can be left out to keep the drawing's aspect ratio; with neither, the SVG's
+/// own size is used. Antialiasing is off, so that edges don't turn into extra colors.
+pub fn svg_to_image(svg: &[u8], width: Option<u32>, height: Option<u32>) -> Result<DynamicImage> {
+    use resvg::{tiny_skia, usvg};
+
+    let options = usvg::Options {
+        shape_rendering: usvg::ShapeRendering::CrispEdges,
+        ..usvg::Options::default()
+    };
+    let tree = usvg::Tree::from_data(svg, &options).map_err(|e| Error::parse(e.to_string()))?;
+    let size = tree.size();
+    let (width, height) = match (width, height) {
+        (Some(width), Some(height)) => (width, height),
+        (Some(width), None) => (
+            width,
+            (width as f32 * size.height() / size.width()).round() as u32,
+        ),
+        (None, Some(height)) => (
+            (height as f32 * size.width() / size.height()).round() as u32,
+            height,
+        ),
+        (None, None) => (size.width().round() as u32, size.height().round() as u32),
+    };
+    let mut pixmap = tiny_skia::Pixmap::new(std::cmp::max(width, 1), std::cmp::max(height, 1))
+        .ok_or_else(|| Error::Unsupported(format!("can't rasterize at {}x{}", width, height)))?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    let transform = tiny_skia::Transform::from_scale(
+        pixmap.width() as f32 / size.width(),
+        pixmap.height() as f32 / size.height(),
+    );
+    resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+    let mut image = image::RgbaImage::new(pixmap.width(), pixmap.height());
+    for (pixel, rendered) in image.pixels_mut().zip(pixmap.pixels()) {
+        // Opaque, since the background was filled in, so there's no need to demultiply.
+        *pixel = Rgba([rendered.red(), rendered.green(), rendered.blue(), 255]);
     }
+    Ok(DynamicImage::ImageRgba8(image))
 }
 
-pub fn char_grid_to_solution(char_grid: &str) -> Solution {
+/// With `strict`, it's an error for the rows to be different lengths, for the background character
+/// to be unguessable, or for a character to have no built-in color (unless it's in `known_chars`,
+/// which is for characters that a palette will give colors to). Otherwise, short rows are padded
+/// with background and unknown characters get arbitrary colors.
+pub fn char_grid_to_solution(
+    char_grid: &str,
+    strict: bool,
+    known_chars: &HashSet<char>,
+) -> Result<Solution> {
     let mut palette = HashMap::<char, ColorInfo>::new();
 
     // We want deterministic behavior
@@ -86,9 +161,18 @@ pub fn char_grid_to_solution(char_grid: &str) -> Solution {
     // But we need to *some* color as background to proceed!
     let bg_ch = match bg_ch {
         Some(x) => x,
+        None if strict => {
+            return Err(Error::parse(
+                "unable to guess which character is supposed to be the background",
+            ))
+        }
         None => {
-            eprintln!("convert-nonogram: Warning: unable to guess which character is supposed to be the background; using the upper-left corner");
-            char_grid.trim_start().chars().next().unwrap()
+            verbosity::warn("unable to guess which character is supposed to be the background; using the upper-left corner");
+            char_grid
+                .trim_start()
+                .chars()
+                .next()
+                .ok_or_else(|| Error::parse("the char grid is empty"))?
         }
     };
 
@@ -133,7 +217,19 @@ pub fn char_grid_to_solution(char_grid: &str) -> Solution {
     unused_colors.insert('c', (0, 255, 255));
     unused_colors.insert('m', (255, 0, 255));
 
+    // Check up front, since color indices run out not long after this.
+    if palette.len() + unused_chars.len() > 30 {
+        return Err(too_many_colors(palette.len() + unused_chars.len()));
+    }
+
     for ch in unused_chars {
+        if strict && !known_chars.contains(&ch) && !"rgbycm1234RGBYCM".contains(ch) {
+            let line = char_grid.lines().position(|line| line.contains(ch));
+            return Err(Error::parse_at(
+                line.map(|idx| idx + 1),
+                format!("no color for the character {:?}", ch),
+            ));
+        }
         if unused_colors.is_empty() {
             // If desperate, use grays and dark colors:
             for i in 1_u8..5_u8 {
@@ -163,53 +259,80 @@ pub fn char_grid_to_solution(char_grid: &str) -> Solution {
         next_color += 1;
     }
 
-    let mut grid: Vec<Vec<Color>> = vec![];
-
-    // TODO: check that rows are the same length!
-    for (y, row) in char_grid
+    let rows: Vec<&str> = char_grid
         .split("\n")
         .filter(|line| !line.is_empty())
-        .enumerate()
-    {
-        for (x, ch) in row.chars().enumerate() {
-            // There's probably a better way than this...
-            grid.resize(std::cmp::max(grid.len(), x + 1), vec![]);
-            let new_height = std::cmp::max(grid[x].len(), y + 1);
-            grid[x].resize(new_height, BACKGROUND);
+        .collect();
+    let width = rows
+        .iter()
+        .map(|row| row.chars().count())
+        .max()
+        .unwrap_or(0);
 
+    let mut grid: Vec<Vec<Color>> = vec![vec![BACKGROUND; rows.len()]; width];
+    let mut ragged = false;
+    for (y, row) in rows.iter().enumerate() {
+        let row_width = row.chars().count();
+        if row_width != width {
+            if strict {
+                return Err(Error::parse(format!(
+                    "row {} is {} characters wide, but the widest row is {}",
+                    y + 1,
+                    row_width,
+                    width
+                )));
+            }
+            ragged = true;
+        }
+        for (x, ch) in row.chars().enumerate() {
             grid[x][y] = palette[&ch].color;
         }
     }
+    if ragged {
+        verbosity::warn("the rows are different lengths; padding them with background");
+    }
 
-    Solution {
+    Ok(Solution {
         palette: palette
             .into_values()
             .map(|color_info| (color_info.color, color_info))
             .collect(),
         grid,
-    }
+        void: BTreeSet::new(),
+    })
+}
+
+/// The line `node` starts on, for error messages.
+fn line_of(node: roxmltree::Node) -> Option<usize> {
+    Some(node.document().text_pos_at(node.range().start).row as usize)
 }
 
 pub fn get_children<'a, 'input>(
     node: roxmltree::Node<'a, 'input>,
     tag: &str,
-) -> anyhow::Result<Vec<roxmltree::Node<'a, 'input>>> {
+) -> Result<Vec<roxmltree::Node<'a, 'input>>> {
     let mut res = vec![];
 
     for child in node.children() {
-        if child.is_text() {
-            if child.text().unwrap().trim() != "" {
-                bail!("unexpected text: {}", child.text().unwrap());
+        if let Some(text) = child.text().filter(|_| child.is_text()) {
+            if text.trim() != "" {
+                return Err(Error::parse_at(
+                    line_of(child),
+                    format!("unexpected text: {}", text.trim()),
+                ));
             }
         }
         if child.is_element() {
             if child.tag_name().name() == tag {
                 res.push(child);
             } else {
-                bail!(
-                    "unexpected element {}; was looking for {tag}",
-                    child.tag_name().name()
-                )
+                return Err(Error::parse_at(
+                    line_of(child),
+                    format!(
+                        "unexpected element {}; was looking for {tag}",
+                        child.tag_name().name()
+                    ),
+                ));
             }
         }
     }
@@ -220,25 +343,222 @@ pub fn get_children<'a, 'input>(
 pub fn get_single_child<'a, 'input>(
     node: roxmltree::Node<'a, 'input>,
     tag: &str,
-) -> anyhow::Result<roxmltree::Node<'a, 'input>> {
-    let mut res = get_children(node, tag)?;
-    if res.len() == 0 {
-        bail!("did not find the element {tag}");
+) -> Result<roxmltree::Node<'a, 'input>> {
+    match get_children(node, tag)?.as_slice() {
+        [] => Err(Error::parse_at(
+            line_of(node),
+            format!("did not find the element {tag}"),
+        )),
+        [child] => Ok(*child),
+        [_, second, ..] => Err(Error::parse_at(
+            line_of(*second),
+            format!("expected only one element named {tag}"),
+        )),
     }
-    if res.len() > 1 {
-        bail!("expected only one element named {tag}");
+}
+
+/// The ID, title, author, copyright (and license), and description of a webpbn puzzle, and solver
+/// stats and timestamps from its notes. (It has nowhere to put a difficulty.)
+pub fn webpbn_metadata(webpbn: &str) -> Result<puzzle::Metadata> {
+    let doc = roxmltree::Document::parse(webpbn)?;
+    let puzzle = get_single_child(doc.root_element(), "puzzle")?;
+    let text = |tag: &str| {
+        puzzle
+            .children()
+            .find(|node| node.has_tag_name(tag))
+            .and_then(|node| node.text())
+            .map(|text| text.trim().to_string())
+    };
+    let notes: Vec<&str> = puzzle
+        .children()
+        .filter(|node| node.has_tag_name("note"))
+        .filter_map(|node| node.text())
+        .collect();
+    let note = |prefix: &str| {
+        notes
+            .iter()
+            .find_map(|note| note.strip_prefix(prefix))
+            .map(|value| value.trim().to_string())
+    };
+    let (copyright, license) = match text("copyright") {
+        Some(notice) => puzzle::Metadata::parse_copyright_notice(&notice),
+        None => (None, None),
+    };
+    Ok(puzzle::Metadata {
+        title: text("title"),
+        author: text("author"),
+        copyright,
+        license,
+        description: text("description"),
+        difficulty: None,
+        solver_stats: notes
+            .iter()
+            .find_map(|note| puzzle::SolverStats::parse(note)),
+        id: text("id"),
+        created: note("Created: "),
+        modified: note("Modified: "),
+        notes: notes
+            .iter()
+            .filter_map(|note| puzzle::Note::parse(note))
+            .collect(),
+        source: None,
+    })
+}
+
+/// Metadata from a PNG's text chunks, under the keywords `export` writes (the standard ones, plus
+/// "License", "Difficulty", "Solver", and "Identifier"). Anything that isn't a PNG just has none.
+/// Only chunks before the image data are read, which is where nearly everything puts them.
+pub fn png_metadata(bytes: &[u8]) -> puzzle::Metadata {
+    let mut res = puzzle::Metadata::default();
+    let reader = match png::Decoder::new(bytes).read_info() {
+        Ok(reader) => reader,
+        Err(_) => return res,
+    };
+    let info = reader.info();
+    let chunks = info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), Some(chunk.text.clone())))
+        .chain(
+            info.compressed_latin1_text
+                .iter()
+                .map(|chunk| (chunk.keyword.clone(), chunk.get_text().ok())),
+        )
+        .chain(
+            info.utf8_text
+                .iter()
+                .map(|chunk| (chunk.keyword.clone(), chunk.get_text().ok())),
+        );
+    for (keyword, text) in chunks {
+        let field = match keyword.as_str() {
+            "Title" => &mut res.title,
+            "Author" => &mut res.author,
+            "Copyright" => &mut res.copyright,
+            "License" => &mut res.license,
+            "Description" => &mut res.description,
+            "Difficulty" => &mut res.difficulty,
+            "Identifier" => &mut res.id,
+            "Creation Time" => &mut res.created,
+            "Modification Time" => &mut res.modified,
+            "Notes" => {
+                let text = text.unwrap_or_default();
+                res.notes = text.lines().filter_map(puzzle::Note::parse).collect();
+                continue;
+            }
+            "Solver" => {
+                res.solver_stats = text.as_deref().and_then(puzzle::SolverStats::parse);
+                continue;
+            }
+            _ => continue,
+        };
+        *field = text;
     }
-    Ok(res.pop().unwrap())
+    res
 }
 
-pub fn webpbn_to_puzzle(webpbn: &str) -> Puzzle {
-    let doc = roxmltree::Document::parse(webpbn).unwrap();
+/// Reads a `.loompack` (see `export::as_loompack`).
+pub fn loompack_to_pack(bytes: &[u8]) -> Result<puzzle::Pack> {
+    let mut value: serde_json::Value = serde_json::from_slice(bytes)
+        .map_err(|e| Error::parse_at(Some(e.line()), e.to_string()))?;
+    match value.get("loompack").and_then(|version| version.as_u64()) {
+        Some(1) => {}
+        Some(version) => {
+            return Err(Error::Unsupported(format!(
+                "can't read version {} of the loompack format",
+                version
+            )))
+        }
+        None => return Err(Error::parse("not a loompack (no \"loompack\" version)")),
+    }
+
+    let mut take = |key: &str| value.get_mut(key).map(serde_json::Value::take);
+    let palettes: Vec<Vec<ColorInfo>> =
+        serde_json::from_value(take("palettes").unwrap_or_default())
+            .map_err(|e| Error::parse(format!("palettes: {}", e)))?;
+    let metadata = match take("metadata") {
+        Some(stored) => serde_json::from_value(stored).map_err(|e| Error::parse(e.to_string()))?,
+        None => puzzle::Metadata::default(),
+    };
+    let entries: Vec<serde_json::Value> =
+        serde_json::from_value(take("puzzles").unwrap_or_default())
+            .map_err(|e| Error::parse(format!("puzzles: {}", e)))?;
+
+    let mut documents = vec![];
+    for (i, mut entry) in entries.into_iter().enumerate() {
+        let problem = |message: String| Error::parse(format!("puzzle {}: {}", i + 1, message));
+        let Some(object) = entry.as_object_mut() else {
+            return Err(problem("not an object".to_string()));
+        };
+
+        let palette = object
+            .remove("palette")
+            .and_then(|index| index.as_u64())
+            .and_then(|index| palettes.get(index as usize))
+            .ok_or_else(|| problem("no palette, or not one of the pack's".to_string()))?;
+        let palette: HashMap<Color, ColorInfo> = palette
+            .iter()
+            .map(|info| (info.color, info.clone()))
+            .collect();
+        let grid: Option<Vec<Vec<Color>>> = match object.remove("solution") {
+            Some(grid) => Some(serde_json::from_value(grid).map_err(|e| problem(e.to_string()))?),
+            None => None,
+        };
+        let document_metadata = match object.remove("metadata") {
+            Some(stored) => serde_json::from_value(stored).map_err(|e| problem(e.to_string()))?,
+            None => puzzle::Metadata::default(),
+        };
+        object.insert("palette".to_string(), serde_json::json!({}));
+        let mut puzzle: Puzzle =
+            serde_json::from_value(entry).map_err(|e| problem(e.to_string()))?;
+        puzzle.palette = palette;
+        check_json_puzzle(&puzzle).map_err(|e| problem(e.to_string()))?;
+
+        let solution = match grid {
+            Some(grid) => {
+                let (width, height) = (puzzle.cols.len(), puzzle.rows.len());
+                if grid.len() != width || grid.iter().any(|col| col.len() != height) {
+                    return Err(problem(format!("the solution isn't {}x{}", width, height)));
+                }
+                if let Some(color) = grid
+                    .iter()
+                    .flatten()
+                    .find(|c| !puzzle.palette.contains_key(c))
+                {
+                    return Err(problem(format!(
+                        "the solution uses color {}, which isn't in its palette",
+                        color.0
+                    )));
+                }
+                Some(Solution {
+                    palette: puzzle.palette.clone(),
+                    grid,
+                    void: puzzle.void.clone(),
+                })
+            }
+            None => None,
+        };
+
+        let mut document = Document::new(puzzle, solution, "loompack");
+        document.metadata = document_metadata;
+        documents.push(document);
+    }
+
+    Ok(puzzle::Pack {
+        metadata,
+        documents,
+    })
+}
+
+/// With `strict`, it's an error for a color's `char` to be more than one character, or for two
+/// colors to share a name or a character.
+pub fn webpbn_to_puzzle(webpbn: &str, strict: bool) -> Result<Puzzle> {
+    let doc = roxmltree::Document::parse(webpbn)?;
     let puzzleset = doc.root_element();
-    let puzzle = get_single_child(puzzleset, "puzzle").unwrap();
+    let puzzle = get_single_child(puzzleset, "puzzle")?;
 
     let default_color = puzzle
         .attribute("defaultcolor")
-        .expect("Expected a 'defaultcolor");
+        .ok_or_else(|| Error::parse_at(line_of(puzzle), "expected a 'defaultcolor'"))?;
     let mut next_color_index = 1;
 
     let mut named_colors = HashMap::<String, Color>::new();
@@ -247,11 +567,18 @@ pub fn webpbn_to_puzzle(webpbn: &str) -> Puzzle {
         palette: HashMap::<Color, ColorInfo>::new(),
         rows: vec![],
         cols: vec![],
+        void: BTreeSet::new(),
+        separate_blocks: false,
     };
 
     for puzzle_part in puzzle.children() {
+        let line = line_of(puzzle_part);
+        let parse_error = |message: String| Error::parse_at(line, message);
+
         if puzzle_part.tag_name().name() == "color" {
-            let color_name = puzzle_part.attribute("name").unwrap();
+            let color_name = puzzle_part
+                .attribute("name")
+                .ok_or_else(|| parse_error("expected a 'name' for the color".to_string()))?;
             let color = if color_name == default_color {
                 BACKGROUND
             } else {
@@ -267,24 +594,47 @@ pub fn webpbn_to_puzzle(webpbn: &str) -> Puzzle {
             )
             .unwrap();
 
-            let color_text = puzzle_part.text().expect("Expected hex color in text");
+            let color_text = puzzle_part
+                .text()
+                .ok_or_else(|| parse_error("expected hex color in text".to_string()))?;
             let (_, component_strs) = hex_color
-                .captures(&color_text)
-                .expect("Expected a string of 6 hex digits")
+                .captures(color_text)
+                .ok_or_else(|| parse_error("expected a string of 6 hex digits".to_string()))?
                 .extract();
 
-            let [r, g, b] = component_strs.map(|s| u8::from_str_radix(s, 16).unwrap());
+            let [r, g, b] = component_strs.map(|s| {
+                u8::from_str_radix(s, 16)
+                    .map_err(|_| parse_error(format!("expected hex digits, not {s:?}")))
+            });
+
+            let ch_str = puzzle_part.attribute("char").ok_or_else(|| {
+                parse_error(format!("expected a 'char' for the color {color_name}"))
+            })?;
+            if strict && ch_str.chars().count() != 1 {
+                return Err(parse_error(format!(
+                    "the 'char' for the color {color_name} should be one character, not {ch_str:?}"
+                )));
+            }
+            let ch = ch_str.chars().next().ok_or_else(|| {
+                parse_error(format!("expected a 'char' for the color {color_name}"))
+            })?;
+            if strict {
+                if named_colors.contains_key(color_name) {
+                    return Err(parse_error(format!(
+                        "there's more than one color named {color_name}"
+                    )));
+                }
+                if res.palette.values().any(|ci| ci.ch == ch) {
+                    return Err(parse_error(format!(
+                        "there's more than one color with the char {ch:?}"
+                    )));
+                }
+            }
 
             let color_info = ColorInfo {
-                // TODO: error if there's more than one char!
-                ch: puzzle_part
-                    .attribute("char")
-                    .unwrap()
-                    .chars()
-                    .next()
-                    .unwrap(),
+                ch,
                 name: color_name.to_string(),
-                rgb: (r, g, b),
+                rgb: (r?, g?, b?),
                 color: color,
             };
 
@@ -296,21 +646,31 @@ pub fn webpbn_to_puzzle(webpbn: &str) -> Puzzle {
             } else if puzzle_part.attribute("type") == Some("columns") {
                 false
             } else {
-                panic!("Expected rows or columns.")
+                return Err(parse_error(
+                    "expected clues of type 'rows' or 'columns'".to_string(),
+                ));
             };
 
             let mut clue_lanes = vec![];
 
-            for lane in get_children(puzzle_part, "line").unwrap() {
+            for lane in get_children(puzzle_part, "line")? {
                 let mut clues = vec![];
-                for block in get_children(lane, "count").unwrap() {
-                    clues.push(Clue {
-                        color: named_colors[block
-                            .attribute("color")
-                            .expect("Expected 'color' attribute")],
-                        count: u16::from_str_radix(&block.text().unwrap(), 10)
-                            .expect("Expected a number."),
-                    });
+                for block in get_children(lane, "count")? {
+                    let parse_error = |message: String| Error::parse_at(line_of(block), message);
+                    let color_name = block
+                        .attribute("color")
+                        .ok_or_else(|| parse_error("expected 'color' attribute".to_string()))?;
+                    clues.push(Clue::new(
+                        *named_colors
+                            .get(color_name)
+                            .ok_or_else(|| parse_error(format!("unknown color {color_name}")))?,
+                        block
+                            .text()
+                            .unwrap_or_default()
+                            .trim()
+                            .parse::<u16>()
+                            .map_err(|_| parse_error("expected a number".to_string()))?,
+                    ));
                 }
                 clue_lanes.push(clues);
             }
@@ -323,74 +683,1463 @@ pub fn webpbn_to_puzzle(webpbn: &str) -> Puzzle {
         }
     }
 
+    Ok(res)
+}
+
+/// Parses the format used by the Olsak solver (as written by `export::as_olsak`).
+///
+/// Palette lines look like `a:a  #FF0000   red`; the first one is the background. Without a
+/// palette, it's a black-and-white puzzle. Clues are a number followed by the color's character
+/// (which may be left off for the first non-background color). As an extension, a clue can also
+/// be a range (`3-5a`) or a blot with a known minimum (`3+a`, or `?a` for any length).
+pub fn olsak_to_puzzle(olsak: &str) -> Result<Puzzle> {
+    let palette_line = regex::Regex::new(
+        r"^\s*(\S):\S*\s+#([0-9A-Fa-f]{2})([0-9A-Fa-f]{2})([0-9A-Fa-f]{2})\s*(\S*)",
+    )
+    .unwrap();
+    let clue_token = regex::Regex::new(r"^(?:(\d+)(?:-(\d+)|(\+))?|(\?))(\D?)$").unwrap();
+
+    let mut res = Puzzle {
+        palette: HashMap::<Color, ColorInfo>::new(),
+        rows: vec![],
+        cols: vec![],
+        void: BTreeSet::new(),
+        separate_blocks: false,
+    };
+    let mut colors_by_ch = HashMap::<char, Color>::new();
+    let mut section: Option<bool> = None; // `Some(true)` for rows
+
+    // (Trailing blank lines aren't empty lanes.)
+    for (line_idx, line) in olsak.trim_end().lines().enumerate() {
+        let parse_error = |message: String| Error::parse_at(Some(line_idx + 1), message);
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix(':') {
+            let name = name.trim();
+            section = if name.starts_with("row") {
+                Some(true)
+            } else if name.starts_with("col") {
+                Some(false)
+            } else {
+                return Err(parse_error(format!(
+                    "expected ': rows' or ': columns', not {trimmed:?}"
+                )));
+            };
+            continue;
+        }
+
+        let is_rows = match section {
+            Some(is_rows) => is_rows,
+            None => {
+                if let Some(captures) = palette_line.captures(line) {
+                    let ch = captures[1].chars().next().unwrap();
+                    let [r, g, b] =
+                        [2, 3, 4].map(|i| u8::from_str_radix(&captures[i], 16).unwrap());
+                    let color = Color(res.palette.len() as u8);
+                    let name = match &captures[5] {
+                        "" => format!("color{}", color.0),
+                        name => name.to_string(),
+                    };
+                    res.palette.insert(
+                        color,
+                        ColorInfo {
+                            ch,
+                            name,
+                            rgb: (r, g, b),
+                            color,
+                        },
+                    );
+                    colors_by_ch.insert(ch, color);
+                } else if !trimmed.is_empty() && !trimmed.starts_with('#') {
+                    return Err(parse_error(format!(
+                        "expected a palette entry, not {trimmed:?}"
+                    )));
+                }
+                continue;
+            }
+        };
+
+        if trimmed.starts_with('#') {
+            continue;
+        }
+        if res.palette.is_empty() {
+            // A black-and-white puzzle.
+            for (ch, name, rgb) in [('0', "white", (255, 255, 255)), ('#', "black", (0, 0, 0))] {
+                let color = Color(res.palette.len() as u8);
+                res.palette.insert(
+                    color,
+                    ColorInfo {
+                        ch,
+                        name: name.to_string(),
+                        rgb,
+                        color,
+                    },
+                );
+                colors_by_ch.insert(ch, color);
+            }
+        }
+
+        let mut clues = vec![];
+        for token in trimmed.split_whitespace() {
+            let captures = clue_token
+                .captures(token)
+                .ok_or_else(|| parse_error(format!("expected a clue, not {token:?}")))?;
+            let number = |i: usize| {
+                captures[i]
+                    .parse::<u16>()
+                    .map_err(|_| parse_error(format!("{:?} is too large", &captures[i])))
+            };
+            let color = match captures[5].chars().next() {
+                Some(ch) => *colors_by_ch
+                    .get(&ch)
+                    .ok_or_else(|| parse_error(format!("unknown color {ch:?}")))?,
+                None => Color(1),
+            };
+            if color == BACKGROUND || !res.palette.contains_key(&color) {
+                return Err(parse_error(format!(
+                    "{token:?} isn't a clue for a foreground color"
+                )));
+            }
+
+            let clue = if captures.get(4).is_some() {
+                Clue {
+                    length: Length::Blotted,
+                    ..Clue::new(color, 1)
+                }
+            } else if captures.get(3).is_some() {
+                let min = number(1)?;
+                if min == 0 {
+                    return Err(parse_error(format!("{token:?} isn't a usable blot")));
+                }
+                Clue {
+                    length: Length::Blotted,
+                    ..Clue::new(color, min)
+                }
+            } else if captures.get(2).is_some() {
+                let (min, max) = (number(1)?, number(2)?);
+                if min == 0 || max < min {
+                    return Err(parse_error(format!("{token:?} isn't a usable range")));
+                }
+                Clue {
+                    length: Length::AtMost(max),
+                    ..Clue::new(color, min)
+                }
+            } else {
+                Clue::new(color, number(1)?)
+            };
+            // Some files spell out empty lanes with a 0.
+            if clue.count > 0 || !clue.length.is_exact() {
+                clues.push(clue);
+            }
+        }
+        if is_rows {
+            res.rows.push(clues);
+        } else {
+            res.cols.push(clues);
+        }
+    }
+
+    if res.rows.is_empty() || res.cols.is_empty() {
+        return Err(Error::parse("expected both ': rows' and ': columns'"));
+    }
+    Ok(res)
+}
+
+/// Parses a GIMP palette (`.gpl`) file.
+///
+/// Each color line is `R G B name`. If the name is of the form `x rest-of-name`, with a single
+/// character first, that character is the one the color uses in grids; otherwise, the first
+/// character of the name is used.
+pub fn gpl_to_palette(gpl: &str) -> Result<PalettePreset> {
+    let mut lines = gpl.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("GIMP Palette") {
+        return Err(Error::parse_at(
+            Some(1),
+            "expected a GIMP palette, starting with 'GIMP Palette'",
+        ));
+    }
+
+    let mut res = PalettePreset {
+        name: String::new(),
+        colors: vec![],
+    };
+
+    for (line_idx, line) in lines {
+        let parse_error = |message: String| Error::parse_at(Some(line_idx + 1), message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("Name:") {
+            res.name = name.trim().to_string();
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let mut component = || -> Result<u8> {
+            let word = words
+                .next()
+                .ok_or_else(|| parse_error(format!("expected 'R G B name', not {line:?}")))?;
+            word.parse::<u8>()
+                .map_err(|_| parse_error(format!("expected a number from 0 to 255, not {word:?}")))
+        };
+        let rgb = (component()?, component()?, component()?);
+
+        let rest: Vec<&str> = words.collect();
+        let (ch, name) = match rest.as_slice() {
+            [] => return Err(parse_error(format!("palette color {:?} needs a name", rgb))),
+            [first, others @ ..] if first.chars().count() == 1 && !others.is_empty() => {
+                (first.chars().next().unwrap(), others.join(" "))
+            }
+            words => (words[0].chars().next().unwrap(), words.join(" ")),
+        };
+
+        if res.colors.iter().any(|c| c.ch == ch) {
+            return Err(parse_error(format!(
+                "palette character '{ch}' is used more than once"
+            )));
+        }
+
+        res.colors.push(PresetColor { ch, name, rgb });
+    }
+
+    Ok(res)
+}
+
+/// Takes names, characters, and (if `by_char`) RGB values from `preset`. Colors are matched up by
+/// character if `by_char` (for inputs like char grids, where the characters are meaningful), and
+/// by RGB value otherwise.
+pub fn apply_palette_preset(
+    palette: &mut HashMap<Color, ColorInfo>,
+    preset: &PalettePreset,
+    by_char: bool,
+) {
+    let mut matched = HashSet::<Color>::new();
+    for color_info in palette.values_mut() {
+        let preset_color = preset.colors.iter().find(|pc| {
+            if by_char {
+                pc.ch == color_info.ch
+            } else {
+                pc.rgb == color_info.rgb
+            }
+        });
+        if let Some(pc) = preset_color {
+            color_info.ch = pc.ch;
+            color_info.rgb = pc.rgb;
+            // pbnsolve output looks weird if the default color isn't called "white".
+            if color_info.color != BACKGROUND {
+                color_info.name = pc.name.clone();
+            }
+            matched.insert(color_info.color);
+        }
+    }
+
+    // Colors that the preset doesn't know about might now have clashing characters.
+    let mut used_chars: HashSet<char> = palette.values().map(|ci| ci.ch).collect();
+    let mut keys: Vec<Color> = palette.keys().cloned().collect();
+    keys.sort_by_key(|c| c.0);
+    for key in keys {
+        if matched.contains(&key) {
+            continue;
+        }
+        let ch = palette[&key].ch;
+        let clashes = palette
+            .values()
+            .any(|ci| ci.color != key && ci.ch == ch && matched.contains(&ci.color));
+        if clashes {
+            let new_ch = ('a'..='z')
+                .chain('A'..='Z')
+                .chain('0'..='9')
+                .find(|c| !used_chars.contains(c))
+                .expect("ran out of characters for colors");
+            used_chars.insert(new_ch);
+            palette.get_mut(&key).unwrap().ch = new_ch;
+        }
+    }
+}
+
+pub type Rgb = (u8, u8, u8);
+
+/// Parses a `#RRGGBB=#RRGGBB` color mapping (the `#`s are optional).
+pub fn parse_color_mapping(mapping: &str) -> Result<(Rgb, Rgb)> {
+    fn parse_rgb(hex: &str) -> Result<Rgb> {
+        let hex = hex.trim().trim_start_matches('#');
+        let bad_color = || Error::parse(format!("expected a color like #FF0000, got {:?}", hex));
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(bad_color());
+        }
+        let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| bad_color());
+        Ok((component(0)?, component(2)?, component(4)?))
+    }
+
+    let (from, to) = mapping
+        .split_once('=')
+        .ok_or_else(|| Error::parse(format!("expected FROM=TO, got {:?}", mapping)))?;
+    Ok((parse_rgb(from)?, parse_rgb(to)?))
+}
+
+/// Parses a file of color mappings, one per line. Blank lines and lines starting with `;` are
+/// ignored.
+pub fn parse_color_map(text: &str) -> Result<Vec<(Rgb, Rgb)>> {
+    text.lines()
+        .enumerate()
+        .map(|(idx, line)| (idx, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with(';'))
+        .map(|(idx, line)| {
+            parse_color_mapping(line).map_err(|e| match e {
+                Error::Parse { message, .. } => Error::parse_at(Some(idx + 1), message),
+                e => e,
+            })
+        })
+        .collect()
+}
+
+fn recolor(color_info: &mut ColorInfo, map: &[(Rgb, Rgb)]) {
+    let Some((_, to)) = map.iter().find(|(from, _)| *from == color_info.rgb) else {
+        return;
+    };
+    // Keep names generated by `image_to_solution` in sync with the color.
+    let hex = |(r, g, b): Rgb| format!("{}{:02X}{:02X}{:02X}", color_info.ch, r, g, b);
+    if color_info.name == hex(color_info.rgb) {
+        color_info.name = hex(*to);
+    }
+    color_info.rgb = *to;
+}
+
+/// Recolors palette entries according to `map`. Colors that end up identical are merged (into
+/// the one with the lowest index, so the background stays the background).
+pub fn remap_colors(solution: &mut Solution, map: &[(Rgb, Rgb)]) {
+    for color_info in solution.palette.values_mut() {
+        recolor(color_info, map);
+    }
+
+    let mut keys: Vec<Color> = solution.palette.keys().cloned().collect();
+    keys.sort_by_key(|c| c.0);
+    let mut merged_into = HashMap::<Color, Color>::new();
+    for (i, key) in keys.iter().enumerate() {
+        let rgb = solution.palette[key].rgb;
+        if let Some(earlier) = keys[..i]
+            .iter()
+            .find(|k| !merged_into.contains_key(k) && solution.palette[k].rgb == rgb)
+        {
+            merged_into.insert(*key, *earlier);
+        }
+    }
+
+    for from in merged_into.keys() {
+        solution.palette.remove(from);
+    }
+    for col in solution.grid.iter_mut() {
+        for color in col.iter_mut() {
+            if let Some(to) = merged_into.get(color) {
+                *color = *to;
+            }
+        }
+    }
+}
+
+/// Like `remap_colors`, but for a puzzle without a solution, where merging isn't possible (the
+/// clues don't say whether same-colored blocks would end up adjacent).
+pub fn remap_puzzle_colors(puzzle: &mut Puzzle, map: &[(Rgb, Rgb)]) -> Result<()> {
+    for color_info in puzzle.palette.values_mut() {
+        recolor(color_info, map);
+    }
+
+    let distinct: HashSet<Rgb> = puzzle.palette.values().map(|ci| ci.rgb).collect();
+    if distinct.len() < puzzle.palette.len() {
+        return Err(Error::Unsupported(
+            "merging colors requires an input with a solution, not just clues".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Cells that touch a differently-colored block to their right or below, which the "blocks may not
+/// touch" variant (`Puzzle::separate_blocks`) doesn't allow.
+pub fn touching_blocks(solution: &Solution) -> Vec<(usize, usize)> {
+    let width = solution.grid.len();
+    let height = solution.grid.first().map_or(0, Vec::len);
+    let touch = |a: Color, b: Color| a != BACKGROUND && b != BACKGROUND && a != b;
+
+    let mut res = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            let color = solution.grid[x][y];
+            let right = x + 1 < width && touch(color, solution.grid[x + 1][y]);
+            let below = y + 1 < height && touch(color, solution.grid[x][y + 1]);
+            if right || below {
+                res.push((x, y));
+            }
+        }
+    }
     res
 }
 
-pub fn solution_to_puzzle(solution: &Solution) -> Puzzle {
+/// Combines the pictures of a multi-panel ("double") puzzle into one, so that it can be clued and
+/// solved as a whole. Side by side, the panels share their row clues (and have to be the same
+/// height); `stacked`, they share their column clues. The `gutter` between them is `void`, so
+/// blocks don't continue from one panel into the next. Colors are matched by RGB value.
+pub fn join_panels(panels: &[Solution], stacked: bool, gutter: usize) -> Result<Solution> {
+    let size = |panel: &Solution| (panel.grid.len(), panel.grid.first().map_or(0, Vec::len));
+    // Lay them out side by side, and transpose at the end if they're supposed to be stacked.
+    let transpose = |panel: &Solution| Solution {
+        palette: panel.palette.clone(),
+        grid: (0..size(panel).1)
+            .map(|y| panel.grid.iter().map(|col| col[y]).collect())
+            .collect(),
+        void: panel.void.iter().map(|&(x, y)| (y, x)).collect(),
+    };
+    let panels: Vec<Solution> = if stacked {
+        panels.iter().map(transpose).collect()
+    } else {
+        panels.to_vec()
+    };
+
+    let Some(first) = panels.first() else {
+        return Err(Error::Unsupported(
+            "there are no panels to join".to_string(),
+        ));
+    };
+    let height = size(first).1;
+    let mut res = Solution {
+        palette: first.palette.clone(),
+        grid: vec![],
+        void: BTreeSet::new(),
+    };
+
+    for (i, panel) in panels.iter().enumerate() {
+        if size(panel).1 != height {
+            return Err(Error::Unsupported(format!(
+                "panel {} is {} cells {}, but panel 1 is {}",
+                i + 1,
+                size(panel).1,
+                if stacked { "wide" } else { "tall" },
+                height
+            )));
+        }
+        if i > 0 {
+            for _ in 0..gutter {
+                let x = res.grid.len();
+                res.void.extend((0..height).map(|y| (x, y)));
+                res.grid.push(vec![BACKGROUND; height]);
+            }
+        }
+
+        let mut colors = HashMap::<Color, Color>::new();
+        colors.insert(BACKGROUND, BACKGROUND);
+        for color_info in panel.palette.values() {
+            if color_info.color == BACKGROUND {
+                continue;
+            }
+            let existing = res
+                .palette
+                .values()
+                .find(|ci| ci.color != BACKGROUND && ci.rgb == color_info.rgb);
+            let color = match existing {
+                Some(ci) => ci.color,
+                None => {
+                    if res.palette.len() >= 30 {
+                        return Err(too_many_colors(res.palette.len() + 1));
+                    }
+                    let color = Color(res.palette.len() as u8);
+                    let mut ch = color_info.ch;
+                    if res.palette.values().any(|ci| ci.ch == ch) {
+                        ch = ('a'..='z')
+                            .chain('A'..='Z')
+                            .find(|c| res.palette.values().all(|ci| ci.ch != *c))
+                            .unwrap();
+                    }
+                    res.palette.insert(
+                        color,
+                        ColorInfo {
+                            ch,
+                            color,
+                            ..color_info.clone()
+                        },
+                    );
+                    color
+                }
+            };
+            colors.insert(color_info.color, color);
+        }
+
+        let offset = res.grid.len();
+        res.void
+            .extend(panel.void.iter().map(|&(x, y)| (x + offset, y)));
+        res.grid.extend(
+            panel
+                .grid
+                .iter()
+                .map(|col| col.iter().map(|color| colors[color]).collect::<Vec<_>>()),
+        );
+    }
+
+    Ok(if stacked { transpose(&res) } else { res })
+}
+
+/// Removes rows and columns of background from the edges of the picture.
+pub fn trim_solution(solution: &mut Solution) {
+    let is_blank = |color: &Color| *color == BACKGROUND;
     let width = solution.grid.len();
-    let height = solution.grid.first().unwrap().len();
+    let height = solution.grid.first().map_or(0, Vec::len);
 
-    let mut rows: Vec<Vec<Clue>> = Vec::new();
-    let mut cols: Vec<Vec<Clue>> = Vec::new();
+    let filled_cols: Vec<usize> = (0..width)
+        .filter(|&x| !solution.grid[x].iter().all(is_blank))
+        .collect();
+    let filled_rows: Vec<usize> = (0..height)
+        .filter(|&y| !solution.grid.iter().all(|col| is_blank(&col[y])))
+        .collect();
+
+    let (Some(&left), Some(&right)) = (filled_cols.first(), filled_cols.last()) else {
+        solution.grid.clear(); // Nothing but background
+        solution.void.clear();
+        return;
+    };
+    let (top, bottom) = (filled_rows[0], *filled_rows.last().unwrap());
+
+    solution.grid = solution.grid[left..=right]
+        .iter()
+        .map(|col| col[top..=bottom].to_vec())
+        .collect();
+    solution.void = solution
+        .void
+        .iter()
+        .filter(|(x, y)| (left..=right).contains(x) && (top..=bottom).contains(y))
+        .map(|(x, y)| (x - left, y - top))
+        .collect();
+}
+
+/// Like `trim_solution`, but for a puzzle without a solution: drops clueless lanes from the edges.
+pub fn trim_puzzle(puzzle: &mut Puzzle) {
+    // Blank rows don't show up in the column clues (and vice versa), so dropping them from the
+    // edges doesn't affect the other lanes.
+    let mut kept = vec![];
+    for lanes in [&mut puzzle.rows, &mut puzzle.cols] {
+        while lanes.last().is_some_and(Vec::is_empty) {
+            lanes.pop();
+        }
+        let leading = lanes.iter().take_while(|lane| lane.is_empty()).count();
+        lanes.drain(..leading);
+        kept.push(leading..leading + lanes.len());
+    }
+    let (ys, xs) = (&kept[0], &kept[1]);
+    puzzle.void = puzzle
+        .void
+        .iter()
+        .filter(|(x, y)| xs.contains(x) && ys.contains(y))
+        .map(|(x, y)| (x - xs.start, y - ys.start))
+        .collect();
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+pub enum Severity {
+    /// Worth knowing, but often intentional.
+    Note,
+    /// Probably a mistake.
+    Warning,
+}
+
+/// Something that's likely to make for a bad nonogram.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct QualityWarning {
+    pub severity: Severity,
+    pub message: String,
+    /// The cells involved, as (x, y), if it's about particular cells.
+    pub cells: Vec<(usize, usize)>,
+    /// The rows and columns involved, if it's about whole lanes.
+    pub lanes: Vec<Lane>,
+    /// What to do about it, if there's an obvious fix.
+    pub suggestion: Option<String>,
+}
+
+impl std::fmt::Display for QualityWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " ({})", suggestion)?;
+        }
+        Ok(())
+    }
+}
 
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct QualityReport {
+    pub warnings: Vec<QualityWarning>,
+}
+
+impl QualityReport {
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    fn add(&mut self, severity: Severity, message: String, suggestion: Option<&str>) {
+        self.warnings.push(QualityWarning {
+            severity,
+            message,
+            cells: vec![],
+            lanes: vec![],
+            suggestion: suggestion.map(str::to_string),
+        });
+    }
+
+    /// Prints each warning to stderr (unless we're being quiet).
+    pub fn print(&self) {
+        for warning in &self.warnings {
+            match warning.severity {
+                Severity::Note => verbosity::note(warning),
+                Severity::Warning => verbosity::warn(warning),
+            }
+        }
+    }
+}
+
+/// Rows and columns with no clues.
+pub fn empty_lanes(puzzle: &Puzzle) -> Vec<Lane> {
+    let rows = puzzle.rows.iter().map(|clues| (true, clues));
+    let cols = puzzle.cols.iter().map(|clues| (false, clues));
+    rows.enumerate()
+        .chain(cols.enumerate())
+        .filter(|(_, (_, clues))| clues.is_empty())
+        .map(|(index, (row, _))| Lane { row, index })
+        .collect()
+}
+
+/// Blank lanes along the edges usually mean the picture wasn't cropped; blank lanes anywhere make
+/// the clue area look broken when printed.
+fn check_empty_lanes(solution: &Solution, report: &mut QualityReport) {
+    let width = solution.grid.len();
+    let height = solution.grid.first().map_or(0, |col| col.len());
+    if width == 0 || height == 0 {
+        return;
+    }
+    let is_empty = |lane: Lane| {
+        if lane.row {
+            (0..width).all(|x| solution.grid[x][lane.index] == BACKGROUND)
+        } else {
+            solution.grid[lane.index].iter().all(|c| *c == BACKGROUND)
+        }
+    };
+    // Lanes entirely outside the outline (like the gutters between panels) are there on purpose.
+    let is_void = |lane: Lane| {
+        if lane.row {
+            (0..width).all(|x| solution.void.contains(&(x, lane.index)))
+        } else {
+            (0..height).all(|y| solution.void.contains(&(lane.index, y)))
+        }
+    };
+    // How many blank lanes there are in from each edge, i.e. how much `--trim` would remove.
+    let margins = |row: bool, len: usize| {
+        let blank = |index: usize| is_empty(Lane { row, index });
+        let before = (0..len).take_while(|i| blank(*i)).count();
+        let after = (before..len).rev().take_while(|i| blank(*i)).count();
+        (before, after)
+    };
+    let (top, bottom) = margins(true, height);
+    let (left, right) = margins(false, width);
+
+    let lanes = (0..height)
+        .map(|index| Lane { row: true, index })
+        .chain((0..width).map(|index| Lane { row: false, index }));
+    let (edge, interior): (Vec<Lane>, Vec<Lane>) = lanes
+        .filter(|lane| is_empty(*lane) && !is_void(*lane))
+        .partition(|lane| {
+            let (len, before, after) = if lane.row {
+                (height, top, bottom)
+            } else {
+                (width, left, right)
+            };
+            lane.index < before || lane.index >= len - after
+        });
+
+    let list = |lanes: &[Lane]| {
+        lanes
+            .iter()
+            .map(|lane| lane.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !edge.is_empty() {
+        report.warnings.push(QualityWarning {
+            severity: Severity::Warning,
+            message: format!("blank lanes along the edge: {}", list(&edge)),
+            cells: vec![],
+            lanes: edge,
+            suggestion: Some(format!(
+                "crop them off with --trim, which would make it {}x{} instead of {}x{}",
+                width.saturating_sub(left + right),
+                height.saturating_sub(top + bottom),
+                width,
+                height
+            )),
+        });
+    }
+    if !interior.is_empty() {
+        report.warnings.push(QualityWarning {
+            severity: Severity::Note,
+            message: format!("blank lanes: {}", list(&interior)),
+            cells: vec![],
+            lanes: interior,
+            suggestion: None,
+        });
+    }
+}
+
+/// Sizes that are awkward to rule, print, or solve.
+fn check_size(width: usize, height: usize, report: &mut QualityReport) {
+    // Bigger than this, and many solvers and most pages give up.
+    const MAX_SIZE: usize = 100;
+
+    if width <= 1 || height <= 1 {
+        report.add(
+            Severity::Warning,
+            format!("{}x{} is hardly a picture", width, height),
+            None,
+        );
+    } else if !width.is_multiple_of(5) || !height.is_multiple_of(5) {
+        report.add(
+            Severity::Note,
+            format!(
+                "{}x{} isn't a multiple of 5 in each direction, so the bold grid lines won't \
+                 line up with the edges",
+                width, height
+            ),
+            None,
+        );
+    }
+    if width > MAX_SIZE || height > MAX_SIZE {
+        report.add(
+            Severity::Warning,
+            format!(
+                "{}x{} is larger than most solvers and printed pages can handle ({} on a side)",
+                width, height, MAX_SIZE
+            ),
+            None,
+        );
+    }
+}
+
+/// Lanes with so many clues that they won't fit in the margins of a typical printed page.
+fn check_clue_area(solution: &Solution, report: &mut QualityReport) {
+    const MAX_CLUES: usize = 12;
+
+    let puzzle = match solution_to_puzzle(solution) {
+        Ok(puzzle) => puzzle,
+        Err(_) => return, // Too many colors, which is reported separately.
+    };
+    let area = puzzle.clue_area();
+    let crowded = |lanes: &[Vec<Clue>], row: bool| -> Vec<Lane> {
+        lanes
+            .iter()
+            .enumerate()
+            .filter(|(_, clues)| clues.len() > MAX_CLUES)
+            .map(|(index, _)| Lane { row, index })
+            .collect()
+    };
+    let rows = crowded(&puzzle.rows, true);
+    if !rows.is_empty() {
+        report.warnings.push(QualityWarning {
+            severity: Severity::Warning,
+            message: format!(
+                "{} {} more than {} clues (up to {}, needing a margin {} characters wide), \
+                 which won't fit on most printed pages",
+                rows.len(),
+                if rows.len() == 1 {
+                    "row has"
+                } else {
+                    "rows have"
+                },
+                MAX_CLUES,
+                area.max_row_clues,
+                area.row_margin
+            ),
+            cells: vec![],
+            lanes: rows,
+            suggestion: None,
+        });
+    }
+    let cols = crowded(&puzzle.cols, false);
+    if !cols.is_empty() {
+        report.warnings.push(QualityWarning {
+            severity: Severity::Warning,
+            message: format!(
+                "{} {} more than {} clues (up to {}, needing a margin {} lines tall), \
+                 which won't fit on most printed pages",
+                cols.len(),
+                if cols.len() == 1 {
+                    "column has"
+                } else {
+                    "columns have"
+                },
+                MAX_CLUES,
+                area.max_col_clues,
+                area.col_margin
+            ),
+            cells: vec![],
+            lanes: cols,
+            suggestion: None,
+        });
+    }
+}
+
+fn color_distance((r, g, b): Rgb, (r2, g2, b2): Rgb) -> i16 {
+    (r2 as i16 - r as i16).abs() + (g2 as i16 - g as i16).abs() + (b2 as i16 - b as i16).abs()
+}
+
+/// Color-vision deficiencies, as matrices on linear RGB (from Machado, Oliveira, and Fernandes,
+/// 2009, at full severity).
+const COLOR_VISION_DEFICIENCIES: [(&str, [[f64; 3]; 3]); 3] = [
+    (
+        "protanopia",
+        [
+            [0.152286, 1.052583, -0.204868],
+            [0.114503, 0.786281, 0.099216],
+            [-0.003882, -0.048116, 1.051998],
+        ],
+    ),
+    (
+        "deuteranopia",
+        [
+            [0.367322, 0.860646, -0.227968],
+            [0.280085, 0.672501, 0.047413],
+            [-0.011820, 0.042940, 0.968881],
+        ],
+    ),
+    (
+        "tritanopia",
+        [
+            [1.255528, -0.076749, -0.178779],
+            [-0.078411, 0.930809, 0.147602],
+            [0.004733, 0.691367, 0.303900],
+        ],
+    ),
+];
+
+/// How `rgb` looks to someone with the deficiency described by `matrix`.
+fn simulate_color_vision(rgb: Rgb, matrix: &[[f64; 3]; 3]) -> Rgb {
+    let to_linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let from_linear = |c: f64| {
+        let c = c.clamp(0.0, 1.0);
+        let c = if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        };
+        (c * 255.0).round() as u8
+    };
+    let linear = [to_linear(rgb.0), to_linear(rgb.1), to_linear(rgb.2)];
+    let [r, g, b] = matrix.map(|row| from_linear((0..3).map(|i| row[i] * linear[i]).sum()));
+    (r, g, b)
+}
+
+/// Pairs of colors that are easy to tell apart, except for colorblind solvers.
+fn check_color_vision(colors: &[&ColorInfo], report: &mut QualityReport) {
+    // A bit more lenient than the ordinary similar-color check; the simulation is approximate.
+    const THRESHOLD: i16 = 50;
+
+    for (i, color) in colors.iter().enumerate() {
+        for color2 in &colors[i + 1..] {
+            if color_distance(color.rgb, color2.rgb) < 30 {
+                continue; // Already reported as too similar for anyone.
+            }
+            let confused: Vec<&str> = COLOR_VISION_DEFICIENCIES
+                .iter()
+                .filter(|(_, matrix)| {
+                    color_distance(
+                        simulate_color_vision(color.rgb, matrix),
+                        simulate_color_vision(color2.rgb, matrix),
+                    ) < THRESHOLD
+                })
+                .map(|(name, _)| *name)
+                .collect();
+            if !confused.is_empty() {
+                report.add(
+                    Severity::Warning,
+                    format!(
+                        "colors {:?} and {:?} are hard to tell apart with {}",
+                        color.rgb,
+                        color2.rgb,
+                        confused.join(" or ")
+                    ),
+                    Some("make one of them lighter or darker than the other"),
+                );
+            }
+        }
+    }
+}
+
+/// Single foreground cells with no orthogonal neighbor of the same color. These are usually stray
+/// pixels, and each one adds a 1 to the clues of its row and column.
+fn check_dust(solution: &Solution, report: &mut QualityReport) {
+    // More than this, and it's probably deliberate texture, which listing won't help with.
+    const MAX_LISTED: usize = 10;
+
+    let width = solution.grid.len();
+    let height = solution.grid.first().map_or(0, |col| col.len());
+    let color_at = |x: isize, y: isize| {
+        if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+            None
+        } else {
+            Some(solution.grid[x as usize][y as usize])
+        }
+    };
+
+    let mut dust = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            let color = solution.grid[x][y];
+            if color == BACKGROUND {
+                continue;
+            }
+            let (xi, yi) = (x as isize, y as isize);
+            let lonely = [(xi - 1, yi), (xi + 1, yi), (xi, yi - 1), (xi, yi + 1)]
+                .iter()
+                .all(|&(nx, ny)| color_at(nx, ny) != Some(color));
+            if lonely {
+                dust.push((x, y));
+            }
+        }
+    }
+    if dust.is_empty() {
+        return;
+    }
+
+    let mut listed: Vec<String> = dust
+        .iter()
+        .take(MAX_LISTED)
+        .map(|(x, y)| format!("row {} column {}", y + 1, x + 1))
+        .collect();
+    if dust.len() > MAX_LISTED {
+        listed.push(format!("and {} more", dust.len() - MAX_LISTED));
+    }
+    report.warnings.push(QualityWarning {
+        severity: Severity::Warning,
+        message: format!(
+            "{} isolated cells, which each add a 1 to the clues: {}",
+            dust.len(),
+            listed.join(", ")
+        ),
+        cells: dust,
+        lanes: vec![],
+        suggestion: Some("remove them if they're stray pixels".to_string()),
+    });
+}
+
+/// Pairs of colors too similar to tell apart, as (from, to): `from` is the one that should be
+/// merged into `to`, which is the one with the lower index (so the background stays the
+/// background).
+pub fn similar_colors(solution: &Solution) -> Vec<(Rgb, Rgb)> {
+    let mut colors: Vec<&ColorInfo> = solution.palette.values().collect();
+    colors.sort_by_key(|ci| ci.color.0);
+    let mut pairs = vec![];
+    for (i, color) in colors.iter().enumerate() {
+        for color2 in &colors[i + 1..] {
+            if color_distance(color.rgb, color2.rgb) < 30 {
+                pairs.push((color2.rgb, color.rgb));
+            }
+        }
+    }
+    pairs
+}
+
+/// Merges similar colors (as found by `similar_colors`) until there are none left, and returns
+/// the merges, in the order they were made.
+pub fn fix_similar_colors(solution: &mut Solution) -> Vec<(Rgb, Rgb)> {
+    let mut merges = vec![];
+    // One at a time, since merging one pair may take care of another (e.g. A~B and B~C).
+    while let Some(pair) = similar_colors(solution).first().copied() {
+        remap_colors(solution, &[pair]);
+        merges.push(pair);
+    }
+    merges
+}
+
+/// Looks for things that are likely to make for a bad nonogram.
+pub fn quality_check(solution: &Solution) -> QualityReport {
+    let mut report = QualityReport::default();
+    let width = solution.grid.len();
+    let height = solution.grid.first().map_or(0, |col| col.len());
+
+    // Void cells are outside the puzzle, so they're neither.
+    let cells = width * height - solution.void.len();
     let bg_squares_found: usize = solution
         .grid
         .iter()
-        .map(|col| {
+        .enumerate()
+        .map(|(x, col)| {
             col.iter()
-                .map(|c| if *c == BACKGROUND { 1 } else { 0 })
-                .sum::<usize>()
+                .enumerate()
+                .filter(|&(y, c)| *c == BACKGROUND && !solution.void.contains(&(x, y)))
+                .count()
         })
         .sum();
 
     if bg_squares_found < (width + height) {
-        eprintln!(
-            "convert-nonogram: warning: {} is a very small number of background squares",
-            bg_squares_found
+        report.add(
+            Severity::Warning,
+            format!(
+                "{} is a very small number of background squares",
+                bg_squares_found
+            ),
+            Some("is the background color the right one?"),
         );
     }
 
-    if (width * height - bg_squares_found) < (width + height) {
-        eprintln!(
-            "convert-nonogram: warning: {} is a very small number of foreground squares",
-            width * height - bg_squares_found
+    if (cells - bg_squares_found) < (width + height) {
+        report.add(
+            Severity::Warning,
+            format!(
+                "{} is a very small number of foreground squares",
+                cells - bg_squares_found
+            ),
+            Some("is the background color the right one?"),
         );
     }
 
     let num_colors = solution.palette.len();
-    if num_colors > 30 {
-        panic!(
-            "{} colors detected. Nonograms with more than 30 colors are not supported.",
-            num_colors
+    if num_colors > 10 {
+        report.add(
+            Severity::Warning,
+            format!("{} colors detected. That's probably too many.", num_colors),
+            Some("try --max-colors"),
         );
-    } else if num_colors > 10 {
-        eprintln!(
-            "convert-nonogram: {} colors detected. That's probably too many.",
-            num_colors
-        )
     }
 
-    // Find similar colors
-    for (color_key, color) in &solution.palette {
-        for (color_key2, color2) in &solution.palette {
-            if color_key == color_key2 {
-                continue;
+    for (from, to) in similar_colors(solution) {
+        let hex = |(r, g, b): Rgb| format!("#{:02X}{:02X}{:02X}", r, g, b);
+        report.warnings.push(QualityWarning {
+            severity: Severity::Warning,
+            message: format!("very similar colors found: {:?} and {:?}", to, from),
+            cells: vec![],
+            lanes: vec![],
+            suggestion: Some(format!(
+                "merge them with --map '{}={}' or --fix-similar-colors",
+                hex(from),
+                hex(to)
+            )),
+        });
+    }
+
+    let mut colors: Vec<&ColorInfo> = solution.palette.values().collect();
+    colors.sort_by_key(|ci| ci.color.0);
+    check_color_vision(&colors, &mut report);
+    check_size(width, height, &mut report);
+    check_empty_lanes(solution, &mut report);
+    check_dust(solution, &mut report);
+    check_clue_area(solution, &mut report);
+    report
+}
+
+/// Picks at most `max_colors` colors to stand in for the ones in `counts` (which says how many
+/// cells use each color), by median cut. White is kept exact, since it's usually the background.
+/// The result maps every color in `counts` to its stand-in.
+pub fn median_cut(counts: &HashMap<Rgb, usize>, max_colors: usize) -> HashMap<Rgb, Rgb> {
+    fn channel(rgb: Rgb, i: usize) -> u8 {
+        [rgb.0, rgb.1, rgb.2][i]
+    }
+    // The channel with the widest range, and that range.
+    fn widest_channel(bucket: &[(Rgb, usize)]) -> (usize, u8) {
+        (0..3)
+            .map(|i| {
+                let min = bucket
+                    .iter()
+                    .map(|(rgb, _)| channel(*rgb, i))
+                    .min()
+                    .unwrap();
+                let max = bucket
+                    .iter()
+                    .map(|(rgb, _)| channel(*rgb, i))
+                    .max()
+                    .unwrap();
+                (i, max - min)
+            })
+            .max_by_key(|(_, range)| *range)
+            .unwrap()
+    }
+
+    let mut all: Vec<(Rgb, usize)> = counts.iter().map(|(rgb, n)| (*rgb, *n)).collect();
+    all.sort(); // We want deterministic behavior
+    let mut buckets = vec![all];
+
+    while buckets.len() < max_colors {
+        let Some((idx, (channel_idx, _))) = buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, bucket)| bucket.len() > 1)
+            .map(|(idx, bucket)| (idx, widest_channel(bucket)))
+            .max_by_key(|(_, (_, range))| *range)
+        else {
+            break; // Every color has a bucket to itself
+        };
+
+        let mut bucket = buckets.swap_remove(idx);
+        bucket.sort_by_key(|(rgb, _)| channel(*rgb, channel_idx));
+        let total: usize = bucket.iter().map(|(_, n)| n).sum();
+        let mut so_far = 0;
+        let split = bucket
+            .iter()
+            .position(|(_, n)| {
+                so_far += n;
+                so_far * 2 >= total
+            })
+            .unwrap();
+        let split = (split + 1).clamp(1, bucket.len() - 1);
+        let upper = bucket.split_off(split);
+        buckets.push(bucket);
+        buckets.push(upper);
+    }
+
+    let mut res = HashMap::new();
+    for bucket in buckets {
+        let stand_in = if bucket.iter().any(|(rgb, _)| *rgb == (255, 255, 255)) {
+            (255, 255, 255)
+        } else {
+            let total: usize = bucket.iter().map(|(_, n)| n).sum();
+            let mean = |i: usize| {
+                let sum: usize = bucket
+                    .iter()
+                    .map(|(rgb, n)| channel(*rgb, i) as usize * n)
+                    .sum();
+                ((sum + total / 2) / total) as u8
+            };
+            (mean(0), mean(1), mean(2))
+        };
+        for (rgb, _) in bucket {
+            res.insert(rgb, stand_in);
+        }
+    }
+    res
+}
+
+/// Reduces the image to at most `max_colors` colors, counting the background; see `median_cut`.
+/// Returns the new image and the mapping from old colors to new.
+pub fn reduce_image_colors(
+    image: &DynamicImage,
+    max_colors: usize,
+) -> (DynamicImage, HashMap<Rgb, Rgb>) {
+    let mut image = image.to_rgba8();
+    let mut counts = HashMap::<Rgb, usize>::new();
+    // Transparent pixels are void cells; whatever RGB they happen to have doesn't matter.
+    for pixel in image.pixels().filter(|pixel| pixel[3] != 0) {
+        *counts.entry((pixel[0], pixel[1], pixel[2])).or_default() += 1;
+    }
+
+    // `image_to_solution` adds white for the background if there isn't any, so leave room for it.
+    let max_colors = if counts.contains_key(&(255, 255, 255)) {
+        max_colors
+    } else {
+        max_colors.saturating_sub(1).max(1)
+    };
+    let map = median_cut(&counts, max_colors);
+    for pixel in image.pixels_mut().filter(|pixel| pixel[3] != 0) {
+        let (r, g, b) = map[&(pixel[0], pixel[1], pixel[2])];
+        *pixel = Rgba([r, g, b, pixel[3]]);
+    }
+    (DynamicImage::ImageRgba8(image), map)
+}
+
+/// Merges foreground colors of similar brightness until there are at most `max_colors` colors
+/// (counting the background, which is left alone). Colors are sorted by luminance and split at the
+/// widest gaps; each group takes on the color of its most common member. Returns the mapping from
+/// old colors to new.
+pub fn merge_by_luminance(solution: &mut Solution, max_colors: usize) -> HashMap<Rgb, Rgb> {
+    let luminance = |(r, g, b): Rgb| 0.2126 * r as f64 + 0.7152 * g as f64 + 0.0722 * b as f64;
+
+    let mut counts = HashMap::<Color, usize>::new();
+    for col in &solution.grid {
+        for color in col {
+            *counts.entry(*color).or_default() += 1;
+        }
+    }
+    let mut foreground: Vec<&ColorInfo> = solution
+        .palette
+        .values()
+        .filter(|ci| ci.color != BACKGROUND)
+        .collect();
+    foreground.sort_by(|a, b| luminance(a.rgb).total_cmp(&luminance(b.rgb)));
+
+    // Split before the `groups - 1` largest gaps in luminance.
+    let groups = std::cmp::max(max_colors, 2) - 1;
+    let mut gaps: Vec<usize> = (1..foreground.len()).collect();
+    gaps.sort_by(|a, b| {
+        let gap = |i: usize| luminance(foreground[i].rgb) - luminance(foreground[i - 1].rgb);
+        gap(*b).total_cmp(&gap(*a))
+    });
+    let mut splits: Vec<usize> = gaps.into_iter().take(groups - 1).collect();
+    splits.sort();
+    splits.push(foreground.len());
+
+    let mut map = HashMap::new();
+    let mut start = 0;
+    for end in splits {
+        let group = &foreground[start..end];
+        if let Some(stand_in) = group.iter().max_by_key(|ci| {
+            (
+                counts.get(&ci.color).copied().unwrap_or(0),
+                std::cmp::Reverse(ci.color.0),
+            )
+        }) {
+            for ci in group {
+                map.insert(ci.rgb, stand_in.rgb);
             }
-            let (r, g, b) = color.rgb;
-            let (r2, g2, b2) = color2.rgb;
-            if (r2 as i16 - r as i16).abs()
-                + (g2 as i16 - g as i16).abs()
-                + (b2 as i16 - b as i16).abs()
-                < 30
+        }
+        start = end;
+    }
+
+    let pairs: Vec<(Rgb, Rgb)> = map.iter().map(|(from, to)| (*from, *to)).collect();
+    remap_colors(solution, &pairs);
+    map
+}
+
+/// Maps every pixel to the nearest color in `palette`, spreading the error onto its neighbors
+/// (Floyd-Steinberg dithering), so that gradients survive having few colors.
+pub fn dither_image(image: &DynamicImage, palette: &[Rgb]) -> DynamicImage {
+    let mut image = image.to_rgba8();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut error = vec![[0.0f32; 3]; width * height];
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.get_pixel_mut(x as u32, y as u32);
+            let wanted: Vec<f32> = (0..3)
+                .map(|i| pixel[i] as f32 + error[y * width + x][i])
+                .collect();
+            let distance = |&(r, g, b): &Rgb| {
+                [r, g, b]
+                    .iter()
+                    .zip(&wanted)
+                    .map(|(c, w)| (*c as f32 - w).powi(2))
+                    .sum::<f32>()
+            };
+            let nearest = match palette
+                .iter()
+                .min_by(|a, b| distance(a).total_cmp(&distance(b)))
             {
-                eprintln!(
-                    "convert-nonogram: warning: very similar colors found: {:?} and {:?}",
-                    color.rgb, color2.rgb
-                );
+                Some(nearest) => *nearest,
+                None => return DynamicImage::ImageRgba8(image),
+            };
+            *pixel = Rgba([nearest.0, nearest.1, nearest.2, pixel[3]]);
+
+            let diff = [
+                wanted[0] - nearest.0 as f32,
+                wanted[1] - nearest.1 as f32,
+                wanted[2] - nearest.2 as f32,
+            ];
+            for (dx, dy, weight) in [(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)] {
+                let (nx, ny) = (x as isize + dx, y + dy);
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    continue;
+                }
+                for i in 0..3 {
+                    error[ny * width + nx as usize][i] += diff[i] * weight / 16.0;
+                }
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+/// Like `reduce_image_colors`, but for a solution that's already been imported.
+pub fn reduce_solution_colors(solution: &mut Solution, max_colors: usize) -> HashMap<Rgb, Rgb> {
+    let mut counts = HashMap::<Rgb, usize>::new();
+    for col in &solution.grid {
+        for color in col {
+            *counts.entry(solution.palette[color].rgb).or_default() += 1;
+        }
+    }
+
+    let map = median_cut(&counts, max_colors);
+    let pairs: Vec<(Rgb, Rgb)> = map.iter().map(|(from, to)| (*from, *to)).collect();
+    remap_colors(solution, &pairs);
+    map
+}
+
+/// Reads a document (with a solution, if the format has one) in the format named `format`: one of
+/// `image`, `svg`, `webpbn`, `olsak`, `char-grid`, or `json` (the `serde` form of `Puzzle`). This
+/// is the lenient, no-frills path for embedders; the command line has more options.
+pub fn from_bytes(bytes: &[u8], format: &str) -> Result<Document> {
+    let as_text =
+        || std::str::from_utf8(bytes).map_err(|e| Error::parse(format!("not valid UTF-8: {}", e)));
+    let mut metadata = puzzle::Metadata::default();
+    let (puzzle, solution) = match format {
+        "image" => {
+            let solution = image_to_solution(&image::load_from_memory(bytes)?)?;
+            metadata = png_metadata(bytes);
+            (solution_to_puzzle(&solution)?, Some(solution))
+        }
+        "svg" => {
+            let solution = image_to_solution(&svg_to_image(bytes, None, None)?)?;
+            (solution_to_puzzle(&solution)?, Some(solution))
+        }
+        "webpbn" => {
+            metadata = webpbn_metadata(as_text()?)?;
+            (webpbn_to_puzzle(as_text()?, false)?, None)
+        }
+        "olsak" => (olsak_to_puzzle(as_text()?)?, None),
+        "char-grid" => {
+            let solution = char_grid_to_solution(as_text()?, false, &HashSet::new())?;
+            (solution_to_puzzle(&solution)?, Some(solution))
+        }
+        "json" => {
+            let mut value: serde_json::Value = serde_json::from_slice(bytes)
+                .map_err(|e| Error::parse_at(Some(e.line()), e.to_string()))?;
+            // Stored next to the puzzle's own fields; see `export::to_bytes`.
+            if let Some(stored) = value.as_object_mut().and_then(|o| o.remove("metadata")) {
+                metadata =
+                    serde_json::from_value(stored).map_err(|e| Error::parse(e.to_string()))?;
+            }
+            let puzzle = serde_json::from_value(value).map_err(|e| Error::parse(e.to_string()))?;
+            check_json_puzzle(&puzzle)?;
+            (puzzle, None)
+        }
+        _ => {
+            return Err(Error::Unsupported(format!(
+                "can't read {:?} puzzles",
+                format
+            )))
+        }
+    };
+    let mut document = Document::new(puzzle, solution, format);
+    document.metadata = metadata;
+    Ok(document)
+}
+
+/// Catches the mistakes in a hand-edited (or corrupted) JSON puzzle that would otherwise crash
+/// whatever uses it: clues in colors that aren't in the palette, and void cells off the grid.
+fn check_json_puzzle(puzzle: &Puzzle) -> Result<()> {
+    for (is_row, lanes) in [(true, &puzzle.rows), (false, &puzzle.cols)] {
+        for (idx, clues) in lanes.iter().enumerate() {
+            for (i, clue) in clues.iter().enumerate() {
+                if clue.color == BACKGROUND || !puzzle.palette.contains_key(&clue.color) {
+                    return Err(Error::parse(format!(
+                        "{}: clue {} has an unknown color (#{})",
+                        Lane {
+                            row: is_row,
+                            index: idx,
+                        },
+                        i + 1,
+                        clue.color.0
+                    )));
+                }
+            }
+        }
+    }
+    let (width, height) = (puzzle.cols.len(), puzzle.rows.len());
+    if let Some((x, y)) = puzzle
+        .void
+        .iter()
+        .find(|(x, y)| *x >= width || *y >= height)
+    {
+        return Err(Error::parse(format!(
+            "void cell ({}, {}) is outside the {}x{} grid",
+            x, y, width, height
+        )));
+    }
+    Ok(())
+}
+
+/// Finds clues that can't possibly be right, regardless of what the picture is supposed to be:
+/// unknown colors, lanes too short for their clues, and colors with different totals in the rows
+/// than in the columns.
+pub fn structural_problems(puzzle: &Puzzle) -> Vec<String> {
+    let mut res = vec![];
+    if puzzle.rows.is_empty() || puzzle.cols.is_empty() {
+        res.push(format!(
+            "the puzzle is {}x{}; it needs at least one row and one column",
+            puzzle.cols.len(),
+            puzzle.rows.len()
+        ));
+        return res;
+    }
+
+    let mut totals = HashMap::<Color, (usize, usize)>::new();
+    // Colors with ranges or blots among their clues don't have totals to compare.
+    let mut uncertain = HashSet::<Color>::new();
+    for (is_row, lanes, len) in [
+        (true, &puzzle.rows, puzzle.cols.len()),
+        (false, &puzzle.cols, puzzle.rows.len()),
+    ] {
+        for (idx, clues) in lanes.iter().enumerate() {
+            let lane = Lane {
+                row: is_row,
+                index: idx,
+            };
+            let mut needed = 0;
+            for (i, clue) in clues.iter().enumerate() {
+                if clue.color == BACKGROUND || !puzzle.palette.contains_key(&clue.color) {
+                    res.push(format!("{}: clue {} has an unknown color", lane, i + 1));
+                }
+                if clue.count == 0 {
+                    res.push(format!("{}: clue {} is zero", lane, i + 1));
+                }
+                let gap =
+                    i > 0 && clues[i - 1].must_be_separated_from(clue, puzzle.separate_blocks);
+                needed += clue.count as usize + gap as usize;
+                if !clue.length.is_exact() {
+                    uncertain.insert(clue.color);
+                }
+
+                let total = totals.entry(clue.color).or_default();
+                if is_row {
+                    total.0 += clue.count as usize;
+                } else {
+                    total.1 += clue.count as usize;
+                }
+            }
+            if needed > len {
+                res.push(format!(
+                    "{}: the clues need {} cells, but there are only {}",
+                    lane, needed, len
+                ));
             }
         }
     }
 
+    let mut colors: Vec<&Color> = totals.keys().collect();
+    colors.sort_by_key(|c| c.0);
+    for color in colors {
+        let (in_rows, in_cols) = totals[color];
+        if in_rows != in_cols && !uncertain.contains(color) {
+            let name = puzzle
+                .palette
+                .get(color)
+                .map_or_else(|| format!("#{}", color.0), |ci| ci.name.clone());
+            res.push(format!(
+                "color {}: the row clues add up to {}, but the column clues add up to {}",
+                name, in_rows, in_cols
+            ));
+        }
+    }
+    res
+}
+
+fn too_many_colors(num_colors: usize) -> Error {
+    Error::Unsupported(format!(
+        "{} colors detected. Nonograms with more than 30 colors are not supported.",
+        num_colors
+    ))
+}
+
+pub fn solution_to_puzzle(solution: &Solution) -> Result<Puzzle> {
+    let width = solution.grid.len();
+    let height = match solution.grid.first() {
+        Some(col) if !col.is_empty() => col.len(),
+        _ => return Err(Error::Unsupported("the grid is empty".to_string())),
+    };
+
+    let mut rows: Vec<Vec<Clue>> = Vec::new();
+    let mut cols: Vec<Vec<Clue>> = Vec::new();
+
+    let num_colors = solution.palette.len();
+    if num_colors > 30 {
+        return Err(too_many_colors(num_colors));
+    }
+
     // Generate row clues
     for y in 0..height {
         let mut clues = Vec::<Clue>::new();
@@ -410,7 +2159,7 @@ pub fn solution_to_puzzle(solution: &Solution) -> Puzzle {
             match cur_color {
                 None => {}
                 Some(color) if color == puzzle::BACKGROUND => {}
-                Some(color) => clues.push(Clue { color, count: run }),
+                Some(color) => clues.push(Clue::new(color, run)),
             }
             cur_color = color;
             run = 1;
@@ -437,7 +2186,7 @@ pub fn solution_to_puzzle(solution: &Solution) -> Puzzle {
             match cur_color {
                 None => {}
                 Some(color) if color == BACKGROUND => {}
-                Some(color) => clues.push(Clue { color, count: run }),
+                Some(color) => clues.push(Clue::new(color, run)),
             }
             cur_color = color;
             run = 1;
@@ -445,9 +2194,336 @@ pub fn solution_to_puzzle(solution: &Solution) -> Puzzle {
         cols.push(clues);
     }
 
-    Puzzle {
+    Ok(Puzzle {
         palette: solution.palette.clone(),
         rows,
         cols,
+        void: solution.void.clone(),
+        separate_blocks: false,
+    })
+}
+
+#[test]
+fn quality_check_test() {
+    let solution = char_grid_to_solution("...\n.#.\n...\n", true, &HashSet::new()).unwrap();
+    let report = quality_check(&solution);
+    assert_eq!(report.warnings.len(), 4);
+    assert_eq!(report.warnings[0].severity, Severity::Warning);
+    assert!(report.warnings[0].message.contains("foreground"));
+    assert_eq!(report.warnings[1].severity, Severity::Note);
+    assert!(report.warnings[1].message.contains("multiple of 5"));
+    assert_eq!(report.warnings[2].lanes.len(), 4);
+    assert_eq!(
+        report.warnings[2].suggestion.as_deref(),
+        Some("crop them off with --trim, which would make it 1x1 instead of 3x3")
+    );
+    assert_eq!(report.warnings[3].cells, vec![(1, 1)]);
+
+    let mut solution = solution;
+    solution.palette.get_mut(&Color(1)).unwrap().rgb = (250, 250, 250);
+    let report = quality_check(&solution);
+    assert!(report
+        .warnings
+        .iter()
+        .any(|w| w.message.contains("similar") && w.suggestion.is_some()));
+    let mut fixed = solution.clone();
+    assert_eq!(
+        fix_similar_colors(&mut fixed),
+        vec![((250, 250, 250), (255, 255, 255))]
+    );
+    assert_eq!(fixed.palette.len(), 1);
+
+    // Red and green are fine for most people, but not for everyone.
+    solution.palette.get_mut(&BACKGROUND).unwrap().rgb = (255, 0, 0);
+    solution.palette.get_mut(&Color(1)).unwrap().rgb = (0, 170, 0);
+    let report = quality_check(&solution);
+    assert!(report
+        .warnings
+        .iter()
+        .any(|w| w.message.contains("deuteranopia")));
+
+    // Thirteen blocks in a row is more than a printed margin has room for.
+    let crowded = char_grid_to_solution(
+        &format!("{}\n{}\n", "#.".repeat(13), ".".repeat(26)),
+        true,
+        &HashSet::new(),
+    )
+    .unwrap();
+    let report = quality_check(&crowded);
+    let warning = report
+        .warnings
+        .iter()
+        .find(|w| w.message.contains("more than 12 clues"))
+        .unwrap();
+    assert!(warning.message.starts_with("1 row has more than 12 clues"));
+    assert_eq!(
+        warning.lanes,
+        vec![Lane {
+            row: true,
+            index: 0
+        }]
+    );
+
+    // The void down the left side isn't background, so there's hardly any.
+    let grid = "..####\n".repeat(6);
+    let mut solution = char_grid_to_solution(&grid, true, &HashSet::new()).unwrap();
+    solution.void = (0..2).flat_map(|x| (0..6).map(move |y| (x, y))).collect();
+    let report = quality_check(&solution);
+    assert!(report.warnings[0].message.contains("background"));
+}
+
+#[test]
+fn median_cut_test() {
+    // A gray ramp, with no white in it.
+    let counts: HashMap<Rgb, usize> = (0..200_u8).map(|v| ((v, v, v), 1 + v as usize)).collect();
+    let map = median_cut(&counts, 4);
+    assert_eq!(map.len(), counts.len());
+    let stand_ins: HashSet<Rgb> = map.values().copied().collect();
+    assert_eq!(stand_ins.len(), 4);
+    assert_eq!(median_cut(&counts, 4), map);
+    // Darker grays never get a lighter stand-in than lighter grays do.
+    assert!((1..200_u8).all(|v| map[&(v - 1, v - 1, v - 1)].0 <= map[&(v, v, v)].0));
+
+    // Reducing a picture like that leaves a slot for the white background.
+    let image = image::RgbaImage::from_fn(20, 10, |x, y| {
+        let v = (x * 10 + y) as u8;
+        Rgba([v, v, v, 255])
+    });
+    let (reduced, _) = reduce_image_colors(&DynamicImage::ImageRgba8(image), 4);
+    let solution = image_to_solution(&reduced).unwrap();
+    assert_eq!(solution.palette.len(), 4);
+}
+
+#[test]
+fn void_test() {
+    // Black, transparent, black: the transparent pixel splits the row into two blocks.
+    let image = image::RgbaImage::from_fn(3, 1, |x, _| {
+        if x == 1 {
+            Rgba([255, 0, 0, 0])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    });
+    // Color reduction doesn't spend any of its budget on the transparent pixel.
+    let (reduced, map) = reduce_image_colors(&DynamicImage::ImageRgba8(image.clone()), 2);
+    assert_eq!(map.len(), 1);
+    assert_eq!(reduced.get_pixel(1, 0)[3], 0);
+
+    let solution = image_to_solution(&DynamicImage::ImageRgba8(image)).unwrap();
+    assert_eq!(solution.palette.len(), 2);
+    assert!(solution.void.contains(&(1, 0)));
+
+    let puzzle = solution_to_puzzle(&solution).unwrap();
+    assert_eq!(puzzle.rows[0].len(), 2);
+    assert!(puzzle.cols[1].is_empty());
+
+    let report = crate::grid_solve::solve(&puzzle, false, None).unwrap();
+    assert_eq!(report.cells_left, 0);
+    assert_eq!(report.solution(&puzzle).unwrap().void, solution.void);
+
+    // Formats that can't draw the outline refuse, unless told to leave it out.
+    assert_eq!(puzzle.rules(), vec![puzzle::Rule::Outline]);
+    let document = Document::new(puzzle, Some(solution), "image");
+    let lenient = crate::export::ImageOptions {
+        drop_rules: true,
+        ..Default::default()
+    };
+    assert!(crate::export::to_bytes(&document, "json", Default::default()).is_ok());
+    assert!(crate::export::to_bytes(&document, "webpbn", Default::default()).is_err());
+    assert!(crate::export::to_bytes(&document, "webpbn", lenient).is_ok());
+}
+
+#[test]
+fn olsak_test() {
+    let olsak = "#d\n   0:   #FFFFFF   white\n   a:a  #FF0000   red\n: rows\n2a\n1-2a\n\n: columns\n2a\n?a\n";
+    let puzzle = olsak_to_puzzle(olsak).unwrap();
+    assert_eq!(puzzle.rows.len(), 3);
+    assert!(puzzle.rows[2].is_empty());
+    assert_eq!(puzzle.rows[1][0].length, Length::AtMost(2));
+    assert_eq!(puzzle.cols[1][0].count_text(), "?");
+    assert!(structural_problems(&puzzle).is_empty());
+
+    let reread = olsak_to_puzzle(&crate::export::as_olsak(&puzzle)).unwrap();
+    assert!(reread.rows == puzzle.rows);
+    assert!(reread.cols == puzzle.cols);
+
+    // Without a palette, it's black and white.
+    let bw = olsak_to_puzzle(": rows\n1 1\n: columns\n1\n0\n1\n").unwrap();
+    assert_eq!(bw.palette.len(), 2);
+    assert!(bw.cols[1].is_empty());
+
+    // A blot has to be at least one cell.
+    assert!(matches!(
+        olsak_to_puzzle(": rows\n1\n0+\n: columns\n1\n"),
+        Err(Error::Parse { line: Some(3), .. })
+    ));
+}
+
+#[test]
+fn join_panels_test() {
+    let left = char_grid_to_solution("#.\n##\n", true, &HashSet::new()).unwrap();
+    let right = char_grid_to_solution(".#\n##\n", true, &HashSet::new()).unwrap();
+
+    let joined = join_panels(&[left.clone(), right.clone()], false, 1).unwrap();
+    assert_eq!(joined.grid.len(), 5);
+    assert_eq!(joined.palette.len(), 2);
+    assert!(joined.void.contains(&(2, 1)));
+    let puzzle = solution_to_puzzle(&joined).unwrap();
+    // The gutter splits the shared row.
+    assert_eq!(puzzle.rows[1].len(), 2);
+    assert!(puzzle.cols[2].is_empty());
+    let report = crate::grid_solve::solve(&puzzle, false, None).unwrap();
+    assert_eq!(report.cells_left, 0);
+
+    let stacked = join_panels(&[left, right], true, 0).unwrap();
+    assert_eq!(stacked.grid.len(), 2);
+    assert_eq!(stacked.grid[0].len(), 4);
+}
+
+#[test]
+fn metadata_round_trip_test() {
+    let solution = char_grid_to_solution("#.\n##\n", true, &HashSet::new()).unwrap();
+    let mut document = Document::new(
+        solution_to_puzzle(&solution).unwrap(),
+        Some(solution),
+        "char-grid",
+    );
+    document.metadata = puzzle::Metadata {
+        title: Some("Stairs & steps".to_string()),
+        author: Some("Jane Doe".to_string()),
+        copyright: Some("© 2024".to_string()),
+        license: Some("CC BY 4.0".to_string()),
+        description: Some("Two steps up.".to_string()),
+        difficulty: Some("1 star".to_string()),
+        solver_stats: Some(puzzle::SolverStats {
+            skims: 12,
+            scrubs: 3,
+            cells_left: 0,
+        }),
+        id: Some("1b4e28ba-2fa1-41d2-883f-0016d3cca427".to_string()),
+        created: Some("2024-01-02T03:04:05Z".to_string()),
+        modified: Some("2024-02-03T04:05:06Z".to_string()),
+        notes: vec![
+            puzzle::Note {
+                on: puzzle::NoteTarget::Cell(1, 0),
+                text: "only here to disambiguate".to_string(),
+            },
+            puzzle::Note {
+                on: puzzle::NoteTarget::Column(0),
+                text: "the easy one".to_string(),
+            },
+        ],
+        source: Some(puzzle::Source {
+            path: Some("/art/stairs.png".to_string()),
+            embedded: None,
+            options: vec!["--max-colors".to_string(), "2".to_string()],
+        }),
+    };
+
+    for format in ["image", "json", "webpbn"] {
+        let bytes = crate::export::to_bytes(&document, format, Default::default()).unwrap();
+        let reread = from_bytes(&bytes, format).unwrap().metadata;
+        let mut expected = document.metadata.clone();
+        if format == "webpbn" {
+            expected.difficulty = None;
+        }
+        // Only the native format has room to remember where the picture came from.
+        if format != "json" {
+            expected.source = None;
+        }
+        assert_eq!(reread, expected, "{}", format);
+    }
+
+    assert_eq!(
+        puzzle::Metadata::parse_copyright_notice("© 2024 Acme Inc.. License: CC0"),
+        (
+            Some("© 2024 Acme Inc.".to_string()),
+            Some("CC0".to_string())
+        )
+    );
+    assert_eq!(
+        puzzle::Metadata::parse_copyright_notice("License: CC0"),
+        (None, Some("CC0".to_string()))
+    );
+
+    let note = puzzle::Note::parse("Row 2: starts here").unwrap();
+    assert_eq!(note.on, puzzle::NoteTarget::Row(1));
+    assert_eq!(note.to_string(), "row 2: starts here");
+    assert!(puzzle::Note::parse("row 0: out of range").is_none());
+
+    // Without metadata, JSON output is a plain `Puzzle`.
+    document.metadata = Default::default();
+    let bytes = crate::export::to_bytes(&document, "json", Default::default()).unwrap();
+    assert!(serde_json::from_slice::<Puzzle>(&bytes).is_ok());
+    assert!(!String::from_utf8(bytes).unwrap().contains("metadata"));
+}
+
+#[test]
+fn bad_json_test() {
+    let puzzle = from_bytes(b".#\n##", "char-grid").unwrap().puzzle;
+    assert!(from_bytes(&serde_json::to_vec(&puzzle).unwrap(), "json").is_ok());
+
+    let mut value = serde_json::to_value(&puzzle).unwrap();
+    value["rows"][0][0]["color"] = serde_json::json!(7);
+    let bytes = serde_json::to_vec(&value).unwrap();
+    assert!(matches!(
+        from_bytes(&bytes, "json"),
+        Err(Error::Parse { .. })
+    ));
+
+    let mut value = serde_json::to_value(&puzzle).unwrap();
+    value["void"] = serde_json::json!([[2, 0]]);
+    let bytes = serde_json::to_vec(&value).unwrap();
+    assert!(matches!(
+        from_bytes(&bytes, "json"),
+        Err(Error::Parse { .. })
+    ));
+}
+
+#[test]
+fn loompack_round_trip_test() {
+    let document = |grid: &str, title: &str| {
+        let solution = char_grid_to_solution(grid, true, &HashSet::new()).unwrap();
+        let mut document = Document::new(
+            solution_to_puzzle(&solution).unwrap(),
+            Some(solution),
+            "char-grid",
+        );
+        document.metadata.title = Some(title.to_string());
+        document
+    };
+    let pack = puzzle::Pack {
+        metadata: puzzle::Metadata {
+            title: Some("Shapes".to_string()),
+            ..Default::default()
+        },
+        documents: vec![
+            document("#.\n##\n", "Stairs"),
+            document(".#\n##\n", "Stairs, mirrored"),
+        ],
+    };
+
+    let bytes = crate::export::as_loompack(&pack).unwrap();
+    let stored: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(stored["palettes"].as_array().unwrap().len(), 1);
+
+    let reread = loompack_to_pack(&bytes).unwrap();
+    assert_eq!(reread.metadata, pack.metadata);
+    assert_eq!(reread.documents.len(), 2);
+    for (reread, original) in reread.documents.iter().zip(&pack.documents) {
+        assert_eq!(reread.metadata, original.metadata);
+        assert!(reread.puzzle.rows == original.puzzle.rows);
+        assert!(reread.puzzle.cols == original.puzzle.cols);
+        assert_eq!(reread.puzzle.palette, original.puzzle.palette);
+        let grid = |document: &Document| document.solution.as_ref().unwrap().grid.clone();
+        assert_eq!(grid(reread), grid(original));
     }
+
+    assert!(loompack_to_pack(b"[]").is_err());
+
+    // A clue in a color the palette doesn't have.
+    let mut stored = stored;
+    stored["puzzles"][1]["rows"][0][0]["color"] = serde_json::json!(7);
+    let bytes = serde_json::to_vec(&stored).unwrap();
+    assert!(matches!(loompack_to_pack(&bytes), Err(Error::Parse { .. })));
 }
@@ -0,0 +1,169 @@
+//! Pictures drawn with the terminal's own graphics, for terminals that have some.
+
+use std::io::IsTerminal;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use convert_nonogram::{
+    export::{self, ImageOptions},
+    puzzle::Solution,
+};
+use image::RgbImage;
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, PartialEq, Eq)]
+pub enum Graphics {
+    /// Guess from the environment, and use colored text if nothing turns up.
+    Auto,
+    /// Kitty's graphics protocol (also in Ghostty and Konsole).
+    Kitty,
+    /// iTerm2's inline images (also in WezTerm).
+    Iterm,
+    /// Sixels (in foot, mlterm, and xterm with `-ti vt340`, among others).
+    Sixel,
+    /// Colored text, which works everywhere.
+    Text,
+}
+
+impl Graphics {
+    /// Resolves `Auto`. There's no portable way to ask a terminal what it can do without waiting
+    /// on a reply, so this goes by what the popular ones put in the environment.
+    pub fn detect(self) -> Graphics {
+        if self != Graphics::Auto {
+            return self;
+        }
+        if !std::io::stdout().is_terminal() {
+            return Graphics::Text;
+        }
+        let var = |name| std::env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if term == "xterm-kitty" || program == "ghostty" || !var("KITTY_WINDOW_ID").is_empty() {
+            Graphics::Kitty
+        } else if program == "iTerm.app" || program == "WezTerm" {
+            Graphics::Iterm
+        } else if term.starts_with("foot") || term.starts_with("mlterm") || term.contains("sixel") {
+            Graphics::Sixel
+        } else {
+            Graphics::Text
+        }
+    }
+}
+
+/// Big enough to see, but not so big that large puzzles run off the screen.
+fn options_for(solution: &Solution) -> ImageOptions {
+    let cells = std::cmp::max(solution.grid.len(), 1) as u32;
+    ImageOptions {
+        scale: (480 / cells).clamp(3, 16),
+        grid_lines: true,
+        ..Default::default()
+    }
+}
+
+/// The escape sequences that draw `solution`, followed by a newline; `None` for `Text` (or
+/// `Auto`, which should be `detect`ed first).
+pub fn render(solution: &Solution, graphics: Graphics) -> anyhow::Result<Option<String>> {
+    let options = options_for(solution);
+    let png = || export::as_image_bytes(solution, options, &Default::default());
+    let res = match graphics {
+        Graphics::Auto | Graphics::Text => return Ok(None),
+        Graphics::Kitty => {
+            // The payload has to be sent in chunks of at most 4096 bytes.
+            let data = STANDARD.encode(png()?);
+            let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+            let mut res = String::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = (i + 1 < chunks.len()) as u8;
+                let control = if i == 0 {
+                    format!("f=100,a=T,m={}", more)
+                } else {
+                    format!("m={}", more)
+                };
+                res.push_str(&format!(
+                    "\x1b_G{};{}\x1b\\",
+                    control,
+                    std::str::from_utf8(chunk)?
+                ));
+            }
+            res
+        }
+        Graphics::Iterm => {
+            let png = png()?;
+            format!(
+                "\x1b]1337;File=inline=1;size={}:{}\x07",
+                png.len(),
+                STANDARD.encode(&png)
+            )
+        }
+        Graphics::Sixel => as_sixel(&export::solution_to_scaled_image(solution, options)),
+    };
+    Ok(Some(res + "\n"))
+}
+
+/// Puzzles only have a handful of colors, so every one of them can go in the sixel palette as-is.
+fn as_sixel(image: &RgbImage) -> String {
+    let mut colors: Vec<[u8; 3]> = vec![];
+    for pixel in image.pixels() {
+        if !colors.contains(&pixel.0) {
+            colors.push(pixel.0);
+        }
+    }
+
+    let (width, height) = image.dimensions();
+    let mut res = format!("\x1bPq\"1;1;{};{}", width, height);
+    for (i, [r, g, b]) in colors.iter().enumerate() {
+        // Sixel color components are percentages.
+        let pct = |c: &u8| *c as u32 * 100 / 255;
+        res.push_str(&format!("#{};2;{};{};{}", i, pct(r), pct(g), pct(b)));
+    }
+
+    // Each band is six pixels tall, drawn once per color, with `$` returning to its start.
+    for band in (0..height).step_by(6) {
+        for (i, color) in colors.iter().enumerate() {
+            let sixels: Vec<u8> = (0..width)
+                .map(|x| {
+                    let mut bits = 0;
+                    for dy in 0..std::cmp::min(6, height - band) {
+                        if image.get_pixel(x, band + dy).0 == *color {
+                            bits |= 1 << dy;
+                        }
+                    }
+                    bits
+                })
+                .collect();
+            if sixels.iter().all(|&bits| bits == 0) {
+                continue;
+            }
+            res.push_str(&format!("#{}", i));
+            // Runs of the same sixel are compressed as `!<count><sixel>`.
+            let mut x = 0;
+            while x < sixels.len() {
+                let run = sixels[x..].iter().take_while(|&&s| s == sixels[x]).count();
+                let ch = (b'?' + sixels[x]) as char;
+                if run > 3 {
+                    res.push_str(&format!("!{}{}", run, ch));
+                } else {
+                    res.push_str(&ch.to_string().repeat(run));
+                }
+                x += run;
+            }
+            res.push('$');
+        }
+        res.push('-');
+    }
+    res.push_str("\x1b\\");
+    res
+}
+
+#[test]
+fn as_sixel_test() {
+    // A black bar along the top of a white picture.
+    let image = RgbImage::from_fn(5, 2, |x, y| {
+        if x > 0 && y == 0 {
+            image::Rgb([0, 0, 0])
+        } else {
+            image::Rgb([255, 255, 255])
+        }
+    });
+    assert_eq!(
+        as_sixel(&image),
+        "\x1bPq\"1;1;5;2#0;2;100;100;100#1;2;0;0;0#0B!4A$#1?!4@$-\x1b\\"
+    );
+}
@@ -0,0 +1,20 @@
+//! Reading, writing, and solving nonograms.
+//!
+//! The `convert-nonogram` binary is a thin command-line wrapper around this library. Importers
+//! (in [`import`]) produce a [`puzzle::Puzzle`] (the clues) and, where the format has one, a
+//! [`puzzle::Solution`] (the picture); exporters (in [`export`]) turn those back into files; and
+//! [`grid_solve::solve`] works out how hard a puzzle is to solve with line logic.
+
+pub mod error;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod generate;
+pub mod grid_solve;
+pub mod import;
+pub mod line_solve;
+pub mod mosaic;
+pub mod puzzle;
+pub mod verbosity;
+#[cfg(feature = "wasm")]
+pub mod wasm;
@@ -1,3 +1,5 @@
+//! Line logic: learning what's possible in one lane from its clues and its known cells.
+
 // They're used in tests, but it can't see that.
 #![allow(unused_macros)]
 
@@ -158,10 +160,7 @@ impl Iterator for Arrangement<'_> {
                 return Some(BACKGROUND);
             } else {
                 // dummy clue for explicit background
-                Clue {
-                    color: BACKGROUND,
-                    count: self.gaps[self.block / 2],
-                }
+                Clue::new(BACKGROUND, self.gaps[self.block / 2])
             }
         } else {
             self.cs[(self.block - 1) / 2]
@@ -266,11 +265,16 @@ fn learn_cell_intersect(
 
 struct ClueAdjIterator<'a> {
     clues: &'a [Clue],
+    separate_blocks: bool,
     i: usize,
 }
 impl<'a> ClueAdjIterator<'a> {
-    fn new(clues: &'a [Clue]) -> ClueAdjIterator<'a> {
-        ClueAdjIterator { clues, i: 0 }
+    fn new(clues: &'a [Clue], separate_blocks: bool) -> ClueAdjIterator<'a> {
+        ClueAdjIterator {
+            clues,
+            separate_blocks,
+            i: 0,
+        }
     }
 }
 
@@ -281,11 +285,12 @@ impl<'a> Iterator for ClueAdjIterator<'a> {
         if self.i == self.clues.len() {
             return None;
         }
+        let clue = &self.clues[self.i];
         let res = (
-            self.i > 0 && self.clues[self.i - 1].color == self.clues[self.i].color,
-            &self.clues[self.i],
+            self.i > 0 && self.clues[self.i - 1].must_be_separated_from(clue, self.separate_blocks),
+            clue,
             self.i < self.clues.len() - 1
-                && self.clues[self.i + 1].color == self.clues[self.i].color,
+                && clue.must_be_separated_from(&self.clues[self.i + 1], self.separate_blocks),
         );
         self.i += 1;
         Some(res)
@@ -294,7 +299,12 @@ impl<'a> Iterator for ClueAdjIterator<'a> {
 
 ///  For example, (1 2 1) with no other constraints gives
 ///  .] .  .  .]  .  .]
-fn packed_extents(clues: &[Clue], lane: &ArrayViewMut1<Cell>, reversed: bool) -> Vec<usize> {
+fn packed_extents(
+    clues: &[Clue],
+    separate_blocks: bool,
+    lane: &ArrayViewMut1<Cell>,
+    reversed: bool,
+) -> anyhow::Result<Vec<usize>> {
     let mut extents: Vec<usize> = vec![];
 
     let lane_at = |idx: usize| -> Cell {
@@ -315,18 +325,20 @@ fn packed_extents(clues: &[Clue], lane: &ArrayViewMut1<Cell>, reversed: bool) ->
     // -- Pack to the left (we've abstracted over `reversed`) --
 
     let mut pos = 0_usize;
-    let mut last_color = None;
+    let mut last_clue: Option<&Clue> = None;
     for clue_idx in 0..clues.len() {
         let clue = clue_at(clue_idx);
-        if Some(clue.color) == last_color {
+        if last_clue.is_some_and(|last| last.must_be_separated_from(clue, separate_blocks)) {
             pos += 1;
         }
         // Scanning backwards for mismatches lets us jump farther sometimes.
         let mut placeable = false;
         while !placeable {
             placeable = true;
+            if pos + clue.count as usize > lane.len() {
+                bail!("clues don't fit in the lane");
+            }
             for possible_pos in (pos..(pos + clue.count as usize)).rev() {
-                // TODO: `possible_pos` can get too high if clues are contradictory; use `Result`
                 let cur = lane_at(possible_pos);
 
                 if !cur.can_be(clue.color) {
@@ -338,7 +350,7 @@ fn packed_extents(clues: &[Clue], lane: &ArrayViewMut1<Cell>, reversed: bool) ->
         }
         extents.push(pos + clue.count as usize - 1);
         pos += clue.count as usize;
-        last_color = Some(clue.color);
+        last_clue = Some(clue);
     }
 
     // TODO: pull out into a separate function!
@@ -368,7 +380,9 @@ fn packed_extents(clues: &[Clue], lane: &ArrayViewMut1<Cell>, reversed: bool) ->
             //  0  1  2  3  4  5  6  7  8  9
             //                   [      #]
             // 8 - 3 = 5 is the next cell we need to examine. But we'll `-= 1` below, so add 1.
-            i = extents[cur_extent_idx] + 1 - clue_at(cur_extent_idx).count as usize;
+            // (If we don't know exactly how long the block is, we have to assume the longest.)
+            let max_count = clue_at(cur_extent_idx).max_count(lane.len());
+            i = (extents[cur_extent_idx] + 1).saturating_sub(max_count);
             if cur_extent_idx == 0 {
                 break;
             }
@@ -389,10 +403,15 @@ fn packed_extents(clues: &[Clue], lane: &ArrayViewMut1<Cell>, reversed: bool) ->
         }
     }
 
-    extents
+    Ok(extents)
 }
 
-pub fn skim_line(clues: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Result<ScrubReport> {
+/// `separate_blocks` is `Puzzle::separate_blocks`, here and below.
+pub fn skim_line(
+    clues: &[Clue],
+    separate_blocks: bool,
+    mut lane: ArrayViewMut1<Cell>,
+) -> anyhow::Result<ScrubReport> {
     let mut affected = Vec::<usize>::new();
     if clues.is_empty() {
         // Special case, so we can safely take the first and last clue.
@@ -404,11 +423,11 @@ pub fn skim_line(clues: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Resul
         });
     }
 
-    let left_packed_right_extents = packed_extents(clues, &lane, false);
-    let right_packed_left_extents = packed_extents(clues, &lane, true);
+    let left_packed_right_extents = packed_extents(clues, separate_blocks, &lane, false)?;
+    let right_packed_left_extents = packed_extents(clues, separate_blocks, &lane, true)?;
 
-    for ((gap_before, clue, gap_after), (left_extent, right_extent)) in ClueAdjIterator::new(clues)
-        .zip(
+    for ((gap_before, clue, gap_after), (left_extent, right_extent)) in
+        ClueAdjIterator::new(clues, separate_blocks).zip(
             right_packed_left_extents
                 .iter()
                 .zip(left_packed_right_extents.iter()),
@@ -420,7 +439,9 @@ pub fn skim_line(clues: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Resul
 
         // TODO: this seems to still be necessary, despite the background inference below!
         // Figure out why.
-        if (*right_extent as i16 - *left_extent as i16) + 1 == clue.count as i16 {
+        if clue.length.is_exact()
+            && (*right_extent as i16 - *left_extent as i16) + 1 == clue.count as i16
+        {
             if gap_before {
                 learn_cell(BACKGROUND, &mut lane, left_extent - 1, &mut affected).context("gb")?
             }
@@ -431,14 +452,17 @@ pub fn skim_line(clues: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Resul
     }
 
     // TODO: `packed_extents` should just return both extents of each block.
+    let len = lane.len();
+    // A block of uncertain length might be as long as its maximum, so these are bounds, not the
+    // actual far ends of the packed blocks.
     let right_packed_right_extents = right_packed_left_extents
         .iter()
         .zip(clues.iter())
-        .map(|(extent, clue)| extent + clue.count as usize - 1);
+        .map(|(extent, clue)| std::cmp::min(extent + clue.max_count(len), len) - 1);
     let left_packed_left_extents = left_packed_right_extents
         .iter()
         .zip(clues.iter())
-        .map(|(extent, clue)| extent + 1 - clue.count as usize);
+        .map(|(extent, clue)| (extent + 1).saturating_sub(clue.max_count(len)));
 
     // Similarly, are there squares between adjacent blocks that can't be hit (must be background)?
     // I learned you can do this from `pbnsolve`.
@@ -453,9 +477,11 @@ pub fn skim_line(clues: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Resul
         }
     }
 
-    let leftmost = left_packed_right_extents[0] as i16 - clues[0].count as i16;
-    let rightmost =
-        right_packed_left_extents.last().unwrap() + clues.last().unwrap().count as usize;
+    let leftmost = left_packed_right_extents[0] as i16 - clues[0].max_count(lane.len()) as i16;
+    let rightmost = std::cmp::min(
+        right_packed_left_extents.last().unwrap() + clues.last().unwrap().max_count(lane.len()),
+        lane.len(),
+    );
 
     for i in 0..=leftmost {
         learn_cell(BACKGROUND, &mut lane, i as usize, &mut affected).context("lopen")?
@@ -503,7 +529,108 @@ pub fn skim_heuristic(clues: &[Clue], lane: ArrayView1<Cell>) -> i32 {
     (total_clue_length + longest_clue) as i32 - longest_foregroundable_span + edge_bonus
 }
 
-pub fn scrub_line(cs: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Result<ScrubReport> {
+/// `fits[i][p]`: whether the first `i` of `clues` can be placed in the first `p` cells of `lane`,
+/// with everything else in those cells being background.
+fn prefix_fits(clues: &[Clue], separate_blocks: bool, lane: &[Cell]) -> Vec<Vec<bool>> {
+    let mut fits = vec![vec![false; lane.len() + 1]; clues.len() + 1];
+    fits[0][0] = true;
+    for p in 1..=lane.len() {
+        fits[0][p] = fits[0][p - 1] && lane[p - 1].can_be(BACKGROUND);
+    }
+    for i in 1..=clues.len() {
+        let count = clues[i - 1].count as usize;
+        for p in 1..=lane.len() {
+            fits[i][p] = (fits[i][p - 1] && lane[p - 1].can_be(BACKGROUND))
+                || (p >= count
+                    && block_fits(clues, i - 1, p - count, separate_blocks, lane, &fits));
+        }
+    }
+    fits
+}
+
+/// Whether the block for `clues[idx]` can start at `start`, given `fits` (from `prefix_fits`) for
+/// the clues before it.
+fn block_fits(
+    clues: &[Clue],
+    idx: usize,
+    start: usize,
+    separate_blocks: bool,
+    lane: &[Cell],
+    fits: &[Vec<bool>],
+) -> bool {
+    let clue = &clues[idx];
+    let end = start + clue.count as usize;
+    if end > lane.len() || !lane[start..end].iter().all(|c| c.can_be(clue.color)) {
+        return false;
+    }
+    if idx > 0 && clues[idx - 1].must_be_separated_from(clue, separate_blocks) {
+        start > 0 && lane[start - 1].can_be(BACKGROUND) && fits[idx][start - 1]
+    } else {
+        fits[idx][start]
+    }
+}
+
+/// Which of `clues` are definitely done in `lane`: there's only one place its block can go, and
+/// all of those cells are known to be its color. (For crossing off clues as a player fills them
+/// in.) Fails if the clues can't fit at all. Clues that are ranges or blots never count as done.
+pub fn placed_clues(
+    clues: &[Clue],
+    separate_blocks: bool,
+    lane: ArrayView1<Cell>,
+) -> anyhow::Result<Vec<bool>> {
+    if clues.iter().any(|c| !c.length.is_exact()) {
+        // Still worth finding out whether the lane is possible at all.
+        skim_line(clues, separate_blocks, lane.to_owned().view_mut())?;
+        return Ok(vec![false; clues.len()]);
+    }
+    let len = lane.len();
+    let forward: Vec<Cell> = lane.iter().copied().collect();
+    let backward: Vec<Cell> = lane.iter().rev().copied().collect();
+    let rev_clues: Vec<Clue> = clues.iter().rev().copied().collect();
+
+    let fits = prefix_fits(clues, separate_blocks, &forward);
+    if !fits[clues.len()][len] {
+        bail!("The clues don't fit.")
+    }
+    let rev_fits = prefix_fits(&rev_clues, separate_blocks, &backward);
+
+    Ok(clues
+        .iter()
+        .enumerate()
+        .map(|(idx, clue)| {
+            let count = clue.count as usize;
+            let mut starts = (0..=len.saturating_sub(count)).filter(|&start| {
+                block_fits(clues, idx, start, separate_blocks, &forward, &fits)
+                    && block_fits(
+                        &rev_clues,
+                        clues.len() - 1 - idx,
+                        len - start - count,
+                        separate_blocks,
+                        &backward,
+                        &rev_fits,
+                    )
+            });
+            match (starts.next(), starts.next()) {
+                (Some(start), None) => {
+                    (start..start + count).all(|i| forward[i].is_known_to_be(clue.color))
+                }
+                _ => false,
+            }
+        })
+        .collect())
+}
+
+pub fn scrub_line(
+    cs: &[Clue],
+    separate_blocks: bool,
+    mut lane: ArrayViewMut1<Cell>,
+) -> anyhow::Result<ScrubReport> {
+    // Enumerating arrangements assumes exact lengths. Clues that are ranges or blots get only the
+    // conservative treatment.
+    if cs.iter().any(|c| !c.length.is_exact()) {
+        return skim_line(cs, separate_blocks, lane);
+    }
+
     let mut possibilities_lane: Vec<Cell> = vec![Cell::new_impossible(); lane.len()];
 
     let dimension = lane.len() as u16;
@@ -514,8 +641,9 @@ pub fn scrub_line(cs: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Result<
     for gaps in PossibleArrangements::new(cs.len() as u16, bg_sq) {
         let mut arrangement_impossible = false;
         for i in 1..cs.len() {
-            if cs[i - 1].color == cs[i].color && gaps[i] == 0 {
-                // Adjacent blocks of the same color need at least one space of separation
+            if cs[i - 1].must_be_separated_from(&cs[i], separate_blocks) && gaps[i] == 0 {
+                // Adjacent blocks of the same color (or any blocks, with `separate_blocks`) need
+                // at least one space of separation
                 arrangement_impossible = true;
             }
         }
@@ -562,21 +690,21 @@ pub fn scrub_line(cs: &[Clue], mut lane: ArrayViewMut1<Cell>) -> anyhow::Result<
     Ok(res)
 }
 
-pub fn scrub_heuristic(clues: &[Clue], lane: ArrayView1<Cell>) -> i32 {
+pub fn scrub_heuristic(clues: &[Clue], separate_blocks: bool, lane: ArrayView1<Cell>) -> i32 {
     let mut foreground_cells: i32 = 0;
     // If `space_taken == lane.len()`, the line is immediately solvable with no other knowledge.
     let mut space_taken: i32 = 0;
     let mut longest_clue: i32 = 0;
-    let mut last_color = None;
+    let mut last_clue: Option<&Clue> = None;
     for c in clues {
         foreground_cells += c.count as i32;
         space_taken += c.count as i32;
-        if last_color == Some(c.color) {
+        if last_clue.is_some_and(|last| last.must_be_separated_from(c, separate_blocks)) {
             space_taken += 1;
         }
 
         longest_clue = std::cmp::max(longest_clue, c.count as i32);
-        last_color = Some(c.color);
+        last_clue = Some(c);
     }
     let longest_clue = longest_clue;
     let space_taken = space_taken;
@@ -626,7 +754,7 @@ fn arrangement_test() {
     let r = Color(1);
     let g = Color(2);
 
-    let clues = vec![Clue { color: r, count: 2 }, Clue { color: g, count: 3 }];
+    let clues = vec![Clue::new(r, 2), Clue::new(g, 3)];
 
     let gaps_1 = vec![0, 0];
     let arr_1 = Arrangement::new(&clues, &gaps_1, 10);
@@ -678,7 +806,8 @@ macro_rules! t_scrub {
         {
             let mut initial = ndarray::arr1(&[ $($state),* ]);
             scrub_line(
-                &vec![ $( Clue { color: $color.unwrap_color(), count: $count} ),* ],
+                &vec![ $( Clue::new($color.unwrap_color(), $count) ),* ],
+                false,
                 initial.rows_mut().into_iter().next().unwrap())
                     .expect("impossible!");
             initial
@@ -691,7 +820,8 @@ macro_rules! t_skim {
         {
             let mut initial = ndarray::arr1(&[ $($state),* ]);
             skim_line(
-                &vec![ $( Clue { color: $color.unwrap_color(), count: $count} ),* ],
+                &vec![ $( Clue::new($color.unwrap_color(), $count) ),* ],
+                false,
                 initial.rows_mut().into_iter().next().unwrap())
                     .expect("impossible!");
             initial
@@ -775,12 +905,113 @@ fn skim_test() {
     assert_eq!(t_skim!([r, 2; b, 2]  x, x, x, x, x), t_line!(x, r, x, b, x));
 }
 
+#[test]
+fn separate_blocks_test() {
+    let x = Cell::new_anything();
+    let w = Cell::from_color(Color(0));
+    let b = Cell::from_color(Color(1));
+    let r = Cell::from_color(Color(2));
+    let clues = [Clue::new(Color(2), 2), Clue::new(Color(1), 2)];
+
+    // With the "blocks may not touch" rule, different colors need separation too.
+    let mut lane = ndarray::arr1(&[x, x, x, x, x]);
+    skim_line(&clues, true, lane.rows_mut().into_iter().next().unwrap()).unwrap();
+    assert_eq!(lane, t_line!(r, r, w, b, b));
+
+    let mut lane = ndarray::arr1(&[x, x, x, x, x]);
+    scrub_line(&clues, true, lane.rows_mut().into_iter().next().unwrap()).unwrap();
+    assert_eq!(lane, t_line!(r, r, w, b, b));
+}
+
+#[test]
+fn placed_clues_test() {
+    let x = Cell::new_anything();
+    let w = Cell::from_color(Color(0));
+    let b = Cell::from_color(Color(1));
+    let clues = [Clue::new(Color(1), 2), Clue::new(Color(1), 1)];
+
+    // The 2 can only be at the start, but the 1 could be in either of the last two cells.
+    let lane = ndarray::arr1(&[b, b, w, x, x]);
+    assert_eq!(
+        placed_clues(&clues, false, lane.view()).unwrap(),
+        vec![true, false]
+    );
+    let lane = ndarray::arr1(&[b, b, w, w, b]);
+    assert_eq!(
+        placed_clues(&clues, false, lane.view()).unwrap(),
+        vec![true, true]
+    );
+    // Nothing is known, so nothing is done, even though there's only one place for the blocks.
+    let lane = ndarray::arr1(&[x, x, x, x]);
+    assert_eq!(
+        placed_clues(&clues, false, lane.view()).unwrap(),
+        vec![false, false]
+    );
+    // Too much filled in.
+    let lane = ndarray::arr1(&[b, b, b, x, x]);
+    assert!(placed_clues(&clues, false, lane.view()).is_err());
+    assert!(placed_clues(&[], false, lane.view()).is_err());
+
+    // Long, mostly-unknown lanes shouldn't take forever.
+    let clues = vec![Clue::new(Color(1), 1); 20];
+    let mut lane = ndarray::Array1::from_elem(80, x);
+    lane[0] = b;
+    lane[1] = w;
+    let done = placed_clues(&clues, false, lane.view()).unwrap();
+    assert!(done[0] && !done[1]);
+}
+
+#[test]
+fn uncertain_length_test() {
+    use crate::puzzle::Length;
+    let x = Cell::new_anything();
+    let w = Cell::from_color(Color(0));
+    let b = Cell::from_color(Color(1));
+    let range = Clue {
+        length: Length::AtMost(4),
+        ..Clue::new(Color(1), 3)
+    };
+    let blot = Clue {
+        length: Length::Blotted,
+        ..Clue::new(Color(1), 2)
+    };
+
+    let mut lane = ndarray::arr1(&[x, x, x, x, x]);
+    skim_line(&[range], false, lane.rows_mut().into_iter().next().unwrap()).unwrap();
+    assert_eq!(lane, t_line!(x, x, b, x, x));
+
+    // An exact 2 would have to start at 3, but the blot could reach all the way back.
+    let mut lane = ndarray::arr1(&[x, x, x, x, b, x]);
+    skim_line(&[blot], false, lane.rows_mut().into_iter().next().unwrap()).unwrap();
+    assert_eq!(lane, t_line!(x, x, x, x, b, x));
+
+    // Nor is the block known to be complete.
+    let mut lane = ndarray::arr1(&[w, b, b, x, x]);
+    scrub_line(&[blot], false, lane.rows_mut().into_iter().next().unwrap()).unwrap();
+    assert_eq!(lane, t_line!(w, b, b, x, x));
+}
+
+#[test]
+fn skim_contradiction_test() {
+    let x = Cell::new_anything();
+    let b = Cell::from_color(Color(1));
+
+    let mut too_short = ndarray::arr1(&[x, x]);
+    assert!(skim_line(
+        &[Clue::new(b.unwrap_color(), 3)],
+        false,
+        too_short.rows_mut().into_iter().next().unwrap()
+    )
+    .is_err());
+}
+
 macro_rules! t_heur {
     ([$($color:expr, $count:expr);*] $($state:expr),*) => {
         {
             let initial = ndarray::arr1(&[ $($state),* ]);
             scrub_heuristic(
-                &vec![ $( Clue { color: $color.unwrap_color(), count: $count} ),* ],
+                &vec![ $( Clue::new($color.unwrap_color(), $count) ),* ],
+                false,
                 initial.rows().into_iter().next().unwrap())
         }
     };
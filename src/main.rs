@@ -1,15 +1,32 @@
 extern crate clap;
 extern crate image;
 
-mod export;
-mod grid_solve;
-mod import;
-mod line_solve;
-mod puzzle;
-use std::{io::Read, path::PathBuf};
+mod debug_solve;
+mod inline_image;
+mod play;
 
-use clap::Parser;
-use import::webpbn_to_puzzle;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::{CommandFactory, Parser, ValueEnum};
+use convert_nonogram::{
+    error::Lane,
+    export, generate, grid_solve, import,
+    import::webpbn_to_puzzle,
+    mosaic,
+    puzzle::{self, Document, Metadata, Puzzle, Solution},
+    verbosity::{self, Verbosity},
+};
+use image::GenericImageView;
+use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use rand::{rngs::StdRng, SeedableRng};
 
 #[derive(Clone, Copy, Debug, clap::ValueEnum, Default, PartialEq, Eq)]
 enum NonogramFormat {
@@ -19,104 +36,3285 @@ enum NonogramFormat {
     Image,
     /// The widely-used format associated with http://webpbn.com.
     Webpbn,
-    /// (Export-only.) The format used by the 'olsak' solver.
+    /// The format used by the 'olsak' solver. Also allows clues that are ranges (`3-5a`) or blots
+    /// (`3+a`, `?a`).
     Olsak,
     /// A grid of characters. Attempts some sensible matching of characters to colors, but results
     /// will vary.
     CharGrid,
     /// (Export-only.) An HTML representation of a puzzle.
     Html,
+    /// This program's own format: the clues as JSON, along with all of the metadata.
+    Json,
+    /// (Import-only.) An SVG drawing, rasterized at `--svg-width` by `--svg-height` cells.
+    Svg,
+}
+
+impl NonogramFormat {
+    /// File extensions for this format; the first is used when naming output files.
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            NonogramFormat::Image => &[
+                "png", "gif", "bmp", "jpg", "jpeg", "tif", "tiff", "webp", "ico", "tga",
+            ],
+            NonogramFormat::Webpbn => &["xml", "pbn"],
+            NonogramFormat::Olsak => &["g"],
+            NonogramFormat::CharGrid => &["txt"],
+            NonogramFormat::Html => &["html", "htm"],
+            NonogramFormat::Json => &["json"],
+            NonogramFormat::Svg => &["svg"],
+        }
+    }
+
+    /// The name used on the command line (and by `import::from_bytes` and `export::to_bytes`).
+    fn name(self) -> String {
+        self.to_possible_value().unwrap().get_name().to_string()
+    }
+
+    fn capabilities(self) -> FormatCapabilities {
+        let name = self.name();
+        let (import, export, solution) = match self {
+            NonogramFormat::Image => (true, true, true),
+            NonogramFormat::Webpbn => (true, true, false),
+            NonogramFormat::Olsak => (true, true, false),
+            NonogramFormat::CharGrid => (true, true, true),
+            NonogramFormat::Html => (false, true, false),
+            NonogramFormat::Json => (true, true, false),
+            NonogramFormat::Svg => (true, false, true),
+        };
+        FormatCapabilities {
+            name,
+            extensions: self.extensions().to_vec(),
+            import,
+            export,
+            solution,
+            metadata: matches!(
+                self,
+                NonogramFormat::Image
+                    | NonogramFormat::Webpbn
+                    | NonogramFormat::Html
+                    | NonogramFormat::Json
+            ),
+            rules: match self {
+                // Transparent areas become the outline.
+                NonogramFormat::Svg => vec![puzzle::Rule::Outline],
+                _ => export::rules_supported(&self.name()).to_vec(),
+            },
+        }
+    }
+}
+
+/// What a format can do, for `formats`.
+#[derive(serde::Serialize)]
+struct FormatCapabilities {
+    name: String,
+    extensions: Vec<&'static str>,
+    import: bool,
+    export: bool,
+    /// Whether the format stores the picture itself, not just the clues.
+    solution: bool,
+    /// Whether the format stores a title, author, etc. (For images, only PNGs do.)
+    metadata: bool,
+    /// The rule variants it can write (or, for import-only formats, read).
+    rules: Vec<puzzle::Rule>,
+}
+
+/// Exit statuses, so that scripts can tell outcomes apart without scraping stderr.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Any failure not covered below (I/O errors, bad arguments...).
+    OtherError = 1,
+    /// Line logic got stuck before determining every cell.
+    NotLineSolvable = 2,
+    /// There's more than one solution. (Reserved: detecting this needs a search solver.)
+    #[allow(dead_code)]
+    MultipleSolutions = 3,
+    /// The clues contradict each other.
+    Contradictory = 4,
+    /// The input couldn't be read or parsed.
+    ParseError = 5,
+    /// The solver ran out of time (see `--timeout`).
+    TimedOut = 6,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Outcome::OtherError => "error",
+            Outcome::NotLineSolvable => "cannot solve with line logic",
+            Outcome::MultipleSolutions => "multiple solutions",
+            Outcome::Contradictory => "the clues are contradictory",
+            Outcome::ParseError => "unable to read the puzzle",
+            Outcome::TimedOut => "timed out",
+        })
+    }
 }
 
+const EXIT_STATUS_HELP: &str = "Exit status:
+  0  success (for `solve`, the puzzle is solved, and therefore unique)
+  1  other errors
+  2  the puzzle can't be solved with line logic
+  3  the puzzle has multiple solutions (reserved; not yet detected)
+  4  the clues are contradictory
+  5  the input couldn't be read or parsed
+  6  the solver timed out";
+
 #[derive(clap::Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_help = EXIT_STATUS_HELP)]
 struct Args {
-    /// Input path; use "-" for stdin
-    input_path: PathBuf,
+    #[command(subcommand)]
+    command: Command,
 
-    /// Output path for format conversion; use "-" for stdout.
-    /// If omitted, solves the nonogram and reports on the difficulty.
-    output_path: Option<PathBuf>,
+    /// Only print errors (and requested output).
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print more detail, like per-lane solver statistics.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// How many files to work on at once in batch operations; defaults to the number of cores.
+    /// 1 processes them one at a time, in order.
+    #[arg(short = 'j', long, global = true, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+
+    /// Whether to use color (and underlining) in output to the terminal.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    color: ColorMode,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Convert a nonogram from one format to another.
+    Convert(ConvertArgs),
+    /// Solve a nonogram and report on its difficulty.
+    Solve(SolveArgs),
+    /// Solve every puzzle in some directories and write a table of their difficulty.
+    Report(ReportArgs),
+    /// List the supported formats and what each one can represent.
+    Formats(FormatsArgs),
+    /// Render a small preview image of every puzzle in some directories.
+    Thumbs(ThumbsArgs),
+    /// Make a browsable web page of the puzzles in some directories, with a page for each one.
+    Gallery(GalleryArgs),
+    /// Bundle several puzzles into one `.loompack` file, sharing their palettes.
+    Bundle(BundleArgs),
+    /// Write each puzzle in a `.loompack` file out on its own.
+    Unbundle(UnbundleArgs),
+    /// Import a puzzle's picture again, with the same settings or new ones.
+    Reimport(ReimportArgs),
+    /// Check a puzzle's clues for mistakes that make it impossible, without solving it.
+    Check(CheckArgs),
+    /// Generate puzzles.
+    #[command(subcommand)]
+    Gen(GenCommand),
+    /// Find the fewest cell changes that would let line logic solve a puzzle.
+    Repair(RepairArgs),
+    /// Change cells near the edges of the picture to make a puzzle harder or easier.
+    Tune(TuneArgs),
+    /// Make versions of a colored puzzle with fewer colors, and report on their difficulty.
+    Variants(VariantsArgs),
+    /// Make a Mosaic (Fill-a-Pix) puzzle from a picture, and check that it can be solved.
+    Mosaic(MosaicArgs),
+    /// Step through the solver interactively, looking at what it knows as it goes.
+    DebugSolve(DebugSolveArgs),
+    /// Solve a puzzle by hand, in the terminal.
+    Play(PlayArgs),
+    /// Print a puzzle's picture (if it has one) and clues in the terminal, in color.
+    Show(ShowArgs),
+    /// Turn a stack of same-sized pictures into the layers of a 3-D nonogram, and report on
+    /// their difficulty together.
+    Layers(LayersArgs),
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum GenCommand {
+    /// Generate a random puzzle that line logic can solve, within a range of difficulty.
+    Random(RandomArgs),
+    /// Make a puzzle from a photo (or any other image), at a smaller size and with fewer colors.
+    FromImage(FromImageArgs),
+    /// Make a puzzle from every glyph in a font, or every icon in a directory.
+    Pack(PackArgs),
+    /// Make a test pattern (which usually isn't a good puzzle), for benchmarking the solver.
+    Pattern(PatternArgs),
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, PartialEq, Eq)]
+enum PatternKind {
+    /// Horizontal bands, `--period` cells thick.
+    Stripes,
+    /// Squares, `--period` cells on a side.
+    Checkers,
+    /// Random cells, filled in with probability `--density`.
+    Noise,
+    /// Diagonals `--period` cells apart (defaulting to one diagonal), which line logic can't
+    /// solve any of.
+    WorstCase,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, Default, PartialEq, Eq)]
+enum ColorMode {
+    /// Color output to a terminal, unless the `NO_COLOR` environment variable is set.
+    #[default]
+    Auto,
+    Always,
+    /// Plain text, with colors shown by their characters.
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, Default, PartialEq, Eq)]
+enum LowContrastKind {
+    /// Draw the clue numbers in a darker shade.
+    #[default]
+    Darken,
+    /// Draw the clue numbers on a dark background.
+    Chip,
+    /// Leave them alone.
+    Keep,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, Default, PartialEq, Eq)]
+enum TraceFormat {
+    /// Lines for people to read (with `-t`).
+    #[default]
+    Text,
+    /// One JSON object per step on stdout, for other programs to follow along; everything else
+    /// goes to stderr.
+    Jsonl,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum, Default, PartialEq, Eq)]
+enum ReportFormat {
+    #[default]
+    Csv,
+    Json,
+}
 
+#[derive(clap::Args, Debug)]
+struct InputArgs {
     /// Format to expect the input to be in
     #[arg(short, long, value_enum, default_value_t)]
     input_format: NonogramFormat,
 
+    /// GIMP palette (`.gpl`) to take colors, names, and characters from. Colors are matched by
+    /// character for char grids, and by RGB value otherwise.
+    #[arg(long)]
+    palette: Option<PathBuf>,
+
+    /// Recolor as `#RRGGBB=#RRGGBB`; may be repeated. Colors that end up the same are merged.
+    #[arg(long = "map", value_name = "FROM=TO")]
+    color_map: Vec<String>,
+
+    /// File of `--map` mappings, one per line.
+    #[arg(long)]
+    map_file: Option<PathBuf>,
+
+    /// Use the "blocks may not touch" rule, where there's background between all blocks, even
+    /// ones of different colors.
+    #[arg(long)]
+    separate_blocks: bool,
+
+    /// Merge colors that are too similar to tell apart, instead of just warning about them.
+    #[arg(long)]
+    fix_similar_colors: bool,
+
+    /// Crop rows and columns of background off the edges.
+    #[arg(long)]
+    trim: bool,
+
+    /// Another panel of a multi-panel puzzle, in the same format as the input; may be repeated.
+    /// The panels go side by side, sharing their row clues, and are solved together.
+    #[arg(long = "panel", value_name = "PATH")]
+    panels: Vec<PathBuf>,
+
+    /// Stack the `--panel`s top to bottom, sharing their column clues, instead.
+    #[arg(long, requires = "panels")]
+    stack_panels: bool,
+
+    /// Cells of space between panels.
+    #[arg(long, default_value_t = 1)]
+    panel_gap: usize,
+
+    /// Columns to rasterize SVG input at; defaults to keeping the aspect ratio, or to the SVG's own
+    /// size if `--svg-height` isn't given either.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    svg_width: Option<u32>,
+
+    /// Rows to rasterize SVG input at.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    svg_height: Option<u32>,
+
+    /// Merge similar colors until there are at most this many, counting the background (which is
+    /// white, and takes a slot even if the picture has none).
+    #[arg(long, value_parser = clap::value_parser!(u8).range(2..=31))]
+    max_colors: Option<u8>,
+
+    /// Reject inputs with anything suspicious, like ragged rows or duplicate color characters.
+    #[arg(long, conflicts_with = "lenient")]
+    strict: bool,
+
+    /// Make the best of suspicious inputs (the default).
+    #[arg(long)]
+    lenient: bool,
+}
+
+impl InputArgs {
+    /// These options as they'd be written on the command line, for `puzzle::Source`. Paths are
+    /// made absolute, so that they still work from somewhere else.
+    fn as_options(&self) -> Vec<String> {
+        let absolute = |path: &PathBuf| {
+            std::path::absolute(path)
+                .unwrap_or_else(|_| path.clone())
+                .display()
+                .to_string()
+        };
+        let mut res = vec!["--input-format".to_string(), self.input_format.name()];
+        let mut flag = |name: &str, value: Option<String>| {
+            res.push(name.to_string());
+            res.extend(value);
+        };
+        if let Some(palette) = &self.palette {
+            flag("--palette", Some(absolute(palette)));
+        }
+        for mapping in &self.color_map {
+            flag("--map", Some(mapping.clone()));
+        }
+        if let Some(map_file) = &self.map_file {
+            flag("--map-file", Some(absolute(map_file)));
+        }
+        if self.separate_blocks {
+            flag("--separate-blocks", None);
+        }
+        if self.fix_similar_colors {
+            flag("--fix-similar-colors", None);
+        }
+        if self.trim {
+            flag("--trim", None);
+        }
+        if let Some(svg_width) = self.svg_width {
+            flag("--svg-width", Some(svg_width.to_string()));
+        }
+        if let Some(svg_height) = self.svg_height {
+            flag("--svg-height", Some(svg_height.to_string()));
+        }
+        if let Some(max_colors) = self.max_colors {
+            flag("--max-colors", Some(max_colors.to_string()));
+        }
+        if self.strict {
+            flag("--strict", None);
+        }
+        res
+    }
+
+    /// Reads back `as_options`.
+    fn from_options(options: &[String]) -> anyhow::Result<InputArgs> {
+        let command = <InputArgs as clap::Args>::augment_args(
+            clap::Command::new("import options").no_binary_name(true),
+        );
+        let matches = command.try_get_matches_from(options)?;
+        Ok(<InputArgs as clap::FromArgMatches>::from_arg_matches(
+            &matches,
+        )?)
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct SolverArgs {
+    /// Give up on a puzzle after this many seconds.
+    #[arg(long, value_parser = parse_timeout)]
+    timeout: Option<Duration>,
+}
+
+impl SolverArgs {
+    fn deadline(&self) -> Option<Instant> {
+        self.timeout.map(|timeout| Instant::now() + timeout)
+    }
+}
+
+fn parse_note(s: &str) -> Result<puzzle::Note, String> {
+    puzzle::Note::parse(s).ok_or_else(|| {
+        "expected \"row N, column M: ...\", \"row N: ...\", or \"column M: ...\"".to_string()
+    })
+}
+
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("{}", e))
+}
+
+/// A number from 0 to 1.
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(format!("{} isn't between 0 and 1", s));
+    }
+    Ok(fraction)
+}
+
+#[derive(clap::Args, Debug)]
+struct ConvertArgs {
+    /// Input path, then output path; use "-" for stdin/stdout. With `--output-dir`, every path is
+    /// an input instead, and directories are expanded to the files in them.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Convert each input into this directory, named after the input with the output format's
+    /// extension.
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
     /// Format to emit as output
     #[arg(short, long, value_enum, default_value_t)]
     output_format: NonogramFormat,
 
+    /// Interval between bold grid lines in HTML (and `--grid-lines` image) output; 0 turns them
+    /// off.
+    #[arg(long, default_value_t = 5)]
+    major_lines: usize,
+
+    /// What to do about clue colors in HTML output that are too light to read on white.
+    #[arg(long, value_enum, default_value_t)]
+    low_contrast: LowContrastKind,
+
+    /// Pixels per cell in image output.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: u32,
+
+    /// Draw lines between cells in image output.
+    #[arg(long)]
+    grid_lines: bool,
+
+    /// Also write the solution, to publish with the puzzle: as a second page of HTML output, or
+    /// next to output in other formats as an image named after it (e.g. `tea-answer.png` for
+    /// `tea.xml`).
+    #[arg(long)]
+    answer_key: bool,
+
+    /// Write the puzzle's palette to this path as a GIMP palette (`.gpl`).
+    #[arg(long)]
+    save_palette: Option<PathBuf>,
+
+    /// Keep running, and convert again whenever the input changes.
+    #[arg(long, conflicts_with = "output_dir")]
+    watch: bool,
+
+    /// Write the puzzle even if the output format can't express its rule variants (like blocks
+    /// that may not touch), leaving them out.
+    #[arg(long)]
+    drop_rules: bool,
+
+    /// Keep a copy of the input picture in the output (for the `json` format), so that `reimport`
+    /// still works if the original moves.
+    #[arg(long)]
+    embed_source: bool,
+
+    /// Title to record in the output, for formats that have room for it.
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Author to record in the output, for formats that have room for it.
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Copyright notice to record in the output, for formats that have room for it.
+    #[arg(long)]
+    copyright: Option<String>,
+
+    /// License the puzzle can be shared under (e.g. "CC BY-SA 4.0"), for formats that have room
+    /// for it.
+    #[arg(long)]
+    license: Option<String>,
+
+    /// A sentence or two about the puzzle, for formats that have room for it.
+    #[arg(long)]
+    description: Option<String>,
+
+    /// How hard the puzzle is, in whatever terms you like (e.g. "3 stars"), for formats that have
+    /// room for it.
+    #[arg(long)]
+    difficulty: Option<String>,
+
+    /// A comment on a cell or lane, like "row 3, column 5: only here to make the solution unique",
+    /// "row 3: ...", or "column 5: ...", for formats that have room for it. Can be given more than
+    /// once.
+    #[arg(long = "note", value_parser = parse_note)]
+    notes: Vec<puzzle::Note>,
+
+    /// Solve the puzzle, and record how many skims and scrubs it took (and how many cells line
+    /// logic couldn't determine) in the output, for formats that have room for it.
+    #[arg(long)]
+    stamp_difficulty: bool,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+}
+
+impl ConvertArgs {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.title.clone(),
+            author: self.author.clone(),
+            copyright: self.copyright.clone(),
+            license: self.license.clone(),
+            description: self.description.clone(),
+            difficulty: self.difficulty.clone(),
+            notes: self.notes.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct SolveArgs {
+    /// Input path; use "-" for stdin
+    input_path: PathBuf,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
     /// Explain the solve process line-by-line.
     #[arg(short, long, action = clap::ArgAction::SetTrue)]
     trace_solve: bool,
+
+    /// Wrap traced lanes at this many columns; defaults to the terminal's width (or no wrapping,
+    /// if the output isn't a terminal).
+    #[arg(long, requires = "trace_solve")]
+    trace_width: Option<usize>,
+
+    /// Only trace the part of each lane that changed.
+    #[arg(long, requires = "trace_solve")]
+    trace_changes_only: bool,
+
+    /// How to trace the solve; `jsonl` doesn't need `-t`.
+    #[arg(long, value_enum, default_value_t)]
+    trace_format: TraceFormat,
+
+    /// Keep running, and solve again whenever the input changes.
+    #[arg(long)]
+    watch: bool,
 }
 
-fn read_path(path: &PathBuf) -> String {
-    let mut res = String::new();
-    if path == &PathBuf::from("-") {
-        std::io::stdin()
-            .read_to_string(&mut res)
-            .expect("bad read_to_string!");
-    } else {
-        res = String::from_utf8(std::fs::read(path).unwrap()).expect("not valid UTF-8!");
-    };
-    res
+#[derive(clap::Args, Debug)]
+struct ReportArgs {
+    /// Puzzles to report on; directories are expanded to the files in them.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Format of the table
+    #[arg(long, value_enum, default_value_t)]
+    report_format: ReportFormat,
+
+    /// Where to write the table; defaults to stdout.
+    #[arg(long)]
+    output: Option<PathBuf>,
 }
 
-fn main() -> std::io::Result<()> {
-    let args = Args::parse();
+#[derive(clap::Args, Debug)]
+struct FormatsArgs {
+    /// Emit JSON instead of a table.
+    #[arg(long)]
+    json: bool,
+}
 
-    let (puzzle, solution) = match args.input_format {
-        NonogramFormat::Image => {
-            let img = image::open(args.input_path).unwrap();
+#[derive(clap::Args, Debug)]
+struct CheckArgs {
+    /// Puzzles to check; directories are expanded to the files in them.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
 
-            let solution = import::image_to_solution(&img);
+    #[command(flatten)]
+    input: InputArgs,
+}
 
-            (import::solution_to_puzzle(&solution), Some(solution))
-        }
-        NonogramFormat::Webpbn => {
-            let webpbn_string = read_path(&args.input_path);
+#[derive(clap::Args, Debug)]
+struct RandomArgs {
+    /// Output path; use "-" for stdout.
+    #[arg(default_value = "-")]
+    output_path: PathBuf,
 
-            let puzzle = webpbn_to_puzzle(&webpbn_string);
+    /// Make this many puzzles, rather than one.
+    #[arg(long, default_value_t = 1, requires = "output_dir")]
+    count: usize,
 
-            (puzzle, None)
-        }
-        NonogramFormat::CharGrid => {
-            let grid_string = read_path(&args.input_path);
+    /// Write the puzzles into this directory, numbered, instead of to the output path.
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
 
-            let solution = import::char_grid_to_solution(&grid_string);
+    /// Format to emit as output
+    #[arg(short, long, value_enum, default_value_t = NonogramFormat::Webpbn)]
+    output_format: NonogramFormat,
 
-            (import::solution_to_puzzle(&solution), Some(solution))
-        }
-        _ => todo!(),
-    };
+    #[command(flatten)]
+    solver: SolverArgs,
 
-    match args.output_path {
-        Some(path) => {
-            if args.output_format == NonogramFormat::Image {
-                export::emit_image(&solution.unwrap(), path).unwrap();
-            } else {
-                let output_data = match args.output_format {
-                    NonogramFormat::Olsak => export::as_olsak(&puzzle),
-                    NonogramFormat::Webpbn => export::as_webpbn(&puzzle),
-                    NonogramFormat::Html => export::as_html(&puzzle),
-                    NonogramFormat::Image => panic!(),
-                    _ => {
-                        todo!()
-                    }
-                };
-                if path == PathBuf::from("-") {
-                    print!("{}", output_data);
-                } else {
-                    std::fs::write(path, output_data)?;
-                }
-            }
-        }
+    /// Columns in the puzzle.
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u16).range(1..))]
+    width: u16,
 
-        None => {
-            grid_solve::solve(&puzzle, args.trace_solve).unwrap();
-        }
-    }
+    /// Rows in the puzzle.
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u16).range(1..))]
+    height: u16,
 
-    Ok(())
+    /// Number of colors, not counting the background.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=generate::MAX_COLORS as i64))]
+    colors: u8,
+
+    /// Fraction of cells to fill in.
+    #[arg(long, default_value_t = 0.5, value_parser = parse_fraction)]
+    density: f64,
+
+    /// Average number of cells in each patch of color; 1 scatters cells independently.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    blob_size: u16,
+
+    /// Relative share of the filled cells for each color, like "3,1,1"; by default they're
+    /// equal.
+    #[arg(long, value_delimiter = ',')]
+    color_weights: Vec<f64>,
+
+    /// Fewest skims (passes of the simple line solver) the puzzle may take.
+    #[arg(long, default_value_t = 0)]
+    min_skims: usize,
+
+    /// Most skims the puzzle may take.
+    #[arg(long, default_value_t = usize::MAX, hide_default_value = true)]
+    max_skims: usize,
+
+    /// Fewest scrubs (passes of the thorough line solver) the puzzle may take.
+    #[arg(long, default_value_t = 0)]
+    min_scrubs: usize,
+
+    /// Most scrubs the puzzle may take.
+    #[arg(long, default_value_t = usize::MAX, hide_default_value = true)]
+    max_scrubs: usize,
+
+    /// Give up (on each puzzle) after trying this many pictures.
+    #[arg(long, default_value_t = 1000)]
+    attempts: usize,
+
+    /// Seed for the random number generator, to reproduce an earlier run.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+struct FromImageArgs {
+    /// Input image; use "-" for stdin.
+    input_path: PathBuf,
+
+    /// Output path; use "-" for stdout.
+    #[arg(default_value = "-")]
+    output_path: PathBuf,
+
+    /// Format to emit as output
+    #[arg(short, long, value_enum, default_value_t = NonogramFormat::Webpbn)]
+    output_format: NonogramFormat,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Columns in the puzzle.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: u32,
+
+    /// Rows in the puzzle; defaults to keeping the image's aspect ratio.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    height: Option<u32>,
+
+    /// Number of colors, counting the background.
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(2..=31))]
+    colors: u8,
+
+    /// Dither, so that shading survives the reduction in colors.
+    #[arg(long)]
+    dither: bool,
+
+    /// If line logic can't solve the puzzle, edit up to this many cells to try to fix that.
+    #[arg(long, default_value_t = 0)]
+    repair: usize,
+}
+
+#[derive(clap::Args, Debug)]
+struct RepairArgs {
+    /// Input path; use "-" for stdin. The input needs a solution (so, an image or char grid).
+    input_path: PathBuf,
+
+    /// Write the repaired puzzle here, in addition to listing the changes.
+    output_path: Option<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Format to emit the repaired puzzle as
+    #[arg(short, long, value_enum, default_value_t)]
+    output_format: NonogramFormat,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Most cells to change.
+    #[arg(long, default_value_t = 3)]
+    max_edits: usize,
+
+    /// How many cells to consider changing together, picked from the ones the solver can't
+    /// determine. The search takes time proportional to this to the power of `--max-edits`.
+    #[arg(long, default_value_t = 20)]
+    candidates: usize,
+
+    /// Also write the changes here as CSV, for scripts: where each one is, the characters of the
+    /// colors it changes from and to, and how many cells line logic would leave undetermined with
+    /// only that change. (`-o char-grid` writes the repaired picture in a form that diffs well.)
+    #[arg(long)]
+    edits: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+struct MosaicArgs {
+    /// Input path; use "-" for stdin. The input needs a solution (so, an image or char grid).
+    input_path: PathBuf,
+
+    /// Output path for the clues, as text; use "-" for stdout.
+    #[arg(default_value = "-")]
+    output_path: PathBuf,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Leave out clues that aren't needed to solve it.
+    #[arg(long)]
+    minimize: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct DebugSolveArgs {
+    /// Input path. (Not stdin, which is where the commands come from.)
+    input_path: PathBuf,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct ShowArgs {
+    /// Input path; use "-" for stdin.
+    input_path: PathBuf,
+
+    /// How to draw the picture: as an actual image, in terminals that can show one, or as
+    /// colored text.
+    #[arg(long, value_enum, default_value_t = inline_image::Graphics::Auto)]
+    graphics: inline_image::Graphics,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct PlayArgs {
+    /// Input path. Clue-only formats work if line logic can solve them, since the solver's answer
+    /// is what your marks are checked against.
+    input_path: PathBuf,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Take back wrong marks as soon as you make them, and count them as mistakes.
+    #[arg(long)]
+    auto_check: bool,
+
+    /// Don't use the record next to the puzzle (`<input>.play.json`), which keeps how each solve
+    /// went and where you left off if you quit partway.
+    #[arg(long)]
+    no_record: bool,
+
+    /// Start over, instead of picking up where you left off last time.
+    #[arg(long)]
+    restart: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct LayersArgs {
+    /// The layers, from the bottom up; directories are expanded to the files in them, in order
+    /// by name.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Directory to write a clue sheet for each layer into, named by its number.
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Format to write the clue sheets in
+    #[arg(short, long, value_enum, default_value_t = NonogramFormat::Html)]
+    output_format: NonogramFormat,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group = clap::ArgGroup::new("target").required(true).multiple(true))]
+struct TuneArgs {
+    /// Input path; use "-" for stdin. The input needs a solution (so, an image or char grid).
+    input_path: PathBuf,
+
+    /// Write the tuned puzzle here, in addition to listing the changes.
+    output_path: Option<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Format to emit the tuned puzzle as
+    #[arg(short, long, value_enum, default_value_t)]
+    output_format: NonogramFormat,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Number of skims to aim for.
+    #[arg(long, group = "target")]
+    target_skims: Option<usize>,
+
+    /// Number of scrubs to aim for.
+    #[arg(long, group = "target")]
+    target_scrubs: Option<usize>,
+
+    /// How far from the edges of the picture cells may change; 0 allows only the cells right on an
+    /// edge (next to a cell that differs in being background).
+    #[arg(long, default_value_t = 1)]
+    tolerance: usize,
+
+    /// Number of changes to try.
+    #[arg(long, default_value_t = 500)]
+    iterations: usize,
+
+    /// Seed for the random number generator, to reproduce an earlier run.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+struct PackArgs {
+    /// Fonts (`.ttf` or `.otf`), icons (images or SVGs), and directories of them.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Directory to write the puzzles and `index.csv` (which lists their difficulty) into.
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Format to write the puzzles in
+    #[arg(short, long, value_enum, default_value_t = NonogramFormat::Webpbn)]
+    output_format: NonogramFormat,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Maximum width or height of a puzzle, in cells.
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+    size: u32,
+
+    /// Number of colors in icon puzzles, counting the background. (Glyphs are always black and
+    /// white.)
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(2..=31))]
+    colors: u8,
+
+    /// Characters to take from fonts.
+    #[arg(
+        long,
+        default_value = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789"
+    )]
+    chars: String,
+
+    /// Write puzzles that line logic can't solve, too, rather than just listing them in the
+    /// index.
+    #[arg(long)]
+    keep_ambiguous: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct PatternArgs {
+    #[arg(value_enum)]
+    kind: PatternKind,
+
+    /// Output path; use "-" for stdout.
+    #[arg(default_value = "-")]
+    output_path: PathBuf,
+
+    /// Format to emit as output
+    #[arg(short, long, value_enum, default_value_t = NonogramFormat::Webpbn)]
+    output_format: NonogramFormat,
+
+    /// Columns in the puzzle.
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u16).range(1..))]
+    width: u16,
+
+    /// Rows in the puzzle.
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u16).range(1..))]
+    height: u16,
+
+    /// Number of colors, not counting the background.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=generate::MAX_COLORS as i64))]
+    colors: u8,
+
+    /// Size of the repeating unit of stripes, checkers, and worst-case patterns.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    period: Option<u16>,
+
+    /// Fraction of cells to fill in, for noise.
+    #[arg(long, default_value_t = 0.5, value_parser = parse_fraction)]
+    density: f64,
+
+    /// Seed for the random number generator, for noise.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+struct VariantsArgs {
+    /// Input path; use "-" for stdin. The input needs a solution (so, an image or char grid).
+    input_path: PathBuf,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Directory to write the variants into, named after the input and their number of colors.
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Format to write the variants in
+    #[arg(short, long, value_enum, default_value_t)]
+    output_format: NonogramFormat,
+}
+
+#[derive(clap::Args, Debug)]
+struct ThumbsArgs {
+    /// Puzzles to preview; directories are expanded to the files in them.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Directory to write the previews (as PNGs named after the inputs) into.
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Maximum width or height of a preview, in pixels.
+    #[arg(long, default_value_t = 64)]
+    size: u32,
+
+    /// Average the picture into blocks this many cells wide, to avoid spoilers.
+    #[arg(long)]
+    pixelate: Option<u32>,
+
+    /// Blur the preview with this sigma (in preview pixels), to avoid spoilers.
+    #[arg(long)]
+    blur: Option<f32>,
+}
+
+#[derive(clap::Args, Debug)]
+struct GalleryArgs {
+    /// Puzzles to include; directories are expanded to the files in them.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    #[command(flatten)]
+    solver: SolverArgs,
+
+    /// Directory to write `index.html`, the puzzles' pages, and their previews into.
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Heading for the index page.
+    #[arg(long, default_value = "Nonograms")]
+    title: String,
+
+    /// Maximum width or height of a preview, in pixels.
+    #[arg(long, default_value_t = 128)]
+    size: u32,
+
+    /// Average the previews into blocks this many cells wide, to avoid spoilers.
+    #[arg(long)]
+    pixelate: Option<u32>,
+
+    /// Blur the previews with this sigma (in preview pixels), to avoid spoilers.
+    #[arg(long)]
+    blur: Option<f32>,
+}
+
+#[derive(clap::Args, Debug)]
+struct BundleArgs {
+    /// Puzzles to bundle, in order; directories are expanded to the files in them.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Where to write the pack; use "-" for stdout.
+    #[arg(short = 'o', long)]
+    output_path: PathBuf,
+
+    /// Title of the pack as a whole.
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Author of the pack; puzzles without their own author are taken to be by them, too.
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Copyright notice for the pack; puzzles without their own are covered by it.
+    #[arg(long)]
+    copyright: Option<String>,
+
+    /// License for the pack; puzzles without their own can be shared under it.
+    #[arg(long)]
+    license: Option<String>,
+
+    /// A sentence or two about the pack.
+    #[arg(long)]
+    description: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+struct UnbundleArgs {
+    /// The `.loompack` file; use "-" for stdin
+    input_path: PathBuf,
+
+    /// Directory to write the puzzles into, numbered in the order they're in the pack.
+    #[arg(long)]
+    output_dir: PathBuf,
+
+    /// Format to write the puzzles in
+    #[arg(short, long, value_enum, default_value = "json")]
+    output_format: NonogramFormat,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReimportArgs {
+    /// A puzzle in the `json` format, converted from a picture; use "-" for stdin
+    input_path: PathBuf,
+
+    /// Where to write the new puzzle; defaults to replacing the input.
+    output_path: Option<PathBuf>,
+
+    /// Import options to use instead of the ones recorded in the puzzle, after a `--` (e.g.
+    /// `-- --max-colors 3 --trim`).
+    #[arg(last = true)]
+    options: Vec<String>,
+}
+
+fn read_bytes(path: &PathBuf) -> anyhow::Result<Vec<u8>> {
+    let mut res = vec![];
+    if path == &PathBuf::from("-") {
+        std::io::stdin().read_to_end(&mut res)?;
+    } else {
+        res = std::fs::read(path)?;
+    };
+    Ok(res)
+}
+
+fn read_path(path: &PathBuf) -> anyhow::Result<String> {
+    String::from_utf8(read_bytes(path)?).context("not valid UTF-8")
+}
+
+fn write_path(path: &PathBuf, data: &[u8]) -> anyhow::Result<()> {
+    if path == &PathBuf::from("-") {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()?;
+    } else {
+        std::fs::write(path, data)?;
+    }
+    Ok(())
+}
+/// Describes what `to` can't represent about a puzzle read as `from`, so that conversions don't
+/// silently lose information.
+fn lossy_conversion_warnings(
+    from: NonogramFormat,
+    to: NonogramFormat,
+    puzzle: &Puzzle,
+) -> Vec<String> {
+    let mut res = vec![];
+    // Only reached with `--drop-rules`; otherwise, writing the puzzle fails.
+    let supported = export::rules_supported(&to.name());
+    for rule in puzzle.rules() {
+        let consequence = match rule {
+            puzzle::Rule::SeparateBlocks => "solvers will assume the usual rules",
+            puzzle::Rule::Outline => "they'll be ordinary background",
+            puzzle::Rule::InexactClues => "they'll be written as their minimums",
+        };
+        if !supported.contains(&rule) {
+            res.push(format!(
+                "{} output can't express {}; {}",
+                to.name(),
+                rule,
+                consequence
+            ));
+        }
+    }
+    match to {
+        NonogramFormat::Olsak => {
+            for color_info in puzzle.palette.values() {
+                let is_white = color_info.rgb == (255, 255, 255);
+                if color_info.color == puzzle::BACKGROUND && !is_white {
+                    res.push(format!(
+                        "olsak output always uses white for the background, not {:?}",
+                        color_info.rgb
+                    ));
+                } else if color_info.color != puzzle::BACKGROUND && is_white {
+                    res.push(format!(
+                        "olsak output treats white as the background, so the color '{}' will be lost",
+                        color_info.name
+                    ));
+                }
+            }
+        }
+        NonogramFormat::Image if from != NonogramFormat::Image => {
+            res.push("images don't record color names or characters".to_string());
+        }
+        NonogramFormat::CharGrid if from != NonogramFormat::CharGrid => {
+            res.push("char grids don't record color names or RGB values".to_string());
+        }
+        _ => {}
+    }
+    res
+}
+
+/// Lists which colors `--max-colors` merged into which.
+fn report_color_reduction(map: &HashMap<import::Rgb, import::Rgb>) {
+    let hex = |(r, g, b): import::Rgb| format!("#{:02X}{:02X}{:02X}", r, g, b);
+
+    let mut merged = BTreeMap::<import::Rgb, Vec<import::Rgb>>::new();
+    for (from, to) in map {
+        merged.entry(*to).or_default().push(*from);
+    }
+    if verbosity::get() < Verbosity::Normal || merged.len() == map.len() {
+        return;
+    }
+
+    eprintln!("Reduced {} colors to {}:", map.len(), merged.len());
+    for (to, mut froms) in merged {
+        froms.sort();
+        let shown = if verbosity::get() >= Verbosity::Verbose {
+            froms.len()
+        } else {
+            5
+        };
+        let mut line: Vec<String> = froms.iter().take(shown).map(|rgb| hex(*rgb)).collect();
+        if froms.len() > shown {
+            line.push(format!("and {} more", froms.len() - shown));
+        }
+        eprintln!("  {} <- {}", hex(to), line.join(" "));
+    }
+}
+
+/// Lists which colors `--fix-similar-colors` merged, as `--map` arguments, so that the same fix
+/// can be made elsewhere (or in the source image).
+fn report_similar_color_fixes(merges: &[(import::Rgb, import::Rgb)]) {
+    if verbosity::get() < Verbosity::Normal {
+        return;
+    }
+    let hex = |(r, g, b): import::Rgb| format!("#{:02X}{:02X}{:02X}", r, g, b);
+    eprintln!("Merged {} similar colors:", merges.len());
+    for (from, to) in merges {
+        eprintln!("  --map '{}={}'", hex(*from), hex(*to));
+    }
+}
+
+fn load(input_path: &PathBuf, args: &InputArgs) -> anyhow::Result<Document> {
+    load_unclassified(input_path, args).context(Outcome::ParseError)
+}
+
+fn load_unclassified(input_path: &PathBuf, args: &InputArgs) -> anyhow::Result<Document> {
+    let preset = match &args.palette {
+        Some(palette_path) => Some(import::gpl_to_palette(&read_path(palette_path)?)?),
+        None => None,
+    };
+
+    let (mut puzzle, mut solution, metadata) = read_input(input_path, args, &preset)?;
+
+    if !args.panels.is_empty() {
+        let no_solution = || anyhow!("--panel requires inputs with a solution, not just clues");
+        let mut panels = vec![solution.ok_or_else(no_solution)?];
+        for path in &args.panels {
+            panels.push(read_input(path, args, &preset)?.1.ok_or_else(no_solution)?);
+        }
+        let joined = import::join_panels(&panels, args.stack_panels, args.panel_gap)?;
+        puzzle = import::solution_to_puzzle(&joined)?;
+        solution = Some(joined);
+    }
+
+    let mut color_map = vec![];
+    if let Some(map_path) = &args.map_file {
+        color_map.extend(import::parse_color_map(&read_path(map_path)?)?);
+    }
+    for mapping in &args.color_map {
+        color_map.push(import::parse_color_mapping(mapping)?);
+    }
+    if !color_map.is_empty() {
+        match solution.as_mut() {
+            Some(solution) => {
+                import::remap_colors(solution, &color_map);
+                puzzle = import::solution_to_puzzle(solution)?;
+            }
+            None => import::remap_puzzle_colors(&mut puzzle, &color_map)?,
+        }
+    }
+
+    if args.trim {
+        match solution.as_mut() {
+            Some(solution) => {
+                import::trim_solution(solution);
+                puzzle = import::solution_to_puzzle(solution)?;
+            }
+            None => import::trim_puzzle(&mut puzzle),
+        }
+    }
+
+    if let Some(preset) = &preset {
+        let by_char = args.input_format == NonogramFormat::CharGrid;
+
+        import::apply_palette_preset(&mut puzzle.palette, preset, by_char);
+        if let Some(solution) = solution.as_mut() {
+            import::apply_palette_preset(&mut solution.palette, preset, by_char);
+        }
+    }
+
+    // After the palette, since that can change which colors are similar.
+    if args.fix_similar_colors {
+        if let Some(solution) = solution.as_mut() {
+            let merges = import::fix_similar_colors(solution);
+            if !merges.is_empty() {
+                report_similar_color_fixes(&merges);
+                puzzle = import::solution_to_puzzle(solution)?;
+            }
+        }
+    }
+
+    if args.separate_blocks {
+        if let Some(solution) = &solution {
+            let touching = import::touching_blocks(solution);
+            if let Some((x, y)) = touching.first() {
+                bail!(
+                    "{} cells touch a block of another color, which --separate-blocks doesn't \
+                     allow (the first is at row {}, column {})",
+                    touching.len(),
+                    y + 1,
+                    x + 1
+                );
+            }
+        }
+        puzzle.separate_blocks = true;
+    }
+
+    if let Some(solution) = &solution {
+        import::quality_check(solution).print();
+    }
+
+    let mut document = Document::new(puzzle, solution, &args.input_format.name());
+    document.metadata = metadata;
+    Ok(document)
+}
+
+/// Reads `input_path` as `args.input_format`, before any of the clean-up options are applied.
+fn read_input(
+    input_path: &PathBuf,
+    args: &InputArgs,
+    preset: &Option<puzzle::PalettePreset>,
+) -> anyhow::Result<(Puzzle, Option<Solution>, Metadata)> {
+    let mut metadata = Metadata::default();
+    let (puzzle, solution) = match args.input_format {
+        NonogramFormat::Image | NonogramFormat::Svg => {
+            let img = if args.input_format == NonogramFormat::Svg {
+                import::svg_to_image(&read_bytes(input_path)?, args.svg_width, args.svg_height)?
+            } else {
+                let bytes = read_bytes(input_path)?;
+                metadata = import::png_metadata(&bytes);
+                match image::ImageFormat::from_path(input_path) {
+                    Ok(format) => image::load_from_memory_with_format(&bytes, format)?,
+                    Err(_) => image::load_from_memory(&bytes)?,
+                }
+            };
+
+            let img = match args.max_colors {
+                Some(max_colors) => {
+                    let (img, map) = import::reduce_image_colors(&img, max_colors.into());
+                    report_color_reduction(&map);
+                    img
+                }
+                None => img,
+            };
+
+            let solution = import::image_to_solution(&img)?;
+
+            (import::solution_to_puzzle(&solution)?, Some(solution))
+        }
+        NonogramFormat::Webpbn => {
+            let webpbn_string = read_path(input_path)?;
+
+            if args.max_colors.is_some() {
+                bail!("--max-colors requires an input with a solution, not just clues");
+            }
+            if args.fix_similar_colors {
+                bail!("--fix-similar-colors requires an input with a solution, not just clues");
+            }
+            let puzzle = webpbn_to_puzzle(&webpbn_string, args.strict)?;
+            metadata = import::webpbn_metadata(&webpbn_string)?;
+
+            (puzzle, None)
+        }
+        NonogramFormat::Json => {
+            if args.max_colors.is_some() {
+                bail!("--max-colors requires an input with a solution, not just clues");
+            }
+            if args.fix_similar_colors {
+                bail!("--fix-similar-colors requires an input with a solution, not just clues");
+            }
+            let document = import::from_bytes(&read_bytes(input_path)?, "json")?;
+            metadata = document.metadata;
+            (document.puzzle, None)
+        }
+        NonogramFormat::Olsak => {
+            let olsak_string = read_path(input_path)?;
+
+            if args.max_colors.is_some() {
+                bail!("--max-colors requires an input with a solution, not just clues");
+            }
+            if args.fix_similar_colors {
+                bail!("--fix-similar-colors requires an input with a solution, not just clues");
+            }
+            (import::olsak_to_puzzle(&olsak_string)?, None)
+        }
+        NonogramFormat::CharGrid => {
+            let grid_string = read_path(input_path)?;
+
+            let known_chars = preset
+                .iter()
+                .flat_map(|preset| preset.colors.iter().map(|pc| pc.ch))
+                .collect();
+            let mut solution =
+                import::char_grid_to_solution(&grid_string, args.strict, &known_chars)?;
+            if let Some(max_colors) = args.max_colors {
+                report_color_reduction(&import::reduce_solution_colors(
+                    &mut solution,
+                    max_colors.into(),
+                ));
+            }
+
+            (import::solution_to_puzzle(&solution)?, Some(solution))
+        }
+        NonogramFormat::Html => {
+            bail!("{:?} is an export-only format", args.input_format)
+        }
+    };
+    Ok((puzzle, solution, metadata))
+}
+
+fn convert_one(
+    input_path: &PathBuf,
+    output_path: &PathBuf,
+    args: &ConvertArgs,
+) -> anyhow::Result<()> {
+    let mut document = load(input_path, &args.input)?;
+    // Whatever the input recorded, unless the command line says otherwise.
+    document.metadata.merge(args.metadata());
+    if let Some(source) = source_of(input_path, &args.input, args.embed_source)? {
+        document.metadata.source = Some(source);
+    }
+    let (width, height) = (document.puzzle.cols.len(), document.puzzle.rows.len());
+    for note in &document.metadata.notes {
+        let fits = match note.on {
+            puzzle::NoteTarget::Cell(x, y) => x < width && y < height,
+            puzzle::NoteTarget::Row(y) => y < height,
+            puzzle::NoteTarget::Column(x) => x < width,
+        };
+        if !fits {
+            bail!(
+                "the note \"{}\" is outside the {}x{} puzzle",
+                note,
+                width,
+                height
+            );
+        }
+    }
+    // Pictures can't be inconsistent, but transcribed clues can; better to hear about it now than
+    // after a long solve. Clues in colors the palette doesn't have would crash the exporters, so
+    // those are fatal.
+    if document.solution.is_none() {
+        let puzzle = &document.puzzle;
+        let unknown_color = puzzle
+            .rows
+            .iter()
+            .chain(&puzzle.cols)
+            .flatten()
+            .any(|clue| {
+                clue.color == puzzle::BACKGROUND || !puzzle.palette.contains_key(&clue.color)
+            });
+        if unknown_color {
+            check_structure(puzzle)?;
+        }
+        for problem in import::structural_problems(puzzle) {
+            verbosity::warn(problem);
+        }
+    }
+    if args.stamp_difficulty {
+        let report = grid_solve::solve(&document.puzzle, false, args.solver.deadline())
+            .context(Outcome::Contradictory)?;
+        if report.timed_out {
+            verbosity::warn("the solver timed out, so its stats aren't recorded");
+        } else {
+            document.metadata.solver_stats = Some(puzzle::SolverStats {
+                skims: report.skims,
+                scrubs: report.scrubs,
+                cells_left: report.cells_left,
+            });
+        }
+    }
+    if let Some(save_palette_path) = &args.save_palette {
+        let name = input_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        std::fs::write(
+            save_palette_path,
+            export::as_gpl(&document.puzzle.palette, &name),
+        )?;
+    }
+
+    let metadata = &document.metadata;
+    if metadata.has_attribution() && !args.output_format.capabilities().metadata {
+        verbosity::warn(format!(
+            "{} output has no room for a title, author, copyright, license, description, \
+             difficulty, or notes",
+            args.output_format.name()
+        ));
+    } else if metadata.difficulty.is_some() && args.output_format == NonogramFormat::Webpbn {
+        verbosity::warn("webpbn output has no room for a difficulty");
+    }
+    if !args.drop_rules {
+        if let Err(e) = export::check_rules(&document.puzzle, &args.output_format.name()) {
+            bail!("{} (use --drop-rules to write it anyway)", e);
+        }
+    }
+    for warning in lossy_conversion_warnings(
+        args.input.input_format,
+        args.output_format,
+        &document.puzzle,
+    ) {
+        verbosity::warn(warning);
+    }
+
+    let options = export::ImageOptions {
+        scale: args.scale,
+        grid_lines: args.grid_lines,
+        major_lines: args.major_lines,
+        low_contrast: match args.low_contrast {
+            LowContrastKind::Darken => export::LowContrast::Darken,
+            LowContrastKind::Chip => export::LowContrast::Chip,
+            LowContrastKind::Keep => export::LowContrast::Keep,
+        },
+        answer_key: args.answer_key,
+        drop_rules: args.drop_rules,
+    };
+    if args.answer_key {
+        write_answer_key(&document, output_path, args.output_format, options)?;
+    }
+    emit(&document, output_path, args.output_format, options)
+}
+
+fn emit(
+    document: &Document,
+    output_path: &PathBuf,
+    format: NonogramFormat,
+    options: export::ImageOptions,
+) -> anyhow::Result<()> {
+    let mut document = document.clone();
+    stamp_identity(&mut document.metadata);
+    let document = &document;
+    match (&document.solution, format) {
+        // Only image files get their format from the extension; everything else is bytes.
+        (Some(solution), NonogramFormat::Image) if output_path != &PathBuf::from("-") => {
+            if !options.drop_rules {
+                export::check_rules(&document.puzzle, &format.name())?;
+            }
+            export::emit_image(solution, output_path, options, &document.metadata)?;
+        }
+        _ => write_path(
+            output_path,
+            &export::to_bytes(document, &format.name(), options)?,
+        )?,
+    }
+    Ok(())
+}
+
+/// For `--answer-key`, writes the solution as an image next to `output_path`, unless `format`
+/// already shows it (or has room for it, like HTML).
+fn write_answer_key(
+    document: &Document,
+    output_path: &PathBuf,
+    format: NonogramFormat,
+    options: export::ImageOptions,
+) -> anyhow::Result<()> {
+    let Some(solution) = &document.solution else {
+        bail!("--answer-key requires an input with a solution, not just clues");
+    };
+    match format {
+        NonogramFormat::Html => Ok(()),
+        NonogramFormat::Image | NonogramFormat::CharGrid => {
+            verbosity::warn(format!(
+                "{} output is the solution already, so there's no separate answer key",
+                format.name()
+            ));
+            Ok(())
+        }
+        _ => {
+            if output_path == &PathBuf::from("-") {
+                bail!("--answer-key needs an output file to name the answer key after");
+            }
+            let stem = output_path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy();
+            let answer_path = output_path.with_file_name(format!("{}-answer.png", stem));
+            export::emit_image(solution, answer_path, options, &document.metadata)?;
+            Ok(())
+        }
+    }
+}
+
+/// Gives a puzzle that's about to be written out an ID and a creation time, if it doesn't have
+/// them already. Commands that change a puzzle clear `modified` so that it's set to now, too.
+fn stamp_identity(metadata: &mut Metadata) {
+    let now = humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string();
+    metadata
+        .id
+        .get_or_insert_with(|| uuid::Uuid::new_v4().to_string());
+    metadata.created.get_or_insert_with(|| now.clone());
+    metadata.modified.get_or_insert(now);
+}
+
+/// Applies `f` to each item using up to `threads` threads, returning the results in order.
+fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let mut results: Vec<(usize, R)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..std::cmp::min(threads, items.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = vec![];
+                    loop {
+                        let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                        let Some(item) = items.get(i) else {
+                            return done;
+                        };
+                        done.push((i, f(item)));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Expands directories into the files in them that have one of `format`'s extensions.
+fn expand_inputs(paths: &[PathBuf], format: NonogramFormat) -> anyhow::Result<Vec<PathBuf>> {
+    let mut res = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            res.push(path.clone());
+            continue;
+        }
+        let mut dir_contents = vec![];
+        for entry in std::fs::read_dir(path)? {
+            let entry_path = entry?.path();
+            let has_extension = entry_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .is_some_and(|ext| format.extensions().contains(&ext.as_str()));
+            if entry_path.is_file() && has_extension {
+                dir_contents.push(entry_path);
+            }
+        }
+        dir_contents.sort();
+        res.append(&mut dir_contents);
+    }
+    Ok(res)
+}
+
+fn convert(args: &ConvertArgs, threads: usize) -> anyhow::Result<()> {
+    let Some(output_dir) = &args.output_dir else {
+        let [input_path, output_path] = args.paths.as_slice() else {
+            let mut command = Args::command();
+            usage_error(command.find_subcommand_mut("convert").unwrap().error(
+                clap::error::ErrorKind::WrongNumberOfValues,
+                "expected an input path and an output path (or use --output-dir)",
+            ));
+        };
+        return convert_one(input_path, output_path, args);
+    };
+
+    std::fs::create_dir_all(output_dir)?;
+
+    let inputs = expand_inputs(&args.paths, args.input.input_format)?;
+    let results = parallel_map(&inputs, threads, |input_path| {
+        let stem = input_path.file_stem().unwrap_or_default();
+        let output_path = output_dir
+            .join(stem)
+            .with_extension(args.output_format.extensions()[0]);
+
+        convert_one(input_path, &output_path, args)
+    });
+
+    let (_, failures) = report_failures(&inputs, results);
+
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!(
+            "Converted {} of {} files.",
+            inputs.len() - failures,
+            inputs.len()
+        );
+    }
+    if failures > 0 {
+        bail!("{} files failed to convert", failures);
+    }
+    Ok(())
+}
+
+/// Prints the errors among `results` (which go with `inputs`, in order), and returns the rest,
+/// along with how many failed.
+fn report_failures<T>(inputs: &[PathBuf], results: Vec<anyhow::Result<T>>) -> (Vec<T>, usize) {
+    let mut successes = vec![];
+    let mut failures = 0;
+    for (input_path, result) in inputs.iter().zip(results) {
+        match result {
+            Ok(success) => successes.push(success),
+            Err(e) => {
+                eprintln!("convert-nonogram: {}: {:#}", input_path.display(), e);
+                failures += 1;
+            }
+        }
+    }
+    (successes, failures)
+}
+
+/// Fails with a list of everything `import::structural_problems` finds.
+fn check_structure(puzzle: &Puzzle) -> anyhow::Result<()> {
+    let problems = import::structural_problems(puzzle);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let noun = if problems.len() == 1 {
+        "problem"
+    } else {
+        "problems"
+    };
+    Err(
+        anyhow!("{} {}:\n  {}", problems.len(), noun, problems.join("\n  "))
+            .context(Outcome::Contradictory),
+    )
+}
+
+fn check(args: &CheckArgs) -> anyhow::Result<()> {
+    let inputs = expand_inputs(&args.paths, args.input.input_format)?;
+    let check_one = |path: &PathBuf| {
+        load(path, &args.input).and_then(|document| check_structure(&document.puzzle))
+    };
+
+    if let [input_path] = inputs.as_slice() {
+        // Just one puzzle, so its error can determine the exit status.
+        check_one(input_path)?;
+    } else {
+        let results = inputs.iter().map(check_one).collect();
+        let (_, failures) = report_failures(&inputs, results);
+        if failures > 0 {
+            bail!("{} of {} puzzles have problems", failures, inputs.len());
+        }
+    }
+
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!("No problems found.");
+    }
+    Ok(())
+}
+
+fn terminal_width() -> Option<usize> {
+    use std::io::IsTerminal;
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let (columns, _) = ratatui::crossterm::terminal::size().ok()?;
+    Some(columns.into())
+}
+
+fn solve(args: &SolveArgs) -> anyhow::Result<()> {
+    let document = load(&args.input_path, &args.input)?;
+    let puzzle = document.puzzle;
+    check_structure(&puzzle)?;
+    // Inputs with a solution have already had `quality_check` complain about these.
+    let empty_lanes = if document.solution.is_none() {
+        import::empty_lanes(&puzzle)
+    } else {
+        vec![]
+    };
+    let jsonl = args.trace_format == TraceFormat::Jsonl;
+    // With a JSON trace, stdout is for the trace alone.
+    let say = |text: &str| {
+        if jsonl {
+            eprintln!("{}", text)
+        } else {
+            println!("{}", text)
+        }
+    };
+
+    let mut solver = grid_solve::Solver::new(&puzzle);
+    solver.set_trace(args.trace_solve && !jsonl);
+    solver.set_trace_layout(grid_solve::TraceLayout {
+        width: args.trace_width.or_else(terminal_width),
+        changed_only: args.trace_changes_only,
+    });
+
+    // The bar only shows up on a terminal, and would garble a trace.
+    let total = solver.cells_left();
+    let bar = if args.trace_solve || jsonl || verbosity::get() < Verbosity::Normal {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(total as u64)
+    };
+    bar.set_style(
+        ProgressStyle::with_template("{bar:40} {pos}/{len} cells  {rate}  ETA {eta}")
+            .unwrap()
+            .with_key(
+                "rate",
+                |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                    write!(w, "{:.0} cells/s", state.per_sec()).unwrap()
+                },
+            ),
+    );
+    // Skims and scrubs in each tenth of the solve, by how far along it was.
+    let mut histogram = [(0, 0); 10];
+    // Cells determined by skims and by scrubs, and the step that determined the most.
+    let mut determined = (0, 0);
+    let mut biggest: Option<grid_solve::SolveStep> = None;
+    let report = solver
+        .run_with(args.solver.deadline(), |step, solver| {
+            let cells_left = solver.cells_left();
+            if jsonl {
+                let cells = grid_solve::lane_text(solver.grid(), &puzzle, step.lane);
+                println!(
+                    "{}",
+                    serde_json::json!({
+                        "lane": step.lane,
+                        "technique": step.technique,
+                        "cells_determined": step.cells_determined,
+                        "cells_left": cells_left,
+                        "cells": cells,
+                    })
+                );
+            }
+            bar.set_position((total - cells_left) as u64);
+            let done_before = total - cells_left - step.cells_determined;
+            let tenth = std::cmp::min(done_before * 10 / std::cmp::max(total, 1), 9);
+            match step.technique {
+                grid_solve::Technique::Skim => {
+                    histogram[tenth].0 += 1;
+                    determined.0 += step.cells_determined;
+                }
+                grid_solve::Technique::Scrub => {
+                    histogram[tenth].1 += 1;
+                    determined.1 += step.cells_determined;
+                }
+            }
+            let is_biggest = match &biggest {
+                Some(b) => step.cells_determined > b.cells_determined,
+                None => true,
+            };
+            if is_biggest {
+                biggest = Some(step.clone());
+            }
+        })
+        .context(Outcome::Contradictory)?;
+    if !bar.is_hidden() {
+        bar.finish_and_clear();
+        let tenths: Vec<String> = histogram
+            .iter()
+            .map(|(skims, scrubs)| format!("{}/{}", skims, scrubs))
+            .collect();
+        eprintln!("Skims/scrubs per 10% of progress: {}", tenths.join(" "));
+    }
+
+    if verbosity::get() >= Verbosity::Verbose {
+        for lane in &report.lanes {
+            say(&format!(
+                "{: <4} {}, {}, {} determined",
+                Lane {
+                    row: lane.row,
+                    index: lane.index,
+                }
+                .short_name(),
+                count_of(lane.skims, "skim"),
+                count_of(lane.scrubs, "scrub"),
+                count_of(lane.cells_determined, "cell")
+            ));
+        }
+    }
+
+    if verbosity::get() >= Verbosity::Normal {
+        say(&solve_summary(&report, determined, biggest.as_ref()));
+    }
+
+    if report.cells_left > 0 {
+        if verbosity::get() >= Verbosity::Normal {
+            say(grid_solve::grid_text(&report.grid, &puzzle).trim_end());
+        }
+        if report.timed_out {
+            return Err(anyhow!("{} cells left", report.cells_left).context(Outcome::TimedOut));
+        }
+        return Err(anyhow!("{} cells left", report.cells_left).context(Outcome::NotLineSolvable));
+    }
+
+    if verbosity::get() >= Verbosity::Normal {
+        say("");
+        say(&format!(
+            "Solved in {} skims, {} scrubs.",
+            report.skims, report.scrubs
+        ));
+        if !empty_lanes.is_empty() {
+            let lanes: Vec<String> = empty_lanes.iter().map(|lane| lane.to_string()).collect();
+            say(&format!("Blank lanes: {}.", lanes.join(", ")));
+        }
+        say(grid_solve::grid_text(&report.grid, &puzzle).trim_end());
+    }
+    Ok(())
+}
+
+/// A few lines on where the solver spent its effort, for finding the parts of a puzzle that make
+/// it hard. `determined` is the cells determined by skims and by scrubs.
+fn solve_summary(
+    report: &grid_solve::Report,
+    determined: (usize, usize),
+    biggest: Option<&grid_solve::SolveStep>,
+) -> String {
+    let visits = |lane: &grid_solve::LaneStats| lane.skims + lane.scrubs;
+    let count = |pred: &dyn Fn(usize) -> bool| {
+        report
+            .lanes
+            .iter()
+            .filter(|lane| pred(visits(lane)))
+            .count()
+    };
+    let name = |lane: &grid_solve::LaneStats| {
+        Lane {
+            row: lane.row,
+            index: lane.index,
+        }
+        .to_string()
+    };
+
+    let mut res = format!(
+        "Lanes visited:  {} once, {} more than once, {} never\n",
+        count(&|n| n == 1),
+        count(&|n| n > 1),
+        count(&|n| n == 0)
+    );
+    res.push_str(&format!(
+        "Cells found by: {} skims, {} scrubs\n",
+        determined.0, determined.1
+    ));
+    if let Some(step) = biggest {
+        let technique = match step.technique {
+            grid_solve::Technique::Skim => "skim",
+            grid_solve::Technique::Scrub => "scrub",
+        };
+        res.push_str(&format!(
+            "Biggest step:   {} cells, from a {} of {}\n",
+            step.cells_determined, technique, step.lane
+        ));
+    }
+    let mut busiest: Vec<&grid_solve::LaneStats> = report.lanes.iter().collect();
+    // Stable, so ties stay in order, rows first.
+    busiest.sort_by_key(|lane| std::cmp::Reverse(visits(lane)));
+    let busiest: Vec<String> = busiest
+        .iter()
+        .take(3)
+        .filter(|lane| visits(lane) > 0)
+        .map(|lane| match visits(lane) {
+            1 => format!("{} (once)", name(lane)),
+            n => format!("{} ({} times)", name(lane), n),
+        })
+        .collect();
+    res.push_str(&format!("Most visited:   {}", busiest.join(", ")));
+    res
+}
+
+/// "1 skim", "2 skims", and so on.
+fn count_of(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", n, noun)
+    }
+}
+
+/// Runs `run` now and again every time `path` is modified, until interrupted. Errors are printed
+/// rather than returned, since the next edit will probably fix them.
+fn watch(path: &PathBuf, mut run: impl FnMut() -> anyhow::Result<()>) -> anyhow::Result<()> {
+    if path == &PathBuf::from("-") {
+        bail!("--watch needs an input file, not stdin");
+    }
+    let modified = || std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    let mut last_modified = modified();
+    loop {
+        if let Err(e) = run() {
+            eprintln!("convert-nonogram: {:#}", e);
+        }
+        if verbosity::get() >= Verbosity::Normal {
+            eprintln!("Watching {} for changes...", path.display());
+        }
+
+        loop {
+            std::thread::sleep(Duration::from_millis(500));
+            let now_modified = modified();
+            if now_modified.is_some() && now_modified != last_modified {
+                last_modified = now_modified;
+                break;
+            }
+        }
+        // Give the editor a moment to finish writing.
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ReportRow {
+    path: String,
+    width: usize,
+    height: usize,
+    colors: usize,
+    skims: Option<usize>,
+    scrubs: Option<usize>,
+    cells_left: Option<usize>,
+    error: Option<String>,
+}
+
+impl ReportRow {
+    /// A row with nothing known yet but the path.
+    fn new(path: String) -> ReportRow {
+        ReportRow {
+            path,
+            width: 0,
+            height: 0,
+            colors: 0,
+            skims: None,
+            scrubs: None,
+            cells_left: None,
+            error: None,
+        }
+    }
+
+    /// Fills in what the line solver made of the puzzle. Running out of time counts as an error.
+    fn record_solve(&mut self, report: &grid_solve::Report) {
+        self.skims = Some(report.skims);
+        self.scrubs = Some(report.scrubs);
+        self.cells_left = Some(report.cells_left);
+        if report.timed_out {
+            self.error = Some(Outcome::TimedOut.to_string());
+        }
+    }
+}
+
+fn report_row(path: &PathBuf, args: &ReportArgs) -> ReportRow {
+    let mut row = ReportRow::new(path.display().to_string());
+
+    let puzzle = match load(path, &args.input) {
+        Ok(document) => document.puzzle,
+        Err(e) => {
+            row.error = Some(format!("{:#}", e));
+            return row;
+        }
+    };
+    row.width = puzzle.cols.len();
+    row.height = puzzle.rows.len();
+    row.colors = puzzle.palette.len() - 1; // Don't count the background
+
+    let result = check_structure(&puzzle).and_then(|()| {
+        grid_solve::solve(&puzzle, false, args.solver.deadline()).context(Outcome::Contradictory)
+    });
+    match result {
+        Ok(report) => row.record_solve(&report),
+        Err(e) => row.error = Some(format!("{:#}", e)),
+    }
+    row
+}
+
+fn as_csv(rows: &[ReportRow]) -> String {
+    fn field<T: ToString>(value: &Option<T>) -> String {
+        value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+    }
+    fn quoted(s: &str) -> String {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    }
+
+    let mut res = String::from("path,width,height,colors,skims,scrubs,cells_left,error\n");
+    for row in rows {
+        res.push_str(&format!(
+            "{},{},{},{},{},{},{},{}\n",
+            quoted(&row.path),
+            row.width,
+            row.height,
+            row.colors,
+            field(&row.skims),
+            field(&row.scrubs),
+            field(&row.cells_left),
+            quoted(&field(&row.error)),
+        ));
+    }
+    res
+}
+
+fn report(args: &ReportArgs, threads: usize) -> anyhow::Result<()> {
+    let inputs = expand_inputs(&args.paths, args.input.input_format)?;
+    let rows = parallel_map(&inputs, threads, |path| report_row(path, args));
+
+    let table = match args.report_format {
+        ReportFormat::Csv => as_csv(&rows),
+        ReportFormat::Json => serde_json::to_string_pretty(&rows)? + "\n",
+    };
+
+    match &args.output {
+        Some(path) => std::fs::write(path, table)?,
+        None => print!("{}", table),
+    }
+    Ok(())
+}
+
+fn formats(args: &FormatsArgs) -> anyhow::Result<()> {
+    let all: Vec<FormatCapabilities> = NonogramFormat::value_variants()
+        .iter()
+        .map(|format| format.capabilities())
+        .collect();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&all)?);
+        return Ok(());
+    }
+
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    println!(
+        "{: <10} {: <8} {: <8} {: <10} {: <10} {: <40} extensions",
+        "format", "import", "export", "solution", "metadata", "rules"
+    );
+    for caps in all {
+        let rules: Vec<&str> = caps.rules.iter().map(|rule| rule.name()).collect();
+        println!(
+            "{: <10} {: <8} {: <8} {: <10} {: <10} {: <40} {}",
+            caps.name,
+            yes_no(caps.import),
+            yes_no(caps.export),
+            yes_no(caps.solution),
+            yes_no(caps.metadata),
+            if rules.is_empty() {
+                "none".to_string()
+            } else {
+                rules.join(", ")
+            },
+            caps.extensions.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Seeds from the clock if there's no `seed`. Returns the seed, too, so that it can be reported.
+fn seeded_rng(seed: Option<u64>) -> (u64, StdRng) {
+    let seed = seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as u64)
+    });
+    (seed, StdRng::seed_from_u64(seed))
+}
+
+fn gen_random(args: &RandomArgs) -> anyhow::Result<()> {
+    let (seed, mut rng) = seeded_rng(args.seed);
+    if args.color_weights.len() > args.colors.into() {
+        bail!(
+            "{} --color-weights given, for {} colors",
+            args.color_weights.len(),
+            args.colors
+        );
+    }
+    if let Some(output_dir) = &args.output_dir {
+        std::fs::create_dir_all(output_dir)?;
+    }
+
+    let options = generate::GenOptions {
+        width: args.width.into(),
+        height: args.height.into(),
+        colors: args.colors.into(),
+        density: args.density,
+        blob_size: args.blob_size.into(),
+        color_weights: args.color_weights.clone(),
+        skims: args.min_skims..=args.max_skims,
+        scrubs: args.min_scrubs..=args.max_scrubs,
+        attempts: args.attempts,
+        timeout: args.solver.timeout,
+    };
+    for i in 1..=args.count {
+        let generated = generate::generate(&mut rng, &options)?.ok_or_else(|| {
+            anyhow!(
+                "none of {} random puzzles were line-solvable in the requested range (seed {})",
+                args.attempts,
+                seed
+            )
+        })?;
+
+        if verbosity::get() >= Verbosity::Normal {
+            eprintln!(
+                "Attempt {} (seed {}): {} skims, {} scrubs",
+                generated.attempt, seed, generated.report.skims, generated.report.scrubs
+            );
+        }
+
+        let output_path = match &args.output_dir {
+            Some(output_dir) => output_dir.join(format!(
+                "random-{:03}.{}",
+                i,
+                args.output_format.extensions()[0]
+            )),
+            None => args.output_path.clone(),
+        };
+        let document = Document::new(generated.puzzle, Some(generated.solution), "gen");
+        emit(
+            &document,
+            &output_path,
+            args.output_format,
+            export::ImageOptions::default(),
+        )?;
+    }
+    Ok(())
+}
+
+fn gen_pattern(args: &PatternArgs) -> anyhow::Result<()> {
+    let (width, height) = (usize::from(args.width), usize::from(args.height));
+    let period = args.period.map(usize::from);
+    let colors = args.colors.into();
+
+    let solution = match args.kind {
+        PatternKind::Noise => {
+            let (seed, mut rng) = seeded_rng(args.seed);
+            if verbosity::get() >= Verbosity::Verbose {
+                eprintln!("Seed: {}", seed);
+            }
+            generate::random_solution(&mut rng, width, height, colors, args.density)
+        }
+        PatternKind::Stripes => generate::pattern_solution(
+            generate::Pattern::Stripes,
+            width,
+            height,
+            colors,
+            period.unwrap_or(1),
+        ),
+        PatternKind::Checkers => generate::pattern_solution(
+            generate::Pattern::Checkers,
+            width,
+            height,
+            colors,
+            period.unwrap_or(1),
+        ),
+        PatternKind::WorstCase => generate::pattern_solution(
+            generate::Pattern::WorstCase,
+            width,
+            height,
+            colors,
+            period.unwrap_or(std::cmp::max(width, height)),
+        ),
+    };
+
+    let document = Document::new(
+        import::solution_to_puzzle(&solution)?,
+        Some(solution),
+        "pattern",
+    );
+    emit(
+        &document,
+        &args.output_path,
+        args.output_format,
+        export::ImageOptions::default(),
+    )
+}
+
+fn gen_from_image(args: &FromImageArgs) -> anyhow::Result<()> {
+    let photo = image::load_from_memory(&read_bytes(&args.input_path)?)?;
+    let options = generate::PhotoOptions {
+        width: args.width,
+        height: args.height,
+        colors: args.colors.into(),
+        dither: args.dither,
+    };
+    let mut solution =
+        generate::photo_to_solution(&photo, &options).context(Outcome::ParseError)?;
+    import::quality_check(&solution).print();
+
+    let (edits, report) = generate::repair(&mut solution, args.repair, args.solver.timeout)?;
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!(
+            "{} skims, {} scrubs, {} cells left{}",
+            report.skims,
+            report.scrubs,
+            report.cells_left,
+            if edits > 0 {
+                format!(" after editing {} cells", edits)
+            } else {
+                String::new()
+            }
+        );
+    }
+
+    let document = Document::new(
+        import::solution_to_puzzle(&solution)?,
+        Some(solution),
+        "image",
+    );
+    emit(
+        &document,
+        &args.output_path,
+        args.output_format,
+        export::ImageOptions::default(),
+    )?;
+
+    if report.cells_left > 0 {
+        let outcome = if report.timed_out {
+            Outcome::TimedOut
+        } else {
+            Outcome::NotLineSolvable
+        };
+        return Err(anyhow!("{} cells left", report.cells_left).context(outcome));
+    }
+    Ok(())
+}
+
+fn repair(args: &RepairArgs) -> anyhow::Result<()> {
+    let mut document = load(&args.input_path, &args.input)?;
+    let mut solution = match document.take_solution() {
+        Some(solution) => solution,
+        None => bail!(
+            "repair needs a solution, but {:?} input only has clues",
+            args.input.input_format
+        ),
+    };
+    check_structure(&document.puzzle)?;
+
+    let edits = generate::minimal_repair(
+        &solution,
+        args.max_edits,
+        args.candidates,
+        args.solver.timeout,
+    )?
+    .ok_or_else(|| {
+        anyhow!(
+            "no way to make it line-solvable by changing {} or fewer cells",
+            args.max_edits
+        )
+        .context(Outcome::NotLineSolvable)
+    })?;
+
+    if edits.is_empty() {
+        if verbosity::get() >= Verbosity::Normal {
+            eprintln!("Line logic already solves it.");
+        }
+    } else {
+        print_edits(&solution, &edits);
+    }
+
+    if let Some(edits_path) = &args.edits {
+        let csv = edits_as_csv(&solution, &edits, args.solver.timeout)?;
+        write_path(edits_path, csv.as_bytes())?;
+    }
+
+    if let Some(output_path) = &args.output_path {
+        generate::apply_edits(&mut solution, &edits);
+        let document = Document {
+            // It's been changed.
+            metadata: Metadata {
+                modified: None,
+                ..document.metadata.clone()
+            },
+            ..Document::new(
+                import::solution_to_puzzle(&solution)?,
+                Some(solution),
+                &document.source_format,
+            )
+        };
+        emit(
+            &document,
+            output_path,
+            args.output_format,
+            export::ImageOptions::default(),
+        )?;
+    }
+    Ok(())
+}
+
+fn edits_as_csv(
+    solution: &Solution,
+    edits: &[generate::Edit],
+    timeout: Option<Duration>,
+) -> anyhow::Result<String> {
+    let ch = |color| solution.palette[&color].ch;
+    let mut res = String::from("row,column,from,to,cells_left_alone\n");
+    for edit in edits {
+        let mut alone = solution.clone();
+        generate::apply_edits(&mut alone, &[*edit]);
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let report = grid_solve::solve(&import::solution_to_puzzle(&alone)?, false, deadline)?;
+        res.push_str(&format!(
+            "{},{},{},{},{}\n",
+            edit.y + 1,
+            edit.x + 1,
+            ch(edit.from),
+            ch(edit.to),
+            report.cells_left
+        ));
+    }
+    Ok(res)
+}
+
+fn mosaic(args: &MosaicArgs) -> anyhow::Result<()> {
+    let mut document = load(&args.input_path, &args.input)?;
+    let solution = match document.take_solution() {
+        Some(solution) => solution,
+        None => bail!(
+            "mosaic needs a solution, but {:?} input only has clues",
+            args.input.input_format
+        ),
+    };
+
+    let mut mosaic = mosaic::clues_from_solution(&solution);
+    let report = mosaic::solve(&mosaic)?;
+    if report.cells_left > 0 {
+        return Err(anyhow!(
+            "{} cells left; the picture is ambiguous as a Mosaic",
+            report.cells_left
+        )
+        .context(Outcome::NotLineSolvable));
+    }
+    if args.minimize {
+        let removed = mosaic::minimize(&mut mosaic)?;
+        if verbosity::get() >= Verbosity::Verbose {
+            eprintln!("Removed {} unneeded clues.", removed);
+        }
+    }
+
+    if verbosity::get() >= Verbosity::Normal {
+        let report = mosaic::solve(&mosaic)?;
+        eprintln!(
+            "{} clues; solved in {} single-clue steps, {} two-clue steps.",
+            mosaic.num_clues(),
+            report.single_steps,
+            report.pair_steps
+        );
+    }
+    write_path(&args.output_path, mosaic::as_text(&mosaic).as_bytes())
+}
+
+fn print_edits(solution: &Solution, edits: &[generate::Edit]) {
+    let name = |color| &solution.palette[&color].name;
+    for edit in edits {
+        println!(
+            "row {}, column {}: {} -> {}",
+            edit.y + 1,
+            edit.x + 1,
+            name(edit.from),
+            name(edit.to)
+        );
+    }
+}
+
+fn tune(args: &TuneArgs) -> anyhow::Result<()> {
+    let mut document = load(&args.input_path, &args.input)?;
+    let solution = match document.take_solution() {
+        Some(solution) => solution,
+        None => bail!(
+            "tune needs a solution, but {:?} input only has clues",
+            args.input.input_format
+        ),
+    };
+    let (seed, mut rng) = seeded_rng(args.seed);
+
+    let options = generate::TuneOptions {
+        target_skims: args.target_skims,
+        target_scrubs: args.target_scrubs,
+        tolerance: args.tolerance,
+        iterations: args.iterations,
+        timeout: args.solver.timeout,
+    };
+    let tuned = generate::tune(&mut rng, &solution, &options)?.ok_or_else(|| {
+        anyhow!("line logic can't solve the original, so there's nothing to tune")
+            .context(Outcome::NotLineSolvable)
+    })?;
+
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!(
+            "{} skims, {} scrubs after changing {} cells (seed {})",
+            tuned.report.skims,
+            tuned.report.scrubs,
+            tuned.edits.len(),
+            seed
+        );
+    }
+    print_edits(&solution, &tuned.edits);
+
+    if let Some(output_path) = &args.output_path {
+        let document = Document {
+            // It's been changed.
+            metadata: Metadata {
+                modified: None,
+                ..document.metadata.clone()
+            },
+            ..Document::new(
+                import::solution_to_puzzle(&tuned.solution)?,
+                Some(tuned.solution),
+                &document.source_format,
+            )
+        };
+        emit(
+            &document,
+            output_path,
+            args.output_format,
+            export::ImageOptions::default(),
+        )?;
+    }
+    Ok(())
+}
+
+/// One puzzle-to-be in a pack.
+enum PackItem {
+    Glyph { font: PathBuf, ch: char },
+    Icon(PathBuf),
+}
+
+impl PackItem {
+    /// A name for the puzzle's file, unique within the pack (as long as the inputs' names are).
+    fn name(&self) -> String {
+        match self {
+            PackItem::Glyph { font, ch } => {
+                let stem = font.file_stem().unwrap_or_default().to_string_lossy();
+                if ch.is_ascii_alphanumeric() {
+                    // Distinguish "a" from "A" on case-insensitive filesystems.
+                    let case = if ch.is_ascii_uppercase() {
+                        "upper-"
+                    } else {
+                        ""
+                    };
+                    format!("{}-{}{}", stem, case, ch)
+                } else {
+                    format!("{}-U{:04X}", stem, *ch as u32)
+                }
+            }
+            PackItem::Icon(path) => path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+}
+
+fn is_font(path: &std::path::Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| ext == "ttf" || ext == "otf")
+}
+
+fn pack_solution(item: &PackItem, args: &PackArgs) -> anyhow::Result<Option<Solution>> {
+    let image = match item {
+        PackItem::Glyph { font, ch } => {
+            match generate::glyph_image(&std::fs::read(font)?, *ch, args.size)? {
+                Some(image) => image,
+                None => return Ok(None),
+            }
+        }
+        PackItem::Icon(path)
+            if NonogramFormat::Svg.extensions().iter().any(|ext| {
+                path.extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case(ext))
+            }) =>
+        {
+            let svg = std::fs::read(path)?;
+            let natural = import::svg_to_image(&svg, None, None)?;
+            let (width, height) = generate::fit(natural.width(), natural.height(), args.size);
+            import::svg_to_image(&svg, Some(width), Some(height))?
+        }
+        PackItem::Icon(path) => image::open(path)?,
+    };
+    let (width, height) = generate::fit(image.width(), image.height(), args.size);
+    let options = generate::PhotoOptions {
+        width,
+        height: Some(height),
+        colors: args.colors.into(),
+        dither: false,
+    };
+    Ok(Some(generate::photo_to_solution(&image, &options)?))
+}
+
+/// Makes one puzzle of a pack, and describes it for the index. `None` for glyphs that are blank.
+fn pack_one(item: &PackItem, args: &PackArgs) -> Option<ReportRow> {
+    let file_name = format!("{}.{}", item.name(), args.output_format.extensions()[0]);
+    let mut row = ReportRow::new(file_name.clone());
+    if let Err(e) = write_pack_puzzle(item, args, &file_name, &mut row) {
+        row.error = Some(format!("{:#}", e));
+    }
+    if row.width == 0 && row.error.is_none() {
+        return None;
+    }
+    Some(row)
+}
+
+/// The work of `pack_one`, filling in `row` as it goes.
+fn write_pack_puzzle(
+    item: &PackItem,
+    args: &PackArgs,
+    file_name: &str,
+    row: &mut ReportRow,
+) -> anyhow::Result<()> {
+    let solution = match pack_solution(item, args)? {
+        Some(solution) => solution,
+        None => return Ok(()),
+    };
+    let puzzle = import::solution_to_puzzle(&solution)?;
+    row.width = puzzle.cols.len();
+    row.height = puzzle.rows.len();
+    row.colors = puzzle.palette.len() - 1; // Don't count the background
+
+    let report = grid_solve::solve(&puzzle, false, args.solver.deadline())?;
+    row.record_solve(&report);
+    if report.cells_left > 0 {
+        if !report.timed_out {
+            row.error = Some(Outcome::NotLineSolvable.to_string());
+        }
+        if !args.keep_ambiguous {
+            return Ok(());
+        }
+    }
+
+    let document = Document::new(puzzle, Some(solution), "image");
+    emit(
+        &document,
+        &args.output_dir.join(file_name),
+        args.output_format,
+        export::ImageOptions::default(),
+    )
+}
+
+fn gen_pack(args: &PackArgs, threads: usize) -> anyhow::Result<()> {
+    std::fs::create_dir_all(&args.output_dir)?;
+
+    let mut items = vec![];
+    for path in &args.paths {
+        let mut files = vec![path.clone()];
+        if path.is_dir() {
+            files = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            files.retain(|file| {
+                let ext = file
+                    .extension()
+                    .map(|ext| ext.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                is_font(file)
+                    || NonogramFormat::Image.extensions().contains(&ext.as_str())
+                    || NonogramFormat::Svg.extensions().contains(&ext.as_str())
+            });
+            files.sort();
+        }
+        for file in files {
+            if is_font(&file) {
+                items.extend(args.chars.chars().map(|ch| PackItem::Glyph {
+                    font: file.clone(),
+                    ch,
+                }));
+            } else {
+                items.push(PackItem::Icon(file));
+            }
+        }
+    }
+
+    let mut rows: Vec<ReportRow> = parallel_map(&items, threads, |item| pack_one(item, args))
+        .into_iter()
+        .flatten()
+        .collect();
+    // Easiest first, with the failures at the end.
+    rows.sort_by_key(|row| (row.error.is_some(), row.scrubs, row.skims));
+    std::fs::write(args.output_dir.join("index.csv"), as_csv(&rows))?;
+
+    let failures = rows.iter().filter(|row| row.error.is_some()).count();
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!(
+            "Made {} puzzles; {} more had problems (see index.csv).",
+            rows.len() - failures,
+            failures
+        );
+    }
+    Ok(())
+}
+
+/// Solves and writes one of the puzzles `variants` makes from `original`, filling in `row`.
+fn write_variant(
+    variant: Solution,
+    original: &Document,
+    args: &VariantsArgs,
+    file_name: &str,
+    row: &mut ReportRow,
+) -> anyhow::Result<()> {
+    let puzzle = import::solution_to_puzzle(&variant)?;
+    let report = grid_solve::solve(&puzzle, false, args.solver.deadline())?;
+    row.record_solve(&report);
+    let document = Document {
+        // A different puzzle, so it gets an identity of its own.
+        metadata: Metadata {
+            id: None,
+            created: None,
+            modified: None,
+            ..original.metadata.clone()
+        },
+        ..Document::new(puzzle, Some(variant), &original.source_format)
+    };
+    emit(
+        &document,
+        &args.output_dir.join(file_name),
+        args.output_format,
+        export::ImageOptions::default(),
+    )
+}
+
+fn variants(args: &VariantsArgs, threads: usize) -> anyhow::Result<()> {
+    let mut document = load(&args.input_path, &args.input)?;
+    let solution = match document.take_solution() {
+        Some(solution) => solution,
+        None => bail!(
+            "variants needs a solution, but {:?} input only has clues",
+            args.input.input_format
+        ),
+    };
+    std::fs::create_dir_all(&args.output_dir)?;
+    let stem = args
+        .input_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "stdin".to_string());
+
+    // From the original down to black-and-white (counting the background as a color).
+    let counts: Vec<usize> = (2..=solution.palette.len()).rev().collect();
+    let rows = parallel_map(&counts, threads, |&colors| {
+        let mut variant = solution.clone();
+        import::merge_by_luminance(&mut variant, colors);
+        let file_name = format!(
+            "{}-{}colors.{}",
+            stem,
+            colors,
+            args.output_format.extensions()[0]
+        );
+        let mut row = ReportRow {
+            width: variant.grid.len(),
+            height: variant.grid.first().map_or(0, Vec::len),
+            colors: variant.palette.len() - 1, // Don't count the background
+            ..ReportRow::new(file_name.clone())
+        };
+        if let Err(e) = write_variant(variant, &document, args, &file_name, &mut row) {
+            row.error = Some(format!("{:#}", e));
+        }
+        row
+    });
+
+    print!("{}", as_csv(&rows));
+    Ok(())
+}
+
+fn debug_solve(args: &DebugSolveArgs) -> anyhow::Result<()> {
+    if args.input_path == Path::new("-") {
+        bail!("debug-solve reads commands from stdin, so the puzzle can't come from there too");
+    }
+    let document = load(&args.input_path, &args.input)?;
+    check_structure(&document.puzzle)?;
+    debug_solve::debug_solve(&document.puzzle)
+}
+
+fn show(args: &ShowArgs) -> anyhow::Result<()> {
+    let document = load(&args.input_path, &args.input)?;
+    let image = match &document.solution {
+        Some(solution) => inline_image::render(solution, args.graphics.detect())?,
+        None => None,
+    };
+    match image {
+        Some(image) => print!("{}\n{}", image, export::as_terminal(&document.puzzle, None)),
+        None => print!(
+            "{}",
+            export::as_terminal(&document.puzzle, document.solution.as_ref())
+        ),
+    }
+    if !document.metadata.notes.is_empty() {
+        println!("Notes:");
+        for note in &document.metadata.notes {
+            println!("  {}", note);
+        }
+    }
+    Ok(())
+}
+
+fn play(args: &PlayArgs) -> anyhow::Result<()> {
+    let mut document = load(&args.input_path, &args.input)?;
+    let solution = picture(&mut document, &args.solver)?;
+    // For comparing the player's experience with the solver's.
+    let solver_stats = grid_solve::solve(&document.puzzle, false, args.solver.deadline())
+        .ok()
+        .filter(|report| !report.timed_out)
+        .map(|report| puzzle::SolverStats {
+            skims: report.skims,
+            scrubs: report.scrubs,
+            cells_left: report.cells_left,
+        });
+    let from_stdin = args.input_path == Path::new("-");
+    let options = play::Options {
+        auto_check: args.auto_check,
+        record: (!args.no_record && !from_stdin).then(|| play::record_path(&args.input_path)),
+        restart: args.restart,
+        solver_stats,
+    };
+    play::play(&document.puzzle, &solution, options)?;
+    Ok(())
+}
+
+/// Checks, solves, and writes one layer, filling in `row`.
+fn write_layer(
+    document: &Document,
+    args: &LayersArgs,
+    file_name: &str,
+    row: &mut ReportRow,
+) -> anyhow::Result<()> {
+    check_structure(&document.puzzle)?;
+    let report = grid_solve::solve(&document.puzzle, false, args.solver.deadline())
+        .context(Outcome::Contradictory)?;
+    row.record_solve(&report);
+    emit(
+        document,
+        &args.output_dir.join(file_name),
+        args.output_format,
+        export::ImageOptions::default(),
+    )
+}
+
+fn layers(args: &LayersArgs, threads: usize) -> anyhow::Result<()> {
+    let paths = expand_inputs(&args.paths, args.input.input_format)?;
+    let mut layers: Vec<(PathBuf, Document)> = vec![];
+    for path in &paths {
+        let document = load(path, &args.input).with_context(|| format!("{}", path.display()))?;
+        if let Some((first_path, first)) = layers.first() {
+            let size = |puzzle: &Puzzle| (puzzle.cols.len(), puzzle.rows.len());
+            let this = &document;
+            if size(&first.puzzle) != size(&this.puzzle) {
+                bail!(
+                    "{} is {}x{}, but {} is {}x{}; the layers have to be the same size",
+                    path.display(),
+                    this.puzzle.cols.len(),
+                    this.puzzle.rows.len(),
+                    first_path.display(),
+                    first.puzzle.cols.len(),
+                    first.puzzle.rows.len()
+                );
+            }
+        }
+        layers.push((path.clone(), document));
+    }
+    if layers.is_empty() {
+        bail!("there are no layers");
+    }
+    std::fs::create_dir_all(&args.output_dir)?;
+
+    let numbered: Vec<(usize, &(PathBuf, Document))> = layers.iter().enumerate().collect();
+    let mut rows = parallel_map(&numbered, threads, |&(i, (path, document))| {
+        let puzzle = &document.puzzle;
+        let mut row = ReportRow {
+            width: puzzle.cols.len(),
+            height: puzzle.rows.len(),
+            colors: puzzle.palette.len() - 1, // Don't count the background
+            ..ReportRow::new(path.display().to_string())
+        };
+        let file_name = format!("layer{:02}.{}", i + 1, args.output_format.extensions()[0]);
+        if let Err(e) = write_layer(document, args, &file_name, &mut row) {
+            row.error = Some(format!("{:#}", e));
+        }
+        row
+    });
+
+    // The set as a whole: it's only solved if every layer is.
+    let sum =
+        |field: fn(&ReportRow) -> Option<usize>| rows.iter().map(field).sum::<Option<usize>>();
+    let colors: std::collections::HashSet<import::Rgb> = layers
+        .iter()
+        .flat_map(|(_, document)| document.puzzle.palette.values())
+        .filter(|color_info| color_info.color != puzzle::BACKGROUND)
+        .map(|color_info| color_info.rgb)
+        .collect();
+    let failures = rows.iter().filter(|row| row.error.is_some()).count();
+    let total = ReportRow {
+        path: format!("total ({} layers)", rows.len()),
+        width: rows[0].width,
+        height: rows[0].height,
+        colors: colors.len(),
+        skims: sum(|row| row.skims),
+        scrubs: sum(|row| row.scrubs),
+        cells_left: sum(|row| row.cells_left),
+        error: (failures > 0).then(|| format!("{} of the layers had problems", failures)),
+    };
+
+    rows.push(total);
+    print!("{}", as_csv(&rows));
+    Ok(())
+}
+
+/// The document's picture, or (for clue-only formats) the solver's.
+fn picture(document: &mut Document, solver: &SolverArgs) -> anyhow::Result<Solution> {
+    if let Some(solution) = document.take_solution() {
+        return Ok(solution);
+    }
+    check_structure(&document.puzzle)?;
+    let report = grid_solve::solve(&document.puzzle, false, solver.deadline())
+        .context(Outcome::Contradictory)?;
+    match report.solution(&document.puzzle) {
+        Some(solution) => Ok(solution),
+        None if report.timed_out => {
+            Err(anyhow!("{} cells left", report.cells_left).context(Outcome::TimedOut))
+        }
+        None => Err(anyhow!("{} cells left", report.cells_left).context(Outcome::NotLineSolvable)),
+    }
+}
+
+fn thumb_one(input_path: &PathBuf, output_path: &PathBuf, args: &ThumbsArgs) -> anyhow::Result<()> {
+    let mut document = load(input_path, &args.input)?;
+    let solution = picture(&mut document, &args.solver)?;
+
+    export::thumbnail(&solution, args.size, args.pixelate, args.blur).save(output_path)?;
+    Ok(())
+}
+
+fn thumbs(args: &ThumbsArgs, threads: usize) -> anyhow::Result<()> {
+    if args.size == 0 {
+        bail!("--size must be at least 1");
+    }
+    std::fs::create_dir_all(&args.output_dir)?;
+
+    let inputs = expand_inputs(&args.paths, args.input.input_format)?;
+    let results = parallel_map(&inputs, threads, |input_path| {
+        let stem = input_path.file_stem().unwrap_or_default();
+        let output_path = args.output_dir.join(stem).with_extension("png");
+
+        thumb_one(input_path, &output_path, args)
+    });
+
+    let (_, failures) = report_failures(&inputs, results);
+
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!(
+            "Rendered {} of {} previews.",
+            inputs.len() - failures,
+            inputs.len()
+        );
+    }
+    if failures > 0 {
+        bail!("{} previews failed", failures);
+    }
+    Ok(())
+}
+
+/// How hard a gallery puzzle is, easiest first: line-solvable ones by scrubs and then skims.
+type GalleryRank = (bool, usize, usize);
+
+fn gallery_one(
+    input_path: &PathBuf,
+    args: &GalleryArgs,
+) -> anyhow::Result<(export::GalleryEntry, GalleryRank)> {
+    let stem = input_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let document = load(input_path, &args.input)?;
+    check_structure(&document.puzzle)?;
+    let report = grid_solve::solve(&document.puzzle, false, args.solver.deadline())
+        .context(Outcome::Contradictory)?;
+
+    let page = format!("{}.html", stem);
+    let options = export::ImageOptions {
+        major_lines: 5,
+        ..Default::default()
+    };
+    emit(
+        &document,
+        &args.output_dir.join(&page),
+        NonogramFormat::Html,
+        options,
+    )?;
+
+    let solution = match &document.solution {
+        Some(solution) => Some(solution.clone()),
+        None => report.solution(&document.puzzle),
+    };
+    let thumbnail = match solution {
+        Some(solution) => {
+            let thumbnail = format!("thumbs/{}.png", stem);
+            export::thumbnail(&solution, args.size, args.pixelate, args.blur)
+                .save(args.output_dir.join(&thumbnail))?;
+            Some(thumbnail)
+        }
+        None => None,
+    };
+
+    let difficulty = if report.cells_left == 0 {
+        format!("{} skims, {} scrubs", report.skims, report.scrubs)
+    } else if report.timed_out {
+        format!("timed out ({} cells left)", report.cells_left)
+    } else {
+        format!("not line-solvable ({} cells left)", report.cells_left)
+    };
+
+    let entry = export::GalleryEntry {
+        title: document.metadata.title.clone().unwrap_or(stem),
+        page,
+        thumbnail,
+        width: document.puzzle.cols.len(),
+        height: document.puzzle.rows.len(),
+        colors: document.puzzle.palette.len() - 1, // Don't count the background
+        difficulty,
+    };
+    Ok((entry, (report.cells_left > 0, report.scrubs, report.skims)))
+}
+
+fn gallery(args: &GalleryArgs, threads: usize) -> anyhow::Result<()> {
+    if args.size == 0 {
+        bail!("--size must be at least 1");
+    }
+    std::fs::create_dir_all(args.output_dir.join("thumbs"))?;
+
+    let inputs = expand_inputs(&args.paths, args.input.input_format)?;
+    let results = parallel_map(&inputs, threads, |input_path| gallery_one(input_path, args));
+
+    let (mut entries, failures) = report_failures(&inputs, results);
+    entries.sort_by_key(|(_, rank)| *rank);
+    let entries: Vec<_> = entries.into_iter().map(|(entry, _)| entry).collect();
+
+    write_path(
+        &args.output_dir.join("index.html"),
+        export::as_gallery_index(&args.title, &entries).as_bytes(),
+    )?;
+
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!(
+            "Added {} of {} puzzles to the gallery.",
+            inputs.len() - failures,
+            inputs.len()
+        );
+    }
+    if failures > 0 {
+        bail!("{} puzzles failed", failures);
+    }
+    Ok(())
+}
+
+fn bundle(args: &BundleArgs, threads: usize) -> anyhow::Result<()> {
+    let inputs = expand_inputs(&args.paths, args.input.input_format)?;
+    let results = parallel_map(&inputs, threads, |input_path| load(input_path, &args.input));
+
+    let (mut documents, failures) = report_failures(&inputs, results);
+    // A pack with holes in it is worse than no pack.
+    if failures > 0 {
+        bail!("{} puzzles failed, so the pack wasn't written", failures);
+    }
+    for document in &mut documents {
+        stamp_identity(&mut document.metadata);
+    }
+
+    let mut pack = puzzle::Pack {
+        metadata: Metadata {
+            title: args.title.clone(),
+            author: args.author.clone(),
+            copyright: args.copyright.clone(),
+            license: args.license.clone(),
+            description: args.description.clone(),
+            ..Default::default()
+        },
+        documents,
+    };
+    stamp_identity(&mut pack.metadata);
+    write_path(&args.output_path, &export::as_loompack(&pack)?)?;
+
+    if verbosity::get() >= Verbosity::Normal {
+        eprintln!("Bundled {} puzzles.", pack.documents.len());
+    }
+    Ok(())
+}
+
+fn unbundle(args: &UnbundleArgs) -> anyhow::Result<()> {
+    let pack =
+        import::loompack_to_pack(&read_bytes(&args.input_path)?).context(Outcome::ParseError)?;
+    std::fs::create_dir_all(&args.output_dir)?;
+
+    let stem = match args.input_path.file_stem() {
+        Some(stem) if args.input_path != Path::new("-") => stem.to_string_lossy().to_string(),
+        _ => "puzzle".to_string(),
+    };
+    for (i, mut document) in pack.documents.into_iter().enumerate() {
+        // The pack's attribution covers the puzzles that don't have their own.
+        if document.metadata.author.is_none() {
+            document.metadata.author = pack.metadata.author.clone();
+        }
+        if document.metadata.copyright.is_none() {
+            document.metadata.copyright = pack.metadata.copyright.clone();
+        }
+        if document.metadata.license.is_none() {
+            document.metadata.license = pack.metadata.license.clone();
+        }
+
+        let output_path = args.output_dir.join(format!(
+            "{}-{:03}.{}",
+            stem,
+            i + 1,
+            args.output_format.extensions()[0]
+        ));
+        emit(
+            &document,
+            &output_path,
+            args.output_format,
+            export::ImageOptions::default(),
+        )
+        .with_context(|| output_path.display().to_string())?;
+    }
+    Ok(())
+}
+
+/// What to record about where a puzzle imported from a picture came from, so that `reimport` can
+/// find it again. `None` for clue formats, and for pictures that can't be read again.
+fn source_of(
+    input_path: &PathBuf,
+    input: &InputArgs,
+    embed: bool,
+) -> anyhow::Result<Option<puzzle::Source>> {
+    let from_picture = matches!(
+        input.input_format,
+        NonogramFormat::Image | NonogramFormat::Svg
+    );
+    if !from_picture || !input.panels.is_empty() {
+        if embed {
+            verbosity::warn("--embed-source only applies to a single image or SVG input");
+        }
+        return Ok(None);
+    }
+    if input_path == &PathBuf::from("-") {
+        if embed {
+            bail!("--embed-source needs an input file, not stdin");
+        }
+        return Ok(None);
+    }
+
+    let embedded = match embed {
+        true => Some(STANDARD.encode(std::fs::read(input_path)?)),
+        false => None,
+    };
+    Ok(Some(puzzle::Source {
+        path: Some(std::path::absolute(input_path)?.display().to_string()),
+        embedded,
+        options: input.as_options(),
+    }))
+}
+
+fn reimport(args: &ReimportArgs) -> anyhow::Result<()> {
+    let old =
+        import::from_bytes(&read_bytes(&args.input_path)?, "json").context(Outcome::ParseError)?;
+    let Some(source) = old.metadata.source.clone() else {
+        bail!(
+            "{} doesn't record a picture to import again (convert it from one with `-o json`)",
+            args.input_path.display()
+        );
+    };
+    let mut options = args.options.clone();
+    if options.is_empty() {
+        options = source.options.clone();
+    } else if !options
+        .iter()
+        .any(|option| option == "-i" || option.starts_with("--input-format"))
+    {
+        // The picture is still the same kind of file.
+        if let Some(format) = source
+            .options
+            .windows(2)
+            .find(|pair| pair[0] == "--input-format")
+        {
+            options.splice(0..0, format.iter().cloned());
+        }
+    }
+    let input = InputArgs::from_options(&options).context("bad import options")?;
+
+    // Prefer the original, in case it's been touched up since; the copy is for when it's gone.
+    let original = source.path.as_ref().map(PathBuf::from);
+    let (picture_path, scratch) = match (original, &source.embedded) {
+        (Some(path), _) if path.is_file() => (path, false),
+        (original, Some(embedded)) => {
+            let bytes = STANDARD
+                .decode(embedded)
+                .context("the embedded copy of the picture is corrupt")?;
+            let extension = original
+                .as_ref()
+                .and_then(|path| path.extension())
+                .map(|ext| ext.to_string_lossy().to_string())
+                .unwrap_or_else(|| input.input_format.extensions()[0].to_string());
+            let path = std::env::temp_dir().join(format!(
+                "convert-nonogram-{}.{}",
+                uuid::Uuid::new_v4(),
+                extension
+            ));
+            std::fs::write(&path, bytes)?;
+            (path, true)
+        }
+        (Some(path), None) => bail!(
+            "{} is gone, and there's no copy of it (use `convert --embed-source` to keep one)",
+            path.display()
+        ),
+        (None, None) => bail!("the puzzle doesn't record where its picture is"),
+    };
+    let loaded = load(&picture_path, &input);
+    if scratch {
+        let _ = std::fs::remove_file(&picture_path);
+    }
+
+    let mut document = loaded?;
+    // Everything about the puzzle but its picture stays the same.
+    document.metadata = old.metadata;
+    document.metadata.modified = None;
+    document.metadata.source = Some(puzzle::Source { options, ..source });
+    let output_path = args.output_path.as_ref().unwrap_or(&args.input_path);
+    emit(
+        &document,
+        output_path,
+        NonogramFormat::Json,
+        export::ImageOptions::default(),
+    )
+}
+
+/// Like `clap::Error::exit`, but without using exit status 2, which means something else here.
+fn usage_error(e: clap::Error) -> ! {
+    let _ = e.print();
+    std::process::exit(if e.use_stderr() {
+        Outcome::OtherError as i32
+    } else {
+        0 // --help or --version
+    });
+}
+
+fn main() -> ExitCode {
+    let args = Args::try_parse().unwrap_or_else(|e| usage_error(e));
+
+    verbosity::set(if args.quiet {
+        Verbosity::Quiet
+    } else if args.verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    });
+
+    colored::control::set_override(match args.color {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => {
+            use std::io::IsTerminal;
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            !no_color && std::io::stdout().is_terminal()
+        }
+    });
+
+    let threads = args.threads.map(usize::from).unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(usize::from)
+            .unwrap_or(1)
+    });
+
+    let result = match args.command {
+        Command::Convert(convert_args) if convert_args.watch => {
+            watch(&convert_args.paths[0], || convert(&convert_args, threads))
+        }
+        Command::Convert(convert_args) => convert(&convert_args, threads),
+        Command::Solve(solve_args) if solve_args.watch => {
+            watch(&solve_args.input_path, || solve(&solve_args))
+        }
+        Command::Solve(solve_args) => solve(&solve_args),
+        Command::Report(report_args) => report(&report_args, threads),
+        Command::Formats(formats_args) => formats(&formats_args),
+        Command::Thumbs(thumbs_args) => thumbs(&thumbs_args, threads),
+        Command::Gallery(gallery_args) => gallery(&gallery_args, threads),
+        Command::Bundle(bundle_args) => bundle(&bundle_args, threads),
+        Command::Unbundle(unbundle_args) => unbundle(&unbundle_args),
+        Command::Reimport(reimport_args) => reimport(&reimport_args),
+        Command::Check(check_args) => check(&check_args),
+        Command::Gen(GenCommand::Random(random_args)) => gen_random(&random_args),
+        Command::Gen(GenCommand::FromImage(from_image_args)) => gen_from_image(&from_image_args),
+        Command::Gen(GenCommand::Pack(pack_args)) => gen_pack(&pack_args, threads),
+        Command::Gen(GenCommand::Pattern(pattern_args)) => gen_pattern(&pattern_args),
+        Command::Repair(repair_args) => repair(&repair_args),
+        Command::Tune(tune_args) => tune(&tune_args),
+        Command::Variants(variants_args) => variants(&variants_args, threads),
+        Command::Mosaic(mosaic_args) => mosaic(&mosaic_args),
+        Command::DebugSolve(debug_solve_args) => debug_solve(&debug_solve_args),
+        Command::Play(play_args) => play(&play_args),
+        Command::Show(show_args) => show(&show_args),
+        Command::Layers(layers_args) => layers(&layers_args, threads),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("convert-nonogram: {:#}", e);
+            let outcome = e
+                .downcast_ref::<Outcome>()
+                .copied()
+                .unwrap_or(Outcome::OtherError);
+            ExitCode::from(outcome as u8)
+        }
+    }
 }
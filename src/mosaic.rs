@@ -0,0 +1,297 @@
+//! Mosaic puzzles (also called Fill-a-Pix): nonograms' closest relative. Instead of clues along
+//! the edges, cells hold digits saying how many of the nine cells around them (counting
+//! themselves) are filled. They're made from the same `Solution`s, with every color other than
+//! the background counting as filled.
+
+use crate::{
+    error::{Error, Result},
+    puzzle::{Solution, BACKGROUND},
+};
+
+/// The clues of a Mosaic puzzle, indexed `[x][y]`, like `Solution::grid`. `None` is a cell with no
+/// clue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mosaic {
+    pub clues: Vec<Vec<Option<u8>>>,
+}
+
+impl Mosaic {
+    pub fn width(&self) -> usize {
+        self.clues.len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.clues.first().map_or(0, Vec::len)
+    }
+
+    /// Clues that are actually there.
+    pub fn num_clues(&self) -> usize {
+        self.clues.iter().flatten().filter(|c| c.is_some()).count()
+    }
+}
+
+/// The cells around (x, y), including itself, that are inside a `width` by `height` grid.
+fn neighborhood(
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let xs = x.saturating_sub(1)..std::cmp::min(x + 2, width);
+    let ys = y.saturating_sub(1)..std::cmp::min(y + 2, height);
+    xs.flat_map(move |nx| ys.clone().map(move |ny| (nx, ny)))
+}
+
+/// Every clue, for every cell of the picture (except ones outside its outline).
+pub fn clues_from_solution(solution: &Solution) -> Mosaic {
+    let width = solution.grid.len();
+    let height = solution.grid.first().map_or(0, Vec::len);
+    let filled = |x: usize, y: usize| solution.grid[x][y] != BACKGROUND;
+
+    Mosaic {
+        clues: (0..width)
+            .map(|x| {
+                (0..height)
+                    .map(|y| {
+                        if solution.void.contains(&(x, y)) {
+                            return None;
+                        }
+                        let count = neighborhood(width, height, x, y)
+                            .filter(|&(nx, ny)| filled(nx, ny))
+                            .count();
+                        Some(count as u8)
+                    })
+                    .collect()
+            })
+            .collect(),
+    }
+}
+
+/// How a `solve` went.
+#[derive(Clone, Debug)]
+pub struct MosaicReport {
+    /// Cells the solver couldn't determine; 0 if the puzzle was solved.
+    pub cells_left: usize,
+    /// Deductions from one clue at a time.
+    pub single_steps: usize,
+    /// Deductions from comparing two overlapping clues, which are harder to spot.
+    pub pair_steps: usize,
+    /// `Some(true)` for filled, indexed `[x][y]`.
+    pub grid: Vec<Vec<Option<bool>>>,
+}
+
+/// What's known about the cells around one clue.
+struct Around {
+    unknown: Vec<(usize, usize)>,
+    /// Filled cells still to be found among `unknown`.
+    remaining: usize,
+}
+
+fn around(mosaic: &Mosaic, grid: &[Vec<Option<bool>>], x: usize, y: usize) -> Result<Around> {
+    let clue = match mosaic.clues[x][y] {
+        Some(clue) => clue as usize,
+        None => unreachable!("only called on clues"),
+    };
+    let mut unknown = vec![];
+    let mut filled = 0;
+    for (nx, ny) in neighborhood(mosaic.width(), mosaic.height(), x, y) {
+        match grid[nx][ny] {
+            Some(true) => filled += 1,
+            Some(false) => {}
+            None => unknown.push((nx, ny)),
+        }
+    }
+    if filled > clue || filled + unknown.len() < clue {
+        return Err(Error::CellContradiction {
+            x,
+            y,
+            message: format!(
+                "the clue is {}, but {} cells around it are filled and {} are undetermined",
+                clue,
+                filled,
+                unknown.len()
+            ),
+        });
+    }
+    Ok(Around {
+        unknown,
+        remaining: clue - filled,
+    })
+}
+
+/// Sets every cell in `cells` to `value`, and returns how many that changed.
+fn learn(grid: &mut [Vec<Option<bool>>], cells: &[(usize, usize)], value: bool) -> usize {
+    let mut learned = 0;
+    for &(x, y) in cells {
+        if grid[x][y].is_none() {
+            grid[x][y] = Some(value);
+            learned += 1;
+        }
+    }
+    learned
+}
+
+/// Solves with the usual two techniques: a clue whose cells must all be filled (or all empty),
+/// and, when that runs out, two overlapping clues whose difference settles the cells only one of
+/// them covers.
+pub fn solve(mosaic: &Mosaic) -> Result<MosaicReport> {
+    let (width, height) = (mosaic.width(), mosaic.height());
+    let mut grid = vec![vec![None; height]; width];
+    let clue_cells: Vec<(usize, usize)> = (0..width)
+        .flat_map(|x| (0..height).map(move |y| (x, y)))
+        .filter(|&(x, y)| mosaic.clues[x][y].is_some())
+        .collect();
+    let mut report = MosaicReport {
+        cells_left: width * height,
+        single_steps: 0,
+        pair_steps: 0,
+        grid: vec![],
+    };
+
+    loop {
+        let mut learned = 0;
+        for &(x, y) in &clue_cells {
+            let around = around(mosaic, &grid, x, y)?;
+            if around.unknown.is_empty() {
+                continue;
+            }
+            if around.remaining == 0 {
+                learned += learn(&mut grid, &around.unknown, false);
+            } else if around.remaining == around.unknown.len() {
+                learned += learn(&mut grid, &around.unknown, true);
+            } else {
+                continue;
+            }
+            report.single_steps += 1;
+        }
+        if learned > 0 {
+            report.cells_left -= learned;
+            continue;
+        }
+
+        for (i, &(ax, ay)) in clue_cells.iter().enumerate() {
+            for &(bx, by) in &clue_cells[i + 1..] {
+                if ax.abs_diff(bx) > 2 || ay.abs_diff(by) > 2 {
+                    continue; // No overlap
+                }
+                let a = around(mosaic, &grid, ax, ay)?;
+                let b = around(mosaic, &grid, bx, by)?;
+                let only_a: Vec<_> = a
+                    .unknown
+                    .iter()
+                    .filter(|c| !b.unknown.contains(c))
+                    .copied()
+                    .collect();
+                let only_b: Vec<_> = b
+                    .unknown
+                    .iter()
+                    .filter(|c| !a.unknown.contains(c))
+                    .copied()
+                    .collect();
+                let shared = a.unknown.len() - only_a.len();
+
+                // How many of the shared cells can be filled, as far as each clue is concerned.
+                let shared_min = a
+                    .remaining
+                    .saturating_sub(only_a.len())
+                    .max(b.remaining.saturating_sub(only_b.len()));
+                let shared_max = shared.min(a.remaining).min(b.remaining);
+                if shared_min > shared_max {
+                    return Err(Error::CellContradiction {
+                        x: bx,
+                        y: by,
+                        message: format!(
+                            "the clue disagrees with the one at row {}, column {}",
+                            ay + 1,
+                            ax + 1
+                        ),
+                    });
+                }
+
+                let mut pair_learned = 0;
+                for (only, remaining) in [(&only_a, a.remaining), (&only_b, b.remaining)] {
+                    if only.is_empty() {
+                        continue;
+                    }
+                    // Between `remaining - shared_max` and `remaining - shared_min` of these are
+                    // filled.
+                    if remaining == shared_min {
+                        pair_learned += learn(&mut grid, only, false);
+                    } else if remaining - shared_max == only.len() {
+                        pair_learned += learn(&mut grid, only, true);
+                    }
+                }
+                if pair_learned > 0 {
+                    report.pair_steps += 1;
+                    learned += pair_learned;
+                }
+            }
+        }
+        if learned == 0 {
+            break;
+        }
+        report.cells_left -= learned;
+    }
+
+    report.grid = grid;
+    Ok(report)
+}
+
+/// Removes clues that the solver doesn't need, one at a time in reading order, so that the puzzle
+/// isn't just a wall of digits. `mosaic` has to be solvable to begin with. Returns how many clues
+/// were removed.
+pub fn minimize(mosaic: &mut Mosaic) -> Result<usize> {
+    let mut removed = 0;
+    for y in 0..mosaic.height() {
+        for x in 0..mosaic.width() {
+            let clue = match mosaic.clues[x][y].take() {
+                Some(clue) => clue,
+                None => continue,
+            };
+            if solve(mosaic)?.cells_left == 0 {
+                removed += 1;
+            } else {
+                mosaic.clues[x][y] = Some(clue);
+            }
+        }
+    }
+    Ok(removed)
+}
+
+/// The usual text form: a line per row, with a digit for each clue and `.` for cells without one.
+pub fn as_text(mosaic: &Mosaic) -> String {
+    let mut res = String::new();
+    for y in 0..mosaic.height() {
+        for x in 0..mosaic.width() {
+            res.push(match mosaic.clues[x][y] {
+                Some(clue) => char::from(b'0' + clue),
+                None => '.',
+            });
+        }
+        res.push('\n');
+    }
+    res
+}
+
+#[test]
+fn mosaic_test() {
+    let solution = crate::import::char_grid_to_solution(
+        "#....\n##...\n.###.\n...##\n....#\n",
+        true,
+        &Default::default(),
+    )
+    .unwrap();
+    let mut mosaic = clues_from_solution(&solution);
+    assert_eq!(mosaic.clues[0][0], Some(3));
+    assert_eq!(mosaic.clues[4][0], Some(0));
+
+    let report = solve(&mosaic).unwrap();
+    assert_eq!(report.cells_left, 0);
+    assert_eq!(report.grid[1][2], Some(true));
+
+    let all = mosaic.num_clues();
+    let removed = minimize(&mut mosaic).unwrap();
+    assert!(removed > 0);
+    assert_eq!(mosaic.num_clues(), all - removed);
+    assert_eq!(solve(&mosaic).unwrap().cells_left, 0);
+}
@@ -0,0 +1,779 @@
+//! `play`: solving a puzzle by hand, in the terminal.
+
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use convert_nonogram::{
+    line_solve::{placed_clues, Cell},
+    puzzle::{Clue, Color, Puzzle, Solution, SolverStats, BACKGROUND},
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    style::{Color as TermColor, Modifier, Style},
+    text::{Line, Span},
+    widgets::Paragraph,
+    DefaultTerminal, Frame,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Mark {
+    Unknown,
+    Filled(Color),
+    /// Known to be background.
+    Crossed,
+    /// A pencil mark: probably filled, but not for sure.
+    Maybe,
+    /// Pencil marks for the colors the cell might be, a bit per `Color`.
+    Candidates(u32),
+}
+
+/// How the game behaves, from the command line.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// Take back wrong marks as soon as they're made, counting each one as a mistake.
+    pub auto_check: bool,
+    /// The file to add a `Session` to when the puzzle is solved, and to keep unfinished progress
+    /// in (see `record_path`).
+    pub record: Option<PathBuf>,
+    /// Ignore any unfinished progress in the record, and start over.
+    pub restart: bool,
+    /// What the line solver made of the puzzle, to compare with the player.
+    pub solver_stats: Option<SolverStats>,
+}
+
+/// How a solve went, for comparing how tedious puzzles are for people with how hard they are for
+/// the line solver.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct Session {
+    /// When the puzzle was solved, in RFC 3339 form.
+    pub finished: String,
+    pub seconds: u64,
+    /// Cells filled in (with any color), including ones filled again after being changed.
+    pub fills: usize,
+    pub crosses: usize,
+    /// Marks taken back by hand.
+    pub erases: usize,
+    /// Wrong marks that checking found (or that `Options::auto_check` took back).
+    pub mistakes: usize,
+    pub checks: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solver: Option<SolverStats>,
+}
+
+impl std::fmt::Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{:02}, {} fills, {} crosses, {} erased, {} mistakes, {} checks",
+            self.seconds / 60,
+            self.seconds % 60,
+            self.fills,
+            self.crosses,
+            self.erases,
+            self.mistakes,
+            self.checks
+        )
+    }
+}
+
+/// Everything `play` keeps about a puzzle, next to it.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct Record {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    sessions: Vec<Session>,
+    /// Where the player left off, if they quit without solving it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    progress: Option<Progress>,
+}
+
+/// An unfinished solve, to pick up again next time.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Progress {
+    /// Indexed `[x][y]`, like `Game::marks`.
+    marks: Vec<Vec<Mark>>,
+    /// The tallies so far; `seconds` is the time spent so far.
+    stats: Session,
+}
+
+/// Where `play` keeps its record of a puzzle: next to it, e.g. `tea.xml.play.json`.
+pub fn record_path(puzzle_path: &std::path::Path) -> PathBuf {
+    let mut name = puzzle_path.as_os_str().to_owned();
+    name.push(".play.json");
+    PathBuf::from(name)
+}
+
+fn read_record(path: &std::path::Path) -> std::io::Result<Record> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Record::default()),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_record(path: &std::path::Path, record: &Record) -> std::io::Result<()> {
+    let bytes = serde_json::to_vec_pretty(record)?;
+    std::fs::write(path, bytes)
+}
+
+struct Game<'a> {
+    puzzle: &'a Puzzle,
+    solution: &'a Solution,
+    options: Options,
+    /// Indexed `[x][y]`, like `Solution::grid`.
+    marks: Vec<Vec<Mark>>,
+    cursor: (usize, usize),
+    /// The foreground colors, in order, and which one is being painted with.
+    colors: Vec<Color>,
+    current: usize,
+    started: Instant,
+    /// Set once the picture is complete, to stop the clock.
+    solved_in: Option<Duration>,
+    message: String,
+    /// Cells that were wrong the last time the player checked, until they change them.
+    flagged: BTreeSet<(usize, usize)>,
+    /// The tallies for `Session`.
+    stats: Session,
+    /// Which clues in each row and column have been filled in, so they can be crossed off.
+    /// `None` means the marks in that lane can't match its clues.
+    row_progress: Vec<Option<Vec<bool>>>,
+    col_progress: Vec<Option<Vec<bool>>>,
+}
+
+fn term_color(puzzle: &Puzzle, color: Color) -> TermColor {
+    let (r, g, b) = puzzle.palette[&color].rgb;
+    TermColor::Rgb(r, g, b)
+}
+
+/// `done` is `Some(true)` for a clue that's been filled in, and `None` if its lane has gone wrong.
+fn clue_span(puzzle: &Puzzle, clue: &Clue, width: usize, done: Option<bool>) -> Span<'static> {
+    let style = match done {
+        Some(true) => Style::default().fg(TermColor::DarkGray),
+        Some(false) => Style::default().fg(term_color(puzzle, clue.color)),
+        None => Style::default()
+            .fg(term_color(puzzle, clue.color))
+            .bg(TermColor::Red),
+    };
+    Span::styled(format!("{:>width$}", clue.count_text()), style)
+}
+
+/// Looks up what `clue_span` needs.
+fn clue_done(progress: &Option<Vec<bool>>, idx: usize) -> Option<bool> {
+    progress.as_ref().map(|done| done[idx])
+}
+
+impl<'a> Game<'a> {
+    fn new(puzzle: &'a Puzzle, solution: &'a Solution, options: Options) -> Game<'a> {
+        let mut colors: Vec<Color> = puzzle
+            .palette
+            .keys()
+            .copied()
+            .filter(|color| *color != BACKGROUND)
+            .collect();
+        colors.sort_by_key(|color| color.0);
+        if colors.is_empty() {
+            colors.push(BACKGROUND); // Nothing to fill in, but painting needs a color
+        }
+
+        let mut game = Game {
+            puzzle,
+            solution,
+            options,
+            marks: vec![vec![Mark::Unknown; puzzle.rows.len()]; puzzle.cols.len()],
+            cursor: (0, 0),
+            colors,
+            current: 0,
+            started: Instant::now(),
+            solved_in: None,
+            message: String::new(),
+            flagged: BTreeSet::new(),
+            stats: Session::default(),
+            row_progress: vec![None; puzzle.rows.len()],
+            col_progress: vec![None; puzzle.cols.len()],
+        };
+        game.update_all();
+        game
+    }
+
+    fn width(&self) -> usize {
+        self.marks.len()
+    }
+
+    fn height(&self) -> usize {
+        self.marks.first().map_or(0, Vec::len)
+    }
+
+    fn is_void(&self, x: usize, y: usize) -> bool {
+        self.solution.void.contains(&(x, y))
+    }
+
+    /// Whether the mark at (x, y) disagrees with the solution. Unmarked cells aren't wrong yet.
+    fn is_wrong(&self, x: usize, y: usize) -> bool {
+        let actual = self.solution.grid[x][y];
+        match self.marks[x][y] {
+            Mark::Filled(color) => color != actual,
+            Mark::Crossed => actual != BACKGROUND,
+            Mark::Unknown | Mark::Maybe | Mark::Candidates(_) => false,
+        }
+    }
+
+    /// What the player has said about the cell at (x, y), for the line solver. Pencil marks
+    /// don't count.
+    fn known(&self, x: usize, y: usize) -> Cell {
+        match self.marks[x][y] {
+            _ if self.is_void(x, y) => Cell::from_color(BACKGROUND),
+            Mark::Filled(color) => Cell::from_color(color),
+            Mark::Crossed => Cell::from_color(BACKGROUND),
+            Mark::Unknown | Mark::Maybe | Mark::Candidates(_) => Cell::new(self.puzzle),
+        }
+    }
+
+    fn progress(&self, clues: &[Clue], lane: Vec<Cell>) -> Option<Vec<bool>> {
+        placed_clues(
+            clues,
+            self.puzzle.separate_blocks,
+            ndarray::Array1::from(lane).view(),
+        )
+        .ok()
+    }
+
+    fn update_row(&mut self, y: usize) {
+        let lane = (0..self.width()).map(|x| self.known(x, y)).collect();
+        self.row_progress[y] = self.progress(&self.puzzle.rows[y], lane);
+    }
+
+    fn update_col(&mut self, x: usize) {
+        let lane = (0..self.height()).map(|y| self.known(x, y)).collect();
+        self.col_progress[x] = self.progress(&self.puzzle.cols[x], lane);
+    }
+
+    fn update_all(&mut self) {
+        for y in 0..self.height() {
+            self.update_row(y);
+        }
+        for x in 0..self.width() {
+            self.update_col(x);
+        }
+    }
+
+    fn set(&mut self, mark: Mark) {
+        let (x, y) = self.cursor;
+        if self.solved_in.is_some() || self.is_void(x, y) {
+            return;
+        }
+        let previous = self.marks[x][y];
+        // Doing the same thing twice undoes it.
+        self.marks[x][y] = if previous == mark {
+            Mark::Unknown
+        } else {
+            mark
+        };
+        // A wrong mark is refused, leaving the cell (and the clues) as they were.
+        if self.options.auto_check && self.is_wrong(x, y) {
+            self.marks[x][y] = previous;
+            self.stats.mistakes += 1;
+            self.message = "Wrong!".to_string();
+            return;
+        }
+        self.message.clear();
+        self.flagged.remove(&(x, y));
+        match self.marks[x][y] {
+            Mark::Filled(_) => self.stats.fills += 1,
+            Mark::Crossed => self.stats.crosses += 1,
+            Mark::Unknown => self.stats.erases += 1,
+            Mark::Maybe | Mark::Candidates(_) => {}
+        }
+
+        self.update_row(y);
+        self.update_col(x);
+
+        let done = (0..self.width()).all(|x| {
+            (0..self.height()).all(|y| {
+                let filled = matches!(self.marks[x][y], Mark::Filled(_));
+                match self.solution.grid[x][y] {
+                    color if color == BACKGROUND => !filled,
+                    color => self.marks[x][y] == Mark::Filled(color),
+                }
+            })
+        });
+        if done {
+            self.finish();
+        }
+    }
+
+    fn finish(&mut self) {
+        let elapsed = self.started.elapsed();
+        self.solved_in = Some(elapsed);
+        self.stats.seconds = elapsed.as_secs();
+        self.stats.finished =
+            humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string();
+        self.stats.solver = self.options.solver_stats;
+        if let Some(path) = &self.options.record {
+            let result = read_record(path).and_then(|mut record| {
+                record.sessions.push(self.stats.clone());
+                record.progress = None;
+                write_record(path, &record)
+            });
+            if let Err(e) = result {
+                self.message = format!("Couldn't save the stats: {}", e);
+            }
+        }
+    }
+
+    /// Changes the pencil marks on the cell under the cursor. They don't count as filling or
+    /// crossing out, and checking ignores them.
+    fn pencil(&mut self, change: impl FnOnce(Mark) -> Mark) {
+        let (x, y) = self.cursor;
+        if self.solved_in.is_some() || self.is_void(x, y) {
+            return;
+        }
+        self.marks[x][y] = change(self.marks[x][y]);
+        self.message.clear();
+        self.flagged.remove(&(x, y));
+        self.update_row(y);
+        self.update_col(x);
+    }
+
+    /// Picks up where the player left off last time, if the marks still fit the puzzle.
+    fn resume(&mut self, progress: Progress) {
+        let fits = progress.marks.len() == self.width()
+            && progress.marks.iter().all(|col| col.len() == self.height())
+            && progress.marks.iter().flatten().all(|mark| match mark {
+                Mark::Filled(color) => self.colors.contains(color),
+                _ => true,
+            });
+        if !fits {
+            self.message = "The puzzle has changed since last time, so starting over.".to_string();
+            return;
+        }
+        self.marks = progress.marks;
+        self.started = Instant::now()
+            .checked_sub(Duration::from_secs(progress.stats.seconds))
+            .unwrap_or(self.started);
+        self.stats = progress.stats;
+        self.message = "Picked up where you left off.".to_string();
+        self.update_all();
+    }
+
+    /// Keeps the marks in the record, so that quitting before it's solved doesn't lose them.
+    fn save_progress(&mut self) -> std::io::Result<()> {
+        let Some(path) = &self.options.record else {
+            return Ok(());
+        };
+        if self.solved_in.is_some() {
+            return Ok(()); // `finish` took care of the record
+        }
+        let mut record = read_record(path)?;
+        let untouched = self
+            .marks
+            .iter()
+            .flatten()
+            .all(|mark| *mark == Mark::Unknown);
+        if untouched && record.progress.is_none() {
+            return Ok(()); // Just looking; no need to write anything
+        }
+        self.stats.seconds = self.started.elapsed().as_secs();
+        record.progress = (!untouched).then(|| Progress {
+            marks: self.marks.clone(),
+            stats: self.stats.clone(),
+        });
+        write_record(path, &record)
+    }
+
+    /// Highlights the marks that disagree with the solution, and counts them.
+    fn check(&mut self) {
+        let flagged: BTreeSet<(usize, usize)> = (0..self.width())
+            .flat_map(|x| (0..self.height()).map(move |y| (x, y)))
+            .filter(|&(x, y)| self.is_wrong(x, y))
+            .collect();
+        // Cells still flagged from the last check were already counted.
+        self.stats.mistakes += flagged.difference(&self.flagged).count();
+        self.stats.checks += 1;
+        self.flagged = flagged;
+        self.message = match self.flagged.len() {
+            0 => "No mistakes so far.".to_string(),
+            1 => "1 cell is wrong.".to_string(),
+            n => format!("{} cells are wrong.", n),
+        };
+    }
+
+    /// Handles a key, and returns false to quit.
+    fn handle(&mut self, code: KeyCode) -> bool {
+        let (x, y) = self.cursor;
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Left | KeyCode::Char('h') => self.cursor.0 = x.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') => self.cursor.0 = (x + 1).min(self.width() - 1),
+            KeyCode::Up | KeyCode::Char('k') => self.cursor.1 = y.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => self.cursor.1 = (y + 1).min(self.height() - 1),
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                self.set(Mark::Filled(self.colors[self.current]))
+            }
+            KeyCode::Char('x') => self.set(Mark::Crossed),
+            KeyCode::Backspace | KeyCode::Delete => self.set(Mark::Unknown),
+            KeyCode::Tab => self.current = (self.current + 1) % self.colors.len(),
+            KeyCode::Char(digit @ '1'..='9') => {
+                let idx = digit as usize - '1' as usize;
+                if idx < self.colors.len() {
+                    self.current = idx;
+                }
+            }
+            KeyCode::Char('?') => self.pencil(|mark| match mark {
+                Mark::Maybe => Mark::Unknown,
+                _ => Mark::Maybe,
+            }),
+            KeyCode::Char('.') => {
+                let bit = 1 << self.colors[self.current].0;
+                self.pencil(|mark| match mark {
+                    Mark::Candidates(bits) if bits == bit => Mark::Unknown,
+                    Mark::Candidates(bits) => Mark::Candidates(bits ^ bit),
+                    _ => Mark::Candidates(bit),
+                })
+            }
+            KeyCode::Char('c') => self.check(),
+            _ => {}
+        }
+        true
+    }
+
+    /// E.g. "1:23, 40 fills, ... (line solver: 30 skims, 2 scrubs, 0 cells left)".
+    fn summary(&self) -> String {
+        match &self.stats.solver {
+            Some(solver) => format!("{} (line solver: {})", self.stats, solver),
+            None => self.stats.to_string(),
+        }
+    }
+
+    /// How the cell at (x, y) is drawn: two characters wide, in one or two spans.
+    fn cell_shape(&self, x: usize, y: usize) -> Vec<Span<'static>> {
+        let puzzle = self.puzzle;
+        let mut spans = match self.marks[x][y] {
+            _ if self.is_void(x, y) => vec![Span::raw("  ")],
+            Mark::Unknown => vec![Span::styled(" ·", Style::default().fg(TermColor::DarkGray))],
+            Mark::Crossed => vec![Span::raw(" ×")],
+            // Shaded when it's wrong, so that the red shows through.
+            Mark::Filled(color) if self.flagged.contains(&(x, y)) => {
+                vec![Span::styled(
+                    "▒▒",
+                    Style::default().fg(term_color(puzzle, color)),
+                )]
+            }
+            Mark::Filled(color) => {
+                vec![Span::styled(
+                    "██",
+                    Style::default().fg(term_color(puzzle, color)),
+                )]
+            }
+            Mark::Maybe => vec![Span::raw(" ?")],
+            Mark::Candidates(bits) => {
+                let candidates: Vec<Color> = self
+                    .colors
+                    .iter()
+                    .copied()
+                    .filter(|color| bits & (1 << color.0) != 0)
+                    .collect();
+                let dot = |color: Color| {
+                    Span::styled("•", Style::default().fg(term_color(puzzle, color)))
+                };
+                match candidates.as_slice() {
+                    [only] => vec![Span::raw(" "), dot(*only)],
+                    [first, second] => vec![dot(*first), dot(*second)],
+                    // No room for them all.
+                    [first, ..] => vec![dot(*first), Span::raw("+")],
+                    [] => vec![Span::raw("  ")],
+                }
+            }
+        };
+        for span in &mut spans {
+            if self.flagged.contains(&(x, y)) {
+                span.style = span.style.bg(TermColor::Red);
+            }
+            if (x, y) == self.cursor {
+                span.style = span.style.add_modifier(Modifier::REVERSED);
+            }
+        }
+        spans
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let puzzle = self.puzzle;
+        let row_clue_width = puzzle
+            .rows
+            .iter()
+            .map(|clues| {
+                clues
+                    .iter()
+                    .map(|clue| clue.count_text().len() + 1)
+                    .sum::<usize>()
+            })
+            .max()
+            .unwrap_or(0);
+        let col_clue_height = puzzle.cols.iter().map(Vec::len).max().unwrap_or(0);
+
+        let mut lines = vec![];
+        // Column clues, bottom-aligned, two characters per cell.
+        for line_idx in 0..col_clue_height {
+            let mut spans = vec![Span::raw(" ".repeat(row_clue_width))];
+            for (x, clues) in puzzle.cols.iter().enumerate() {
+                let skip = col_clue_height - clues.len();
+                spans.push(match line_idx.checked_sub(skip) {
+                    Some(i) => clue_span(puzzle, &clues[i], 2, clue_done(&self.col_progress[x], i)),
+                    None => Span::raw("  "),
+                });
+            }
+            lines.push(Line::from(spans));
+        }
+
+        for y in 0..self.height() {
+            let clue_text: usize = puzzle.rows[y]
+                .iter()
+                .map(|clue| clue.count_text().len() + 1)
+                .sum();
+            let mut spans = vec![Span::raw(" ".repeat(row_clue_width - clue_text))];
+            for (i, clue) in puzzle.rows[y].iter().enumerate() {
+                let done = clue_done(&self.row_progress[y], i);
+                spans.push(clue_span(puzzle, clue, clue.count_text().len(), done));
+                spans.push(Span::raw(" "));
+            }
+            for x in 0..self.width() {
+                spans.extend(self.cell_shape(x, y));
+            }
+            lines.push(Line::from(spans));
+        }
+
+        lines.push(Line::raw(""));
+        let elapsed = self.solved_in.unwrap_or_else(|| self.started.elapsed());
+        let clock = format!("{}:{:02}", elapsed.as_secs() / 60, elapsed.as_secs() % 60);
+        let mut status = vec![Span::raw(clock), Span::raw("  painting with ")];
+        status.push(Span::styled(
+            "██",
+            Style::default().fg(term_color(puzzle, self.colors[self.current])),
+        ));
+        if self.options.auto_check {
+            status.push(Span::raw(format!("  mistakes: {}", self.stats.mistakes)));
+        }
+        if self.solved_in.is_some() {
+            status.push(Span::styled(
+                "  Solved!",
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+        }
+        if !self.message.is_empty() {
+            status.push(Span::raw(format!("  {}", self.message)));
+        }
+        lines.push(Line::from(status));
+        if self.solved_in.is_some() {
+            lines.push(Line::raw(self.summary()));
+        }
+        lines.push(Line::raw(
+            "arrows/hjkl: move  space: fill  x: cross  ?: maybe  .: might be this color  1-9/tab: \
+             color  c: check  q: quit",
+        ));
+
+        frame.render_widget(Paragraph::new(lines), frame.area());
+    }
+}
+
+/// Runs the game until the player quits. `solution` is what their marks are checked against. If
+/// they solved it, the summary of how it went is printed afterwards.
+pub fn play(puzzle: &Puzzle, solution: &Solution, options: Options) -> std::io::Result<()> {
+    let mut game = Game::new(puzzle, solution, options);
+    if let Some(path) = game.options.record.clone() {
+        if !game.options.restart {
+            if let Some(progress) = read_record(&path)?.progress {
+                game.resume(progress);
+            }
+        }
+    }
+
+    let mut terminal = ratatui::init();
+    let result = run(&mut game, &mut terminal);
+    ratatui::restore();
+    game.save_progress()?;
+    if game.solved_in.is_some() {
+        eprintln!("Solved: {}", game.summary());
+    }
+    result
+}
+
+fn run(game: &mut Game, terminal: &mut DefaultTerminal) -> std::io::Result<()> {
+    loop {
+        terminal.draw(|frame| game.draw(frame))?;
+        // Wake up now and then, to keep the clock ticking.
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !game.handle(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_puzzle(grid: &str) -> (Puzzle, Solution) {
+    use convert_nonogram::import;
+    let solution =
+        import::char_grid_to_solution(grid, true, &std::collections::HashSet::new()).unwrap();
+    (import::solution_to_puzzle(&solution).unwrap(), solution)
+}
+
+#[test]
+fn set_test() {
+    let (puzzle, solution) = test_puzzle("#.\n##\n");
+    let mut game = Game::new(&puzzle, &solution, Options::default());
+    let ink = Mark::Filled(game.colors[0]);
+
+    game.set(ink);
+    assert_eq!(game.marks[0][0], ink);
+    assert_eq!(game.row_progress[0], Some(vec![true]));
+    // The same mark again takes it back.
+    game.set(ink);
+    assert_eq!(game.marks[0][0], Mark::Unknown);
+    assert_eq!(game.row_progress[0], Some(vec![false]));
+    // A different one replaces it.
+    game.set(Mark::Crossed);
+    game.set(ink);
+    assert_eq!(game.marks[0][0], ink);
+    assert_eq!((game.stats.fills, game.stats.crosses), (2, 1));
+    assert_eq!(game.stats.erases, 1);
+
+    // Filling in the rest finishes the puzzle, and then marks are ignored.
+    for cursor in [(0, 1), (1, 1)].iter().copied() {
+        game.cursor = cursor;
+        game.set(ink);
+    }
+    assert!(game.solved_in.is_some());
+    game.cursor = (1, 0);
+    game.set(ink);
+    assert_eq!(game.marks[1][0], Mark::Unknown);
+}
+
+#[test]
+fn auto_check_test() {
+    let (puzzle, solution) = test_puzzle("#.\n##\n");
+    let options = Options {
+        auto_check: true,
+        ..Options::default()
+    };
+    let mut game = Game::new(&puzzle, &solution, options);
+    let ink = Mark::Filled(game.colors[0]);
+
+    game.cursor = (1, 0);
+    game.set(Mark::Crossed);
+    // A wrong mark is refused, and the cell keeps what it had.
+    game.set(ink);
+    assert_eq!(game.marks[1][0], Mark::Crossed);
+    assert_eq!(game.stats.mistakes, 1);
+    assert_eq!(game.message, "Wrong!");
+    assert_eq!(game.row_progress[0], Some(vec![false]));
+}
+
+#[test]
+fn check_test() {
+    let (puzzle, solution) = test_puzzle("#.\n##\n");
+    let mut game = Game::new(&puzzle, &solution, Options::default());
+    let ink = Mark::Filled(game.colors[0]);
+
+    game.cursor = (1, 0);
+    game.set(ink);
+    game.check();
+    assert_eq!(game.message, "1 cell is wrong.");
+    assert_eq!(game.flagged.len(), 1);
+    // Checking again doesn't count the same mistake twice.
+    game.check();
+    assert_eq!((game.stats.mistakes, game.stats.checks), (1, 2));
+
+    game.set(Mark::Crossed);
+    assert!(game.flagged.is_empty());
+    game.check();
+    assert_eq!(game.message, "No mistakes so far.");
+    // But making it again does count.
+    game.set(ink);
+    game.check();
+    assert_eq!((game.stats.mistakes, game.stats.checks), (2, 4));
+}
+
+#[test]
+fn pencil_test() {
+    let (puzzle, solution) = test_puzzle("#.\n##\n");
+    let mut game = Game::new(&puzzle, &solution, Options::default());
+
+    game.cursor = (1, 0);
+    game.pencil(|_| Mark::Maybe);
+    assert_eq!(game.marks[1][0], Mark::Maybe);
+    assert_eq!(game.stats.fills, 0);
+    // Pencil marks are never wrong, and tell the clues nothing.
+    game.check();
+    assert_eq!(game.stats.mistakes, 0);
+    assert_eq!(game.row_progress[0], Some(vec![false]));
+}
+
+#[test]
+fn resume_test() {
+    let (puzzle, solution) = test_puzzle("#.\n##\n");
+    let mut game = Game::new(&puzzle, &solution, Options::default());
+    let ink = Mark::Filled(game.colors[0]);
+    let progress = |marks: Vec<Vec<Mark>>| Progress {
+        marks,
+        stats: Session {
+            fills: 1,
+            ..Session::default()
+        },
+    };
+
+    // The wrong size, or a color the puzzle doesn't have, means the puzzle has changed.
+    game.resume(progress(vec![vec![Mark::Unknown; 3]; 3]));
+    game.resume(progress(vec![
+        vec![Mark::Filled(Color(9)), Mark::Unknown],
+        vec![Mark::Unknown; 2],
+    ]));
+    assert!(game.message.starts_with("The puzzle has changed"));
+    assert!(game
+        .marks
+        .iter()
+        .flatten()
+        .all(|mark| *mark == Mark::Unknown));
+    assert_eq!(game.stats.fills, 0);
+
+    game.resume(progress(vec![
+        vec![ink, Mark::Unknown],
+        vec![Mark::Unknown; 2],
+    ]));
+    assert_eq!(game.marks[0][0], ink);
+    assert_eq!(game.stats.fills, 1);
+    assert_eq!(game.row_progress[0], Some(vec![true]));
+}
+
+#[test]
+fn save_progress_test() {
+    let (puzzle, solution) = test_puzzle("#.\n##\n");
+    let path = std::env::temp_dir().join(format!("play-test-{}.play.json", std::process::id()));
+    let options = Options {
+        record: Some(path.clone()),
+        ..Options::default()
+    };
+
+    // Just looking doesn't leave a record behind.
+    let mut game = Game::new(&puzzle, &solution, options.clone());
+    game.save_progress().unwrap();
+    assert!(!path.exists());
+
+    game.set(Mark::Filled(game.colors[0]));
+    game.save_progress().unwrap();
+    let mut resumed = Game::new(&puzzle, &solution, options);
+    resumed.resume(read_record(&path).unwrap().progress.unwrap());
+    assert_eq!(resumed.marks, game.marks);
+    assert_eq!(resumed.stats.fills, 1);
+
+    // Taking everything back clears the progress.
+    game.set(Mark::Unknown);
+    game.save_progress().unwrap();
+    assert!(read_record(&path).unwrap().progress.is_none());
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,17 +1,79 @@
-use std::collections::HashMap;
+//! The data model: clues, colors, and pictures.
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(PartialEq, Eq, Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub struct Clue {
     pub color: Color,
+    /// The length of the block, or the least it can be if `length` isn't `Exact`.
     pub count: u16,
+    #[serde(default, skip_serializing_if = "Length::is_exact")]
+    pub length: Length,
+}
+
+/// How well a clue's length is known, for magazine variants that print some clues as ranges or
+/// blot them out.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum Length {
+    /// Exactly `count`.
+    #[default]
+    Exact,
+    /// From `count` to this.
+    AtMost(u16),
+    /// Unknown, except that it's at least `count`.
+    Blotted,
+}
+
+impl Length {
+    pub fn is_exact(&self) -> bool {
+        *self == Length::Exact
+    }
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
+impl Clue {
+    /// An ordinary clue, whose length is known.
+    pub fn new(color: Color, count: u16) -> Clue {
+        Clue {
+            color,
+            count,
+            length: Length::Exact,
+        }
+    }
+
+    /// The longest the block can be, in a lane `lane_len` long.
+    pub fn max_count(&self, lane_len: usize) -> usize {
+        match self.length {
+            Length::Exact => self.count as usize,
+            Length::AtMost(max) => max as usize,
+            Length::Blotted => lane_len,
+        }
+    }
+
+    /// The clue as printed: "3", or "3-5" for a range, or "3+" (or "?", if it could be any
+    /// length) for a blot.
+    pub fn count_text(&self) -> String {
+        match self.length {
+            Length::Exact => self.count.to_string(),
+            Length::AtMost(max) => format!("{}-{}", self.count, max),
+            Length::Blotted if self.count <= 1 => "?".to_string(),
+            Length::Blotted => format!("{}+", self.count),
+        }
+    }
+
+    /// Whether there has to be background between a block for this clue and one for `next`.
+    /// Same-colored blocks always need it (or they'd be one block); with `separate_blocks`, all
+    /// blocks do.
+    pub fn must_be_separated_from(&self, next: &Clue, separate_blocks: bool) -> bool {
+        separate_blocks || self.color == next.color
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Hash, Debug, serde::Serialize, serde::Deserialize)]
 pub struct Color(pub u8);
 
 pub static BACKGROUND: Color = Color(0);
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct ColorInfo {
     pub ch: char,
     pub name: String,
@@ -19,15 +81,396 @@ pub struct ColorInfo {
     pub color: Color,
 }
 
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Solution {
     pub palette: HashMap<Color, ColorInfo>, // should include the background!
     pub grid: Vec<Vec<Color>>,
+    /// Cells outside an irregular outline, as (x, y). They're `BACKGROUND` in `grid`, so blocks
+    /// can't cross them, but they aren't part of the picture.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub void: BTreeSet<(usize, usize)>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct Puzzle {
     pub palette: HashMap<Color, ColorInfo>, // should include the background!
     pub rows: Vec<Vec<Clue>>,
     pub cols: Vec<Vec<Clue>>,
+    /// As in `Solution`; the solver knows these cells are empty from the start.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub void: BTreeSet<(usize, usize)>,
+    /// The "blocks may not touch" variant: there's background between every pair of blocks, even
+    /// ones of different colors.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub separate_blocks: bool,
+}
+
+/// A departure from the usual rules that a puzzle can make, which not every format can express.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    /// `Puzzle::separate_blocks`.
+    SeparateBlocks,
+    /// Cells outside an irregular outline (`Puzzle::void`).
+    Outline,
+    /// Clues that are ranges or blots, rather than exact lengths.
+    InexactClues,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 3] = [Rule::SeparateBlocks, Rule::Outline, Rule::InexactClues];
+
+    /// As in JSON, e.g. "separate-blocks".
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::SeparateBlocks => "separate-blocks",
+            Rule::Outline => "outline",
+            Rule::InexactClues => "inexact-clues",
+        }
+    }
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Rule::SeparateBlocks => "blocks that may not touch",
+            Rule::Outline => "cells outside the outline",
+            Rule::InexactClues => "clues that are ranges or blots",
+        })
+    }
+}
+
+/// How much room the clues take up when printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct ClueArea {
+    pub max_row_clues: usize,
+    pub max_col_clues: usize,
+    /// Width of the widest row's clues, in characters, with a space after each clue.
+    pub row_margin: usize,
+    /// Height of the tallest column's clues, in lines (one clue per line).
+    pub col_margin: usize,
+}
+
+impl Puzzle {
+    pub fn clue_area(&self) -> ClueArea {
+        let width = |clues: &Vec<Clue>| -> usize {
+            clues.iter().map(|clue| clue.count_text().len() + 1).sum()
+        };
+        let max_row_clues = self.rows.iter().map(Vec::len).max().unwrap_or(0);
+        let max_col_clues = self.cols.iter().map(Vec::len).max().unwrap_or(0);
+        ClueArea {
+            max_row_clues,
+            max_col_clues,
+            row_margin: self.rows.iter().map(width).max().unwrap_or(0),
+            col_margin: max_col_clues,
+        }
+    }
+
+    /// The rule variants this puzzle uses, in the order of `Rule::ALL`.
+    pub fn rules(&self) -> Vec<Rule> {
+        let inexact = self
+            .rows
+            .iter()
+            .chain(&self.cols)
+            .flatten()
+            .any(|clue| !clue.length.is_exact());
+        Rule::ALL
+            .iter()
+            .copied()
+            .filter(|&rule| match rule {
+                Rule::SeparateBlocks => self.separate_blocks,
+                Rule::Outline => !self.void.is_empty(),
+                Rule::InexactClues => inexact,
+            })
+            .collect()
+    }
+}
+
+/// A reusable, named set of colors (e.g. loaded from a GIMP `.gpl` file), so that a series of
+/// puzzles can share a consistent color scheme.
+#[derive(Clone, Debug)]
+pub struct PalettePreset {
+    pub name: String,
+    pub colors: Vec<PresetColor>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PresetColor {
+    pub ch: char,
+    pub name: String,
+    pub rgb: (u8, u8, u8),
+}
+
+/// Attribution and other information about a puzzle, which importers read and exporters write,
+/// where the format has room for it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Metadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copyright: Option<String>,
+    /// The terms the puzzle can be shared under, e.g. "CC BY-SA 4.0".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Free-form, since every publisher rates puzzles differently (e.g. "3 stars", "hard").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<String>,
+    /// What the line solver made of the puzzle, if someone asked for it to be recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub solver_stats: Option<SolverStats>,
+    /// A UUID, so that copies of a puzzle can be recognized across renames and conversions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// When the puzzle was first written out, in RFC 3339 form.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// When the puzzle's picture or clues last changed, in RFC 3339 form.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    /// The author's comments on particular cells and lanes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<Note>,
+    /// The picture the puzzle was imported from, so that it can be imported again with different
+    /// settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<Source>,
+}
+
+/// Where a puzzle's picture came from, and how it was read.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Source {
+    /// The original file, as an absolute path, if it was read from one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// A copy of the original file, base64-encoded, for when the path goes stale.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedded: Option<String>,
+    /// The command-line import options it was read with, e.g. `["--max-colors", "4"]`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+/// What a `Note` is about. Indices count from 0, like everywhere else in the data model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteTarget {
+    /// (x, y)
+    Cell(usize, usize),
+    Row(usize),
+    Column(usize),
+}
+
+/// A comment attached to part of the puzzle (e.g. "this pixel is only here to make the solution
+/// unique"), so that the reasons for design decisions aren't forgotten.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Note {
+    pub on: NoteTarget,
+    pub text: String,
+}
+
+/// E.g. "row 3, column 5: keeps the stem from being ambiguous", with 1-based indices.
+impl std::fmt::Display for Note {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.on {
+            NoteTarget::Cell(x, y) => write!(f, "row {}, column {}", y + 1, x + 1)?,
+            NoteTarget::Row(y) => write!(f, "row {}", y + 1)?,
+            NoteTarget::Column(x) => write!(f, "column {}", x + 1)?,
+        }
+        write!(f, ": {}", self.text)
+    }
+}
+
+impl Note {
+    /// Reads what `Display` writes; `None` if it's not in that form.
+    pub fn parse(text: &str) -> Option<Note> {
+        let re = regex::Regex::new(
+            r"(?is)^\s*(?:row (\d+), column (\d+)|row (\d+)|column (\d+))\s*:\s*(.*)$",
+        )
+        .unwrap();
+        let captures = re.captures(text)?;
+        let index = |i| -> Option<usize> {
+            captures
+                .get(i)?
+                .as_str()
+                .parse::<usize>()
+                .ok()?
+                .checked_sub(1)
+        };
+        let on = if let (Some(y), Some(x)) = (index(1), index(2)) {
+            NoteTarget::Cell(x, y)
+        } else if let Some(y) = index(3) {
+            NoteTarget::Row(y)
+        } else {
+            NoteTarget::Column(index(4)?)
+        };
+        Some(Note {
+            on,
+            text: captures[5].trim_end().to_string(),
+        })
+    }
+}
+
+/// How hard the line solver found a puzzle, recorded so that catalogs don't have to solve every
+/// puzzle again to sort them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SolverStats {
+    pub skims: usize,
+    pub scrubs: usize,
+    /// 0 if line logic solves the puzzle.
+    pub cells_left: usize,
+}
+
+impl std::fmt::Display for SolverStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} skims, {} scrubs, {} cells left",
+            self.skims, self.scrubs, self.cells_left
+        )
+    }
+}
+
+impl SolverStats {
+    /// Reads back what `Display` writes, for formats that only have room for text.
+    pub fn parse(text: &str) -> Option<SolverStats> {
+        let re = regex::Regex::new(r"(\d+) skims, (\d+) scrubs, (\d+) cells left").unwrap();
+        let (_, [skims, scrubs, cells_left]) = re.captures(text)?.extract();
+        Some(SolverStats {
+            skims: skims.parse().ok()?,
+            scrubs: scrubs.parse().ok()?,
+            cells_left: cells_left.parse().ok()?,
+        })
+    }
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        self == &Metadata::default()
+    }
+
+    /// Whether there's anything a person wrote about the puzzle, as opposed to bookkeeping like
+    /// `id`, `created`, and `source`, which a format can drop without anyone missing it.
+    pub fn has_attribution(&self) -> bool {
+        let fields = [
+            &self.title,
+            &self.author,
+            &self.copyright,
+            &self.license,
+            &self.description,
+            &self.difficulty,
+        ];
+        fields.iter().any(|field| field.is_some()) || !self.notes.is_empty()
+    }
+
+    /// Replaces fields with the ones `other` has, e.g. to apply command-line overrides.
+    pub fn merge(&mut self, other: Metadata) {
+        let fields = [
+            (&mut self.title, other.title),
+            (&mut self.author, other.author),
+            (&mut self.copyright, other.copyright),
+            (&mut self.license, other.license),
+            (&mut self.description, other.description),
+            (&mut self.difficulty, other.difficulty),
+            (&mut self.id, other.id),
+            (&mut self.created, other.created),
+            (&mut self.modified, other.modified),
+        ];
+        for (field, value) in fields {
+            if value.is_some() {
+                *field = value;
+            }
+        }
+        if other.solver_stats.is_some() {
+            self.solver_stats = other.solver_stats;
+        }
+        if other.source.is_some() {
+            self.source = other.source;
+        }
+        self.notes.extend(other.notes);
+    }
+
+    /// The text of the note on `target`, if there is one.
+    pub fn note_on(&self, target: NoteTarget) -> Option<&str> {
+        self.notes
+            .iter()
+            .find(|note| note.on == target)
+            .map(|note| note.text.as_str())
+    }
+
+    /// The copyright and the license together, e.g. "© 2024 Jane Doe. License: CC BY 4.0", for
+    /// formats with room for only one of them.
+    pub fn copyright_notice(&self) -> Option<String> {
+        match (&self.copyright, &self.license) {
+            (Some(copyright), Some(license)) => {
+                Some(format!("{}. License: {}", copyright, license))
+            }
+            (Some(copyright), None) => Some(copyright.clone()),
+            (None, Some(license)) => Some(format!("License: {}", license)),
+            (None, None) => None,
+        }
+    }
+
+    /// Splits what `copyright_notice` writes back into the copyright and the license.
+    pub fn parse_copyright_notice(notice: &str) -> (Option<String>, Option<String>) {
+        let (copyright, license) = match notice.split_once("License: ") {
+            Some((copyright, license)) => {
+                let copyright = copyright.trim_end();
+                (
+                    copyright.strip_suffix('.').unwrap_or(copyright),
+                    Some(license),
+                )
+            }
+            None => (notice, None),
+        };
+        let nonempty = |text: &str| Some(text.trim().to_string()).filter(|text| !text.is_empty());
+        (nonempty(copyright), license.and_then(nonempty))
+    }
+
+    /// E.g. "by Jane Doe, © 2024"; `None` if there's neither an author nor a copyright.
+    pub fn byline(&self) -> Option<String> {
+        match (&self.author, &self.copyright) {
+            (Some(author), Some(copyright)) => Some(format!("by {}, {}", author, copyright)),
+            (Some(author), None) => Some(format!("by {}", author)),
+            (None, Some(copyright)) => Some(copyright.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Everything read from a puzzle file: the clues, the picture (if the format has one), and any
+/// attribution, along with the name of the format it came from (e.g. "webpbn").
+#[derive(Clone)]
+pub struct Document {
+    pub puzzle: Puzzle,
+    pub solution: Option<Solution>,
+    pub metadata: Metadata,
+    pub source_format: String,
+}
+
+impl Document {
+    pub fn new(puzzle: Puzzle, solution: Option<Solution>, source_format: &str) -> Document {
+        Document {
+            puzzle,
+            solution,
+            metadata: Metadata::default(),
+            source_format: source_format.to_string(),
+        }
+    }
+
+    /// Removes the solution, e.g. to hand it to something that wants ownership.
+    pub fn take_solution(&mut self) -> Option<Solution> {
+        self.solution.take()
+    }
+}
+
+/// Several documents bundled together, as in a `.loompack` file, along with metadata (a title,
+/// say) for the collection as a whole.
+#[derive(Clone, Default)]
+pub struct Pack {
+    pub metadata: Metadata,
+    pub documents: Vec<Document>,
 }
@@ -0,0 +1,43 @@
+//! How much to print to stderr. This is set once, from the command line, and consulted by
+//! everything that prints warnings or summaries.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors.
+    Quiet,
+    /// Warnings and summaries.
+    Normal,
+    /// Also per-lane statistics and other details.
+    Verbose,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+pub fn set(verbosity: Verbosity) {
+    LEVEL.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn get() -> Verbosity {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Prints a warning, unless we're being quiet.
+pub fn warn(msg: impl std::fmt::Display) {
+    if get() >= Verbosity::Normal {
+        eprintln!("convert-nonogram: warning: {}", msg);
+    }
+}
+
+/// Prints something that might be intentional, but is worth pointing out, unless we're being
+/// quiet.
+pub fn note(msg: impl std::fmt::Display) {
+    if get() >= Verbosity::Normal {
+        eprintln!("convert-nonogram: note: {}", msg);
+    }
+}
@@ -0,0 +1,76 @@
+//! JavaScript bindings, for embedding the solver and converters in web pages without the CLI.
+//! Enabled by the `wasm` feature; build the npm package with
+//! `wasm-pack build --target web -- --features wasm`.
+//!
+//! Puzzles cross the boundary as JSON, in the form `serde` gives `puzzle::Puzzle`.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    export::{self, ImageOptions},
+    grid_solve, import,
+    puzzle::Puzzle,
+};
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SolveResult {
+    solved: bool,
+    skims: usize,
+    scrubs: usize,
+    cells_left: usize,
+    /// One string per row, using the colors' characters, with `?` for undetermined cells.
+    grid: Vec<String>,
+}
+
+/// Refuses puzzles the solver and exporters can't handle (like clues in colors that aren't in the
+/// palette), since a panic would take down the whole wasm instance.
+fn check(puzzle: &Puzzle) -> Result<(), JsError> {
+    let problems = import::structural_problems(puzzle);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(JsError::new(&problems.join("; ")))
+    }
+}
+
+/// Solves a puzzle (as JSON) with line logic, and returns JSON describing how it went.
+#[wasm_bindgen(js_name = solvePuzzle)]
+pub fn solve_puzzle(json: &str) -> Result<String, JsError> {
+    let puzzle: Puzzle = serde_json::from_str(json)?;
+    check(&puzzle)?;
+    let report = grid_solve::solve(&puzzle, false, None)?;
+
+    let grid = report
+        .grid
+        .rows()
+        .into_iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| cell.known_or().map_or('?', |c| puzzle.palette[&c].ch))
+                .collect()
+        })
+        .collect();
+
+    Ok(serde_json::to_string(&SolveResult {
+        solved: report.cells_left == 0,
+        skims: report.skims,
+        scrubs: report.scrubs,
+        cells_left: report.cells_left,
+        grid,
+    })?)
+}
+
+/// Converts a puzzle between formats. Formats are named as on the command line (`image`,
+/// `webpbn`, `char-grid`, `olsak`, `html`), plus `json`, which is what `solvePuzzle` takes.
+/// Images are written as PNGs.
+#[wasm_bindgen]
+pub fn convert(bytes: &[u8], from: &str, to: &str) -> Result<Vec<u8>, JsError> {
+    let document = import::from_bytes(bytes, from)?;
+    check(&document.puzzle)?;
+    let options = ImageOptions {
+        major_lines: 5,
+        ..ImageOptions::default()
+    };
+    Ok(export::to_bytes(&document, to, options)?)
+}